@@ -0,0 +1,196 @@
+use std::time::Duration;
+
+///
+/// Fixed timestep clock deciding how many simulation steps to run for the real time passed
+/// since the last frame.
+///
+/// Every step advances the simulation by the same params.dt, so the result only depends on the
+/// number of steps and not on the frame rate. Time left over at the end of a frame stays in the
+/// accumulator and is used up by the following frames.
+///
+#[derive(Clone, Debug)]
+pub struct Clock{
+    // steps per second of real time at a scale of one.
+    rate: f64,
+    // scaled real time not yet used up by steps, in steps.
+    accumulator: f64,
+    // steps run per frame at most, time the simulation can not keep up with is dropped.
+    max_substeps: u32,
+    // factor applied to the real time, 0.5 runs the simulation at half speed.
+    scale: f64,
+    paused: bool,
+    // steps run since the clock was created.
+    steps: u64,
+}
+
+impl Default for Clock{
+    fn default() -> Self{
+        Self::new(Self::DEFAULT_RATE)
+    }
+}
+
+impl Clock{
+    // six steps per frame at 60 Hz.
+    pub const DEFAULT_RATE: f64 = 360.;
+    // enough to keep up down to 15 frames per second at the default rate.
+    pub const DEFAULT_MAX_SUBSTEPS: u32 = 24;
+
+    pub fn new(rate: f64) -> Self{
+        Self{
+            rate,
+            accumulator: 0.,
+            max_substeps: Self::DEFAULT_MAX_SUBSTEPS,
+            scale: 1.,
+            paused: false,
+            steps: 0,
+        }
+    }
+
+    pub fn set_max_substeps(mut self, max_substeps: u32) -> Self{
+        self.max_substeps = max_substeps;
+        self
+    }
+
+    pub fn max_substeps(&self) -> u32{
+        self.max_substeps
+    }
+
+    pub fn rate(&self) -> f64{
+        self.rate
+    }
+
+    pub fn scale(&self) -> f64{
+        self.scale
+    }
+
+    pub fn set_scale(&mut self, scale: f64){
+        self.scale = scale.max(0.);
+    }
+
+    pub fn paused(&self) -> bool{
+        self.paused
+    }
+
+    ///
+    /// Stops the clock, the time passing while it is paused is not caught up on.
+    ///
+    pub fn pause(&mut self){
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self){
+        self.paused = false;
+    }
+
+    pub fn toggle_pause(&mut self){
+        self.paused = !self.paused;
+    }
+
+    ///
+    /// Steps counted with tick.
+    ///
+    pub fn steps(&self) -> u64{
+        self.steps
+    }
+
+    ///
+    /// Simulated time in seconds.
+    ///
+    pub fn time(&self) -> f64{
+        self.steps as f64 / self.rate
+    }
+
+    ///
+    /// Continue counting from the steps of a restored simulation.
+    ///
+    pub fn set_steps(&mut self, steps: u64){
+        self.steps = steps;
+    }
+
+    ///
+    /// Counts a step run by the simulation.
+    ///
+    pub fn tick(&mut self){
+        self.steps += 1;
+    }
+
+    ///
+    /// Advances the clock by the real time elapsed since the last call and returns the number of
+    /// steps to run for it.
+    ///
+    pub fn advance(&mut self, elapsed: Duration) -> u32{
+        if self.paused{
+            return 0;
+        }
+
+        self.accumulator += elapsed.as_secs_f64() * self.scale * self.rate;
+        let steps = (self.accumulator.floor() as u64).min(self.max_substeps as u64);
+        // At most one step is carried over if the simulation falls behind.
+        self.accumulator = (self.accumulator - steps as f64).min(1.);
+        steps as u32
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    // half a step more than two seconds at the default rate, so rounding of the frame times
+    // can not move the total across a step.
+    const TOTAL: Duration = Duration::from_nanos(2_000_000_000 + 1_000_000_000 / 720);
+
+    ///
+    /// Advances the clock over total in frames of equal length and returns the steps to run.
+    ///
+    fn run(clock: &mut Clock, total: Duration, frames: u64) -> u64{
+        let total = total.as_nanos() as u64;
+        let mut last = 0;
+        let mut steps = 0;
+        for i in 1..=frames{
+            // frame times rounded to nanoseconds as the timestamps of a real frame loop.
+            let now = total * i / frames;
+            steps += clock.advance(Duration::from_nanos(now - last)) as u64;
+            last = now;
+        }
+        steps
+    }
+
+    #[test]
+    fn steps_do_not_depend_on_the_frame_rate(){
+        let steps_30 = run(&mut Clock::default(), TOTAL, 60);
+        let steps_144 = run(&mut Clock::default(), TOTAL, 288);
+        assert_eq!(steps_30, 720);
+        assert_eq!(steps_144, 720);
+    }
+
+    #[test]
+    fn paused_time_is_not_caught_up(){
+        let mut clock = Clock::default();
+        clock.pause();
+        assert_eq!(run(&mut clock, TOTAL, 60), 0);
+
+        clock.resume();
+        assert_eq!(clock.advance(Duration::ZERO), 0);
+        assert_eq!(run(&mut clock, TOTAL, 60), 720);
+    }
+
+    #[test]
+    fn scale_changes_the_steps_per_second(){
+        let mut clock = Clock::default();
+        clock.set_scale(0.5);
+        assert_eq!(run(&mut clock, TOTAL, 60), 360);
+
+        clock.set_scale(-1.);
+        assert_eq!(clock.scale(), 0.);
+        assert_eq!(run(&mut clock, TOTAL, 60), 0);
+    }
+
+    #[test]
+    fn steps_per_frame_are_clamped_to_max_substeps(){
+        let mut clock = Clock::default().set_max_substeps(4);
+        assert_eq!(clock.advance(Duration::from_secs(1)), 4);
+        // the dropped time is not caught up, at most one step is carried over.
+        assert_eq!(clock.advance(Duration::ZERO), 1);
+        assert_eq!(clock.advance(Duration::ZERO), 0);
+    }
+}
@@ -0,0 +1,171 @@
+use crate::wgpu_utils::binding::{GetBindGroupLayout, GetBindGroup, CreateBindGroupLayout, BindGroup};
+use crate::wgpu_utils::mesh::{Mesh, Drawable};
+use crate::wgpu_utils::pipeline::{self, shader_with_shaderc, shader_with_shaderc_defines, VertexStateBuilder, FragmentStateBuilder, PipelineLayoutBuilder, RenderPipelineBuilder, RenderPassBuilder};
+use crate::wgpu_utils::render_target::ColorAttachment;
+use crate::wgpu_utils::uniform::UniformBindGroup;
+use crate::wgpu_utils::{texture::{Texture, TextureArray}, vert::Vert2};
+use crate::paintsim::{PaintSim, PaintUniforms, PaintPigments, PigmentDefines};
+use crate::GlobalShaderData;
+use anyhow::*;
+
+///
+/// What the Display shows of a PaintSim.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisplayMode{
+    // the raw simulation textures in four quadrants.
+    Debug,
+    // the glazes and the wet layer composited over the paper using the Kubelka-Munk model.
+    Composite,
+}
+
+impl DisplayMode{
+    pub fn toggle(self) -> Self{
+        match self{
+            Self::Debug => Self::Composite,
+            Self::Composite => Self::Debug,
+        }
+    }
+}
+
+///
+/// Parameters of the Kubelka-Munk compositing in vf_composite.glsl.
+///
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct CompositeParams{
+    // reflectance of the paper in linear rgb, alpha is unused.
+    pub paper_color: [f32; 4],
+    // converts pigment concentrations into Kubelka-Munk layer thickness.
+    pub thickness_scale: f32,
+    pub _pad0: [f32; 3],
+}
+
+impl Default for CompositeParams{
+    fn default() -> Self{
+        Self{
+            paper_color: [0.95, 0.93, 0.88, 1.0],
+            thickness_scale: 10.0,
+            _pad0: [0.0; 3],
+        }
+    }
+}
+
+///
+/// Renders the state of a PaintSim to a texture view of a given format.
+///
+pub struct Display{
+    mesh: Mesh<Vert2>,
+    display_rp: pipeline::RenderPipeline,
+    composite_rp: pipeline::RenderPipeline,
+    global_uniform: UniformBindGroup<GlobalShaderData>,
+    composite_uniform: UniformBindGroup<CompositeParams>,
+}
+
+impl Display{
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, size: [u32; 2]) -> Result<Self>{
+        let mesh = Mesh::new(device, &Vert2::QUAD_VERTS, &Vert2::QUAD_IDXS)?;
+
+        let global_uniform = UniformBindGroup::<GlobalShaderData>::new(device, GlobalShaderData{
+            size: [size[0] as f32, size[1] as f32],
+            time: 0.0,
+            _pad0: 0.0,
+        });
+
+        let display_vsh = shader_with_shaderc(device, include_str!("shaders/vf_display.glsl"), shaderc::ShaderKind::Vertex, "main", None)?;
+        let display_fsh = shader_with_shaderc(device, include_str!("shaders/vf_display.glsl"), shaderc::ShaderKind::Fragment, "main", None)?;
+
+        let display_vst = VertexStateBuilder::new(&display_vsh)
+            .push_vert_layout(mesh.vert_buffer_layout())
+            .build();
+
+        let display_fst = FragmentStateBuilder::new(&display_fsh)
+            .push_target_replace(format)
+            .build();
+
+        // TODO: put all textures together into one bindgroup.
+        let display_rpl = PipelineLayoutBuilder::new()
+            .push(global_uniform.get_bind_group_layout())
+            .push(&BindGroup::<Texture>::create_bind_group_layout(device, None))
+            .push(&BindGroup::<TextureArray>::create_bind_group_layout(device, None))
+            .push(&BindGroup::<TextureArray>::create_bind_group_layout(device, None))
+            .create(device, None);
+
+        let display_rp = RenderPipelineBuilder::new(display_vst, display_fst)
+            .set_layout(&display_rpl)
+            .build(device);
+
+        let composite_uniform = UniformBindGroup::<CompositeParams>::new(device, CompositeParams::default());
+
+        let pigment_defines = PigmentDefines::new();
+        let composite_vsh = shader_with_shaderc_defines(device, include_str!("shaders/vf_composite.glsl"), shaderc::ShaderKind::Vertex, "main", &pigment_defines.with(&[]), None)?;
+        let composite_fsh = shader_with_shaderc_defines(device, include_str!("shaders/vf_composite.glsl"), shaderc::ShaderKind::Fragment, "main", &pigment_defines.with(&[]), None)?;
+
+        let composite_vst = VertexStateBuilder::new(&composite_vsh)
+            .push_vert_layout(mesh.vert_buffer_layout())
+            .build();
+
+        let composite_fst = FragmentStateBuilder::new(&composite_fsh)
+            .push_target_replace(format)
+            .build();
+
+        // The pigment properties are read from the uniforms of the PaintSim.
+        let composite_rpl = PipelineLayoutBuilder::new()
+            .push(&BindGroup::<PaintUniforms>::create_bind_group_layout(device, None))
+            .push(composite_uniform.get_bind_group_layout())
+            .push(&BindGroup::<PaintPigments>::create_bind_group_layout(device, None))
+            .push(&BindGroup::<TextureArray>::create_bind_group_layout(device, None))
+            .create(device, None);
+
+        let composite_rp = RenderPipelineBuilder::new(composite_vst, composite_fst)
+            .set_layout(&composite_rpl)
+            .build(device);
+
+        Ok(Self{
+            mesh,
+            display_rp,
+            composite_rp,
+            global_uniform,
+            composite_uniform,
+        })
+    }
+
+    pub fn composite_params(&self) -> &CompositeParams{
+        self.composite_uniform.get_content()
+    }
+
+    pub fn set_composite_params(&mut self, queue: &mut wgpu::Queue, params: CompositeParams){
+        *self.composite_uniform.borrow_ref(queue) = params;
+    }
+
+    pub fn resize(&mut self, queue: &mut wgpu::Queue, size: [u32; 2]){
+        self.global_uniform.borrow_ref(queue).size = [size[0] as f32, size[1] as f32];
+    }
+
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, paintsim: &PaintSim, mode: DisplayMode){
+        let mut render_pass = RenderPassBuilder::new()
+            .push_color_attachment(view.color_attachment_clear())
+            .begin(encoder, None);
+
+        match mode{
+            DisplayMode::Debug => {
+                let mut render_pass_pipeline = render_pass.set_pipeline(&self.display_rp);
+                render_pass_pipeline.set_bind_group(0, self.global_uniform.get_bind_group(), &[]);
+                render_pass_pipeline.set_bind_group(1, paintsim.tex_vpf.get_bind_group(), &[]);
+                render_pass_pipeline.set_bind_group(2, paintsim.tex_color.get_bind_group(), &[]);
+                render_pass_pipeline.set_bind_group(3, paintsim.tex_float.get_bind_group(), &[]);
+
+                self.mesh.draw(&mut render_pass_pipeline);
+            }
+            DisplayMode::Composite => {
+                let mut render_pass_pipeline = render_pass.set_pipeline(&self.composite_rp);
+                render_pass_pipeline.set_bind_group(0, paintsim.uniforms.get_bind_group(), &[]);
+                render_pass_pipeline.set_bind_group(1, self.composite_uniform.get_bind_group(), &[]);
+                render_pass_pipeline.set_bind_group(2, paintsim.tex_pigments().get_bind_group(), &[]);
+                render_pass_pipeline.set_bind_group(3, paintsim.tex_glazes.get_bind_group(), &[]);
+
+                self.mesh.draw(&mut render_pass_pipeline);
+            }
+        }
+    }
+}
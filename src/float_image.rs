@@ -0,0 +1,560 @@
+use crate::wgpu_utils::texture::{Texture, TextureArray};
+use anyhow::*;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+
+// magic number of OpenEXR files.
+const EXR_MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+// pixel type of 32 bit float channels in OpenEXR.
+const EXR_FLOAT: i32 = 2;
+
+///
+/// Named float channels of a texture or texture array, written to and read from NumPy .npy and
+/// OpenEXR files for analysis outside of the simulation.
+///
+/// The rows are stored starting with the top row of the image the simulation was created from,
+/// the texture rows are flipped accordingly. Layers of texture arrays become additional
+/// channels.
+///
+#[derive(Clone, Debug)]
+pub struct FloatImage{
+    pub size: [u32; 2],
+    pub channels: Vec<String>,
+    // channels of a pixel next to each other, the pixels row by row.
+    pub data: Vec<f32>,
+}
+
+impl FloatImage{
+    ///
+    /// Reads back a texture with a 32 bit float format, channels names its components.
+    ///
+    pub fn from_texture(texture: &Texture, device: &wgpu::Device, queue: &wgpu::Queue, channels: Vec<String>) -> Result<Self>{
+        let components = format_components(texture.format)?;
+        if channels.len() != components{
+            return Err(anyhow!("texture has {} channels but {} names were given", components, channels.len()));
+        }
+        Ok(Self::from_layers(texture.size, channels, &to_f32s(&texture.read_blocking(device, queue)), 1))
+    }
+
+    ///
+    /// Reads back all layers of a texture array with a 32 bit float format, channels names the
+    /// components of all layers one layer after another.
+    ///
+    pub fn from_texture_array(texture: &TextureArray, device: &wgpu::Device, queue: &wgpu::Queue, channels: Vec<String>) -> Result<Self>{
+        let components = format_components(texture.format)? * texture.layers as usize;
+        if channels.len() != components{
+            return Err(anyhow!("texture array has {} channels but {} names were given", components, channels.len()));
+        }
+        Ok(Self::from_layers(texture.size, channels, &to_f32s(&texture.read_blocking(device, queue)), texture.layers as usize))
+    }
+
+    pub fn write_to_texture(&self, texture: &Texture, queue: &wgpu::Queue) -> Result<()>{
+        self.check_layout(texture.size, format_components(texture.format)?)?;
+        texture.write_bytes(queue, bytemuck::cast_slice(&self.to_layers(1)));
+        Ok(())
+    }
+
+    pub fn write_to_texture_array(&self, texture: &TextureArray, queue: &wgpu::Queue) -> Result<()>{
+        self.check_layout(texture.size, format_components(texture.format)? * texture.layers as usize)?;
+        texture.write_bytes(queue, bytemuck::cast_slice(&self.to_layers(texture.layers as usize)));
+        Ok(())
+    }
+
+    ///
+    /// Writes a .npy or .exr file depending on the extension of the path.
+    ///
+    pub fn write_to_path(&self, path: &str) -> Result<()>{
+        if path.ends_with(".npy"){
+            self.write_npy(path)
+        }
+        else if path.ends_with(".exr"){
+            self.write_exr(path)
+        }
+        else{
+            Err(anyhow!("unknown extension of {}, expected .npy or .exr", path))
+        }
+    }
+
+    ///
+    /// Reads a .npy or .exr file depending on the extension of the path and returns the given
+    /// channels in their order.
+    ///
+    /// The channels of OpenEXR files are selected by name, .npy files have no names and need to
+    /// have exactly these channels in this order.
+    ///
+    pub fn read_from_path(path: &str, channels: &[String]) -> Result<Self>{
+        if path.ends_with(".npy"){
+            Self::read_npy(path, channels)
+        }
+        else if path.ends_with(".exr"){
+            Self::read_exr(path)?.select(channels)
+        }
+        else{
+            Err(anyhow!("unknown extension of {}, expected .npy or .exr", path))
+        }
+    }
+
+    ///
+    /// Writes a little endian float32 array of shape (height, width, channels).
+    ///
+    pub fn write_npy(&self, path: &str) -> Result<()>{
+        let mut header = format!(
+            "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}, {}), }}",
+            self.size[1], self.size[0], self.channels.len()
+        );
+        // The magic string, version, length and header end with a newline at a multiple of 64 bytes.
+        while (10 + header.len() + 1) % 64 != 0{
+            header.push(' ');
+        }
+        header.push('\n');
+
+        let mut w = BufWriter::new(File::create(path)?);
+        w.write_all(b"\x93NUMPY\x01\x00")?;
+        w.write_all(&(header.len() as u16).to_le_bytes())?;
+        w.write_all(header.as_bytes())?;
+        for v in &self.data{
+            w.write_all(&v.to_le_bytes())?;
+        }
+        w.flush()?;
+        Ok(())
+    }
+
+    ///
+    /// Reads a little endian float32 array of shape (height, width, channels) or
+    /// (height, width) for a single channel.
+    ///
+    pub fn read_npy(path: &str, channels: &[String]) -> Result<Self>{
+        let bytes = std::fs::read(path)?;
+        let mut r = &bytes[..];
+
+        let mut magic = [0u8; 8];
+        r.read_exact(&mut magic)?;
+        if &magic[..6] != b"\x93NUMPY"{
+            return Err(anyhow!("{} is not a .npy file", path));
+        }
+        let header_len = match magic[6]{
+            1 => {
+                let mut len = [0u8; 2];
+                r.read_exact(&mut len)?;
+                u16::from_le_bytes(len) as usize
+            }
+            _ => read_u32(&mut r)? as usize,
+        };
+        let header = std::str::from_utf8(r.get(..header_len).ok_or(anyhow!("header of {} is truncated", path))?)?;
+        r = &r[header_len..];
+
+        if !header.contains("'descr': '<f4'") || !header.contains("'fortran_order': False"){
+            return Err(anyhow!("{} does not contain little endian float32 values in C order", path));
+        }
+        let shape = header.split("'shape': (").nth(1)
+            .and_then(|s| s.split(')').next())
+            .ok_or(anyhow!("{} has no shape", path))?;
+        let shape = shape.split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(|s| s.parse::<usize>())
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let (height, width, components) = match shape[..]{
+            [height, width] => (height, width, 1),
+            [height, width, components] => (height, width, components),
+            _ => return Err(anyhow!("{} has shape {:?}, expected (height, width, channels)", path, shape)),
+        };
+        if components != channels.len(){
+            return Err(anyhow!("{} has {} channels, expected {}", path, components, channels.len()));
+        }
+
+        let size = [u32::try_from(width)?, u32::try_from(height)?];
+        let len = height.checked_mul(width)
+            .and_then(|len| len.checked_mul(components))
+            .and_then(|len| len.checked_mul(4))
+            .ok_or(anyhow!("shape {:?} of {} is too large", shape, path))?;
+        let data = r.get(..len).ok_or(anyhow!("data of {} is truncated", path))?;
+        Ok(Self{
+            size,
+            channels: channels.to_vec(),
+            data: to_f32s(data),
+        })
+    }
+
+    ///
+    /// Writes an uncompressed scanline OpenEXR file with a FLOAT channel per channel.
+    ///
+    pub fn write_exr(&self, path: &str) -> Result<()>{
+        let width = self.size[0] as usize;
+        let height = self.size[1] as usize;
+        let components = self.channels.len();
+
+        // OpenEXR requires the channels to be sorted by name, the pixel data follows that order.
+        let mut order = (0..components).collect::<Vec<_>>();
+        order.sort_by(|a, b| self.channels[*a].cmp(&self.channels[*b]));
+
+        let mut chlist = Vec::new();
+        for &c in &order{
+            chlist.extend_from_slice(self.channels[c].as_bytes());
+            chlist.push(0);
+            chlist.extend_from_slice(&EXR_FLOAT.to_le_bytes());
+            // pLinear and reserved bytes.
+            chlist.extend_from_slice(&[0; 4]);
+            // x and y sampling.
+            chlist.extend_from_slice(&1i32.to_le_bytes());
+            chlist.extend_from_slice(&1i32.to_le_bytes());
+        }
+        chlist.push(0);
+
+        let window = [0, 0, width as i32 - 1, height as i32 - 1].iter()
+            .flat_map(|v: &i32| v.to_le_bytes())
+            .collect::<Vec<_>>();
+
+        let mut header = Vec::new();
+        write_attribute(&mut header, "channels", "chlist", &chlist);
+        write_attribute(&mut header, "compression", "compression", &[0]);
+        write_attribute(&mut header, "dataWindow", "box2i", &window);
+        write_attribute(&mut header, "displayWindow", "box2i", &window);
+        write_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+        write_attribute(&mut header, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+        write_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+        write_attribute(&mut header, "screenWindowWidth", "float", &1f32.to_le_bytes());
+        header.push(0);
+
+        // Every scanline is its own chunk of y, size and data behind the table of chunk offsets.
+        let line = width * components * 4;
+        let first_chunk = 8 + header.len() + 8 * height;
+
+        let mut w = BufWriter::new(File::create(path)?);
+        w.write_all(&EXR_MAGIC)?;
+        w.write_all(&2u32.to_le_bytes())?;
+        w.write_all(&header)?;
+        for y in 0..height{
+            w.write_all(&((first_chunk + y * (8 + line)) as u64).to_le_bytes())?;
+        }
+        for y in 0..height{
+            w.write_all(&(y as i32).to_le_bytes())?;
+            w.write_all(&(line as i32).to_le_bytes())?;
+            for &c in &order{
+                for x in 0..width{
+                    w.write_all(&self.data[(y * width + x) * components + c].to_le_bytes())?;
+                }
+            }
+        }
+        w.flush()?;
+        Ok(())
+    }
+
+    ///
+    /// Reads an uncompressed scanline OpenEXR file with FLOAT channels, the channels are
+    /// returned sorted by name.
+    ///
+    pub fn read_exr(path: &str) -> Result<Self>{
+        let bytes = std::fs::read(path)?;
+        let mut r = &bytes[..];
+
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        if magic != EXR_MAGIC{
+            return Err(anyhow!("{} is not an OpenEXR file", path));
+        }
+        let version = read_u32(&mut r)?;
+        // Tiled, deep and multi-part files are not supported, long names are.
+        if version & 0xff != 2 || version & (0x200 | 0x800 | 0x1000) != 0{
+            return Err(anyhow!("{} is not a single part scanline OpenEXR file", path));
+        }
+
+        let mut channels = Vec::new();
+        let mut compression = None;
+        let mut window = None;
+        loop{
+            let name = read_cstr(&mut r)?;
+            if name.is_empty(){
+                break;
+            }
+            let _ty = read_cstr(&mut r)?;
+            let size = read_u32(&mut r)? as usize;
+            let mut value = r.get(..size).ok_or(anyhow!("attribute {} of {} is truncated", name, path))?;
+            r = &r[size..];
+
+            match name.as_str(){
+                "channels" => {
+                    loop{
+                        let channel = read_cstr(&mut value)?;
+                        if channel.is_empty(){
+                            break;
+                        }
+                        let pixel_type = read_i32(&mut value)?;
+                        let _linear = read_u32(&mut value)?;
+                        let sampling = [read_i32(&mut value)?, read_i32(&mut value)?];
+                        if pixel_type != EXR_FLOAT || sampling != [1, 1]{
+                            return Err(anyhow!("channel {} of {} is not an unsampled FLOAT channel", channel, path));
+                        }
+                        channels.push(channel);
+                    }
+                }
+                "compression" => compression = value.first().copied(),
+                "dataWindow" => window = Some([read_i32(&mut value)?, read_i32(&mut value)?, read_i32(&mut value)?, read_i32(&mut value)?]),
+                _ => {}
+            }
+        }
+        if compression != Some(0){
+            return Err(anyhow!("{} is compressed, only uncompressed files are supported", path));
+        }
+        let window: [i32; 4] = window.ok_or(anyhow!("{} has no data window", path))?;
+        let extent = |lo: i32, hi: i32| hi.checked_sub(lo).and_then(|d| d.checked_add(1)).map(|d| d.max(0) as usize);
+        let (width, height) = extent(window[0], window[2]).zip(extent(window[1], window[3]))
+            .ok_or(anyhow!("data window {:?} of {} is too large", window, path))?;
+        let components = channels.len();
+        let line = width.checked_mul(components)
+            .and_then(|line| line.checked_mul(4))
+            .ok_or(anyhow!("data window {:?} of {} is too large", window, path))?;
+        // Every scanline has to be in the file, so the data is never larger than the file.
+        let len = line.checked_mul(height)
+            .filter(|len| *len <= bytes.len())
+            .ok_or(anyhow!("data window {:?} of {} is larger than the file", window, path))?;
+
+        let mut data = vec![0.; len / 4];
+        for _ in 0..height{
+            let mut offset = [0u8; 8];
+            r.read_exact(&mut offset)?;
+            let offset = u64::from_le_bytes(offset) as usize;
+
+            let mut chunk = bytes.get(offset..).ok_or(anyhow!("chunk of {} is out of bounds", path))?;
+            let y = read_i32(&mut chunk)? - window[1];
+            let size = read_i32(&mut chunk)? as usize;
+            if y < 0 || y as usize >= height || size != line{
+                return Err(anyhow!("{} has an invalid scanline", path));
+            }
+            let values = to_f32s(chunk.get(..line).ok_or(anyhow!("scanline of {} is truncated", path))?);
+            for c in 0..components{
+                for x in 0..width{
+                    data[(y as usize * width + x) * components + c] = values[c * width + x];
+                }
+            }
+        }
+
+        Ok(Self{
+            size: [width as u32, height as u32],
+            channels,
+            data,
+        })
+    }
+
+    ///
+    /// Returns the given channels by name in their order.
+    ///
+    pub fn select(&self, channels: &[String]) -> Result<Self>{
+        let indices = channels.iter()
+            .map(|name| self.channels.iter().position(|c| c == name).ok_or(anyhow!("channel {} is missing, found {:?}", name, self.channels)))
+            .collect::<Result<Vec<_>>>()?;
+        let components = self.channels.len();
+        Ok(Self{
+            size: self.size,
+            channels: channels.to_vec(),
+            data: self.data.chunks_exact(components)
+                .flat_map(|pixel| indices.iter().map(move |i| pixel[*i]))
+                .collect(),
+        })
+    }
+
+    fn check_layout(&self, size: [u32; 2], components: usize) -> Result<()>{
+        if self.size != size || self.channels.len() != components{
+            return Err(anyhow!("image of size {:?} with {} channels does not match the texture of size {:?} with {} channels", self.size, self.channels.len(), size, components));
+        }
+        Ok(())
+    }
+
+    ///
+    /// Interleaves the texels of layers stored one after another and flips the rows.
+    ///
+    fn from_layers(size: [u32; 2], channels: Vec<String>, texels: &[f32], layers: usize) -> Self{
+        let width = size[0] as usize;
+        let height = size[1] as usize;
+        let components = channels.len() / layers;
+
+        let mut data = Vec::with_capacity(texels.len());
+        for y in 0..height{
+            let ty = height - 1 - y;
+            for x in 0..width{
+                for l in 0..layers{
+                    let i = ((l * height + ty) * width + x) * components;
+                    data.extend_from_slice(&texels[i..i + components]);
+                }
+            }
+        }
+        Self{
+            size,
+            channels,
+            data,
+        }
+    }
+
+    ///
+    /// Inverse of from_layers.
+    ///
+    fn to_layers(&self, layers: usize) -> Vec<f32>{
+        let width = self.size[0] as usize;
+        let height = self.size[1] as usize;
+        let components = self.channels.len() / layers;
+
+        let mut texels = vec![0.; self.data.len()];
+        for y in 0..height{
+            let ty = height - 1 - y;
+            for x in 0..width{
+                for l in 0..layers{
+                    let i = ((l * height + ty) * width + x) * components;
+                    let j = (y * width + x) * self.channels.len() + l * components;
+                    texels[i..i + components].copy_from_slice(&self.data[j..j + components]);
+                }
+            }
+        }
+        texels
+    }
+}
+
+fn format_components(format: wgpu::TextureFormat) -> Result<usize>{
+    match format{
+        wgpu::TextureFormat::R32Float => Ok(1),
+        wgpu::TextureFormat::Rg32Float => Ok(2),
+        wgpu::TextureFormat::Rgba32Float => Ok(4),
+        _ => Err(anyhow!("texture format {:?} is not a 32 bit float format", format)),
+    }
+}
+
+fn to_f32s(bytes: &[u8]) -> Vec<f32>{
+    bytes.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect()
+}
+
+fn write_attribute(header: &mut Vec<u8>, name: &str, ty: &str, value: &[u8]){
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(ty.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+fn read_u32(r: &mut impl Read) -> Result<u32>{
+    let mut bytes = [0u8; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_i32(r: &mut impl Read) -> Result<i32>{
+    Ok(read_u32(r)? as i32)
+}
+
+fn read_cstr(r: &mut impl Read) -> Result<String>{
+    let mut bytes = Vec::new();
+    loop{
+        let mut byte = [0u8; 1];
+        r.read_exact(&mut byte)?;
+        if byte[0] == 0{
+            break;
+        }
+        bytes.push(byte[0]);
+    }
+    Ok(String::from_utf8(bytes)?)
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String>{
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn image() -> FloatImage{
+        let size = [3, 2];
+        FloatImage{
+            size,
+            channels: names(&["vx", "vy", "h"]),
+            data: (0..size[0] * size[1] * 3).map(|i| i as f32 * 0.5 - 1.).collect(),
+        }
+    }
+
+    fn temp_path(name: &str) -> String{
+        std::env::temp_dir().join(format!("fluid01_{}_{}", std::process::id(), name)).to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn npy_reads_back_equal(){
+        let path = temp_path("image.npy");
+        let written = image();
+        written.write_to_path(&path).unwrap();
+        let read = FloatImage::read_from_path(&path, &written.channels);
+        std::fs::remove_file(&path).unwrap();
+        let read = read.unwrap();
+
+        assert_eq!(read.size, written.size);
+        assert_eq!(read.channels, written.channels);
+        assert_eq!(read.data, written.data);
+    }
+
+    #[test]
+    fn exr_reads_back_equal(){
+        let path = temp_path("image.exr");
+        let written = image();
+        written.write_to_path(&path).unwrap();
+        let read = FloatImage::read_exr(&path);
+        std::fs::remove_file(&path).unwrap();
+        let read = read.unwrap();
+
+        // the channels are stored sorted by name.
+        assert_eq!(read.channels, names(&["h", "vx", "vy"]));
+        let read = read.select(&written.channels).unwrap();
+        assert_eq!(read.size, written.size);
+        assert_eq!(read.data, written.data);
+    }
+
+    #[test]
+    fn select_reorders_the_channels(){
+        let img = image();
+        let selected = img.select(&names(&["h", "vx"])).unwrap();
+        assert_eq!(selected.channels, names(&["h", "vx"]));
+        for (pixel, selected) in img.data.chunks_exact(3).zip(selected.data.chunks_exact(2)){
+            assert_eq!(selected, [pixel[2], pixel[0]]);
+        }
+        assert!(img.select(&names(&["p"])).is_err());
+    }
+
+    #[test]
+    fn layers_are_interleaved_and_flipped(){
+        // two layers of a 2x2 texture with one component, texel value = 100 * layer + 10 * row + column.
+        let texels = [0., 1., 10., 11., 100., 101., 110., 111.];
+        let img = FloatImage::from_layers([2, 2], names(&["a", "b"]), &texels, 2);
+
+        // the top row of the image is the last row of the texture.
+        assert_eq!(img.data, [10., 110., 11., 111., 0., 100., 1., 101.]);
+        assert_eq!(img.to_layers(2), texels);
+    }
+
+    #[test]
+    fn oversized_headers_are_errors(){
+        let path = temp_path("huge.npy");
+        let mut header = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}, 4), }}", u32::MAX, u32::MAX);
+        header.push('\n');
+        let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+        bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        let read = FloatImage::read_npy(&path, &names(&["a", "b", "c", "d"]));
+        std::fs::remove_file(&path).unwrap();
+        assert!(read.is_err());
+
+        // a data window from i32::MIN to i32::MAX.
+        let path = temp_path("huge.exr");
+        let written = FloatImage{
+            size: [1, 1],
+            channels: names(&["a"]),
+            data: vec![1.],
+        };
+        written.write_exr(&path).unwrap();
+        let mut bytes = std::fs::read(&path).unwrap();
+        let window = [0i32, 0, 0, 0].iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<_>>();
+        let huge = [i32::MIN, i32::MIN, i32::MAX, i32::MAX].iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<_>>();
+        let at = bytes.windows(window.len()).position(|w| w == window).unwrap();
+        bytes[at..at + huge.len()].copy_from_slice(&huge);
+        std::fs::write(&path, &bytes).unwrap();
+        let read = FloatImage::read_exr(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(read.is_err());
+    }
+}
@@ -0,0 +1,487 @@
+use crate::wgpu_utils::framework::HeadlessState;
+use crate::wgpu_utils::texture::Texture;
+use crate::wgpu_utils::binding::BindGroup;
+use crate::wgpu_utils::resample::Resample;
+use crate::paintsim::{PaintSim, PaintParams, Brush, StepMode, Solver, Advection, Boundary, SimField, GridSize, MULTIGRID_MIN_SIZE};
+use crate::wgpu_utils::multigrid::Multigrid;
+use crate::display::{Display, DisplayMode};
+use crate::reference::{ReferenceSim, Field};
+use crate::paper::{Paper, PaperParams};
+use crate::obstacles::Obstacles;
+use crate::recorder::{Recorder, RecordOutput};
+use anyhow::*;
+
+const USAGE: &str = "usage: fluid01 --headless <input | --load-state <file>> <output.png> [--steps <n>] [--paper <scan> | --paper-seed <seed>] [--debug-display] [--compute] [--solver <density-invariant | jacobi[:<n>] | gauss-seidel[:<n>] | multigrid[:<cycles>]>] [--advection <semi-lagrangian | rk2 | maccormack[:<limiter>] | bfecc[:<limiter>]>] [--vorticity <strength>] [--obstacles <mask>] [--boundary <open | free-slip | no-slip>] [--periodic] [--grid <scale | <width>x<height>>] [--save-state <file>] [--import <vpf | color | float>:<file.npy | file.exr>]... [--export <vpf | color | float>:<file.npy | file.exr>]... [--stats] [--record <dir | file.gif>] [--record-interval <n>] [--fallback] [--check-reference | --check-tiling] [--tolerance <t>]";
+
+///
+/// Arguments of the offline renderer.
+///
+pub struct HeadlessArgs{
+    pub input: String,
+    pub output: String,
+    pub steps: usize,
+    // paper scan used instead of the generated paper.
+    pub paper: Option<String>,
+    // seed of the generated paper.
+    pub paper_seed: u64,
+    // what is written to the png.
+    pub display_mode: DisplayMode,
+    // run the step with compute shaders.
+    pub step_mode: StepMode,
+    // how the velocity is kept free of divergence.
+    pub solver: Solver,
+    // advection scheme of the velocity and the floating pigment.
+    pub advection: Advection,
+    // strength of the vorticity confinement.
+    pub vorticity: f32,
+    // mask image whose dark pixels are obstacles.
+    pub obstacles: Option<String>,
+    // boundary condition at the edges of the canvas.
+    pub boundary: Boundary,
+    // wrap the canvas around its edges.
+    pub periodic: bool,
+    // size of the simulation grid relative to the input.
+    pub grid: GridSize,
+    // snapshot the simulation continues from instead of starting from the input.
+    pub load_state: Option<String>,
+    // snapshot written after the steps.
+    pub save_state: Option<String>,
+    // fields replaced with the contents of .npy or .exr files before the first step.
+    pub imports: Vec<(SimField, String)>,
+    // fields written to .npy or .exr files after the steps.
+    pub exports: Vec<(SimField, String)>,
+    // print the conservation diagnostics after every step.
+    pub stats: bool,
+    // directory of pngs or gif the time-lapse is written to.
+    pub record: Option<String>,
+    // steps between the frames of the time-lapse.
+    pub record_interval: u64,
+    // use the software (fallback) adapter.
+    pub fallback: bool,
+    // compare the gpu simulation against the cpu reference instead of writing a png.
+    pub check_reference: bool,
+    // check that a periodic simulation behaves the same across the edges as inside instead of
+    // writing a png.
+    pub check_tiling: bool,
+    // maximum absolute difference allowed by check_reference and check_tiling.
+    pub tolerance: f32,
+}
+
+impl HeadlessArgs{
+    pub fn parse(args: &[String]) -> Result<Self>{
+        let mut positional = Vec::new();
+        let mut steps = 600;
+        let mut paper = None;
+        let mut paper_seed = PaperParams::default().seed;
+        let mut display_mode = DisplayMode::Composite;
+        let mut step_mode = StepMode::Render;
+        let mut solver = Solver::DensityInvariant;
+        let mut advection = Advection::SemiLagrangian;
+        let mut vorticity = 0.;
+        let mut obstacles = None;
+        let mut boundary = Boundary::Open;
+        let mut periodic = false;
+        let mut grid = GridSize::Source;
+        let mut load_state = None;
+        let mut save_state = None;
+        let mut imports = Vec::new();
+        let mut exports = Vec::new();
+        let mut stats = false;
+        let mut record = None;
+        let mut record_interval = 10;
+        let mut fallback = false;
+        let mut check_reference = false;
+        let mut check_tiling = false;
+        let mut tolerance = 1e-3;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next(){
+            match arg.as_str(){
+                "--steps" => {
+                    steps = args.next().ok_or(anyhow!("--steps requires a value\n{}", USAGE))?.parse()?;
+                }
+                "--paper" => {
+                    paper = Some(args.next().ok_or(anyhow!("--paper requires a value\n{}", USAGE))?.clone());
+                }
+                "--paper-seed" => {
+                    paper_seed = args.next().ok_or(anyhow!("--paper-seed requires a value\n{}", USAGE))?.parse()?;
+                }
+                "--debug-display" => display_mode = DisplayMode::Debug,
+                "--compute" => step_mode = StepMode::Compute,
+                "--solver" => {
+                    solver = args.next().ok_or(anyhow!("--solver requires a value\n{}", USAGE))?.parse()?;
+                }
+                "--advection" => {
+                    advection = args.next().ok_or(anyhow!("--advection requires a value\n{}", USAGE))?.parse()?;
+                }
+                "--vorticity" => {
+                    vorticity = args.next().ok_or(anyhow!("--vorticity requires a value\n{}", USAGE))?.parse()?;
+                }
+                "--obstacles" => {
+                    obstacles = Some(args.next().ok_or(anyhow!("--obstacles requires a value\n{}", USAGE))?.clone());
+                }
+                "--boundary" => {
+                    boundary = args.next().ok_or(anyhow!("--boundary requires a value\n{}", USAGE))?.parse()?;
+                }
+                "--periodic" => periodic = true,
+                "--grid" => {
+                    grid = args.next().ok_or(anyhow!("--grid requires a value\n{}", USAGE))?.parse()?;
+                }
+                "--load-state" => {
+                    load_state = Some(args.next().ok_or(anyhow!("--load-state requires a value\n{}", USAGE))?.clone());
+                }
+                "--save-state" => {
+                    save_state = Some(args.next().ok_or(anyhow!("--save-state requires a value\n{}", USAGE))?.clone());
+                }
+                "--import" => {
+                    imports.push(parse_field_path(args.next().ok_or(anyhow!("--import requires a value\n{}", USAGE))?)?);
+                }
+                "--export" => {
+                    exports.push(parse_field_path(args.next().ok_or(anyhow!("--export requires a value\n{}", USAGE))?)?);
+                }
+                "--stats" => stats = true,
+                "--record" => {
+                    record = Some(args.next().ok_or(anyhow!("--record requires a value\n{}", USAGE))?.clone());
+                }
+                "--record-interval" => {
+                    record_interval = args.next().ok_or(anyhow!("--record-interval requires a value\n{}", USAGE))?.parse()?;
+                }
+                "--fallback" => fallback = true,
+                "--check-reference" => check_reference = true,
+                "--check-tiling" => check_tiling = true,
+                "--tolerance" => {
+                    tolerance = args.next().ok_or(anyhow!("--tolerance requires a value\n{}", USAGE))?.parse()?;
+                }
+                _ if arg.starts_with("--") => return Err(anyhow!("unknown argument {}\n{}", arg, USAGE)),
+                _ => positional.push(arg.clone()),
+            }
+        }
+
+        let output = if check_reference || check_tiling {String::new()} else {positional.pop().ok_or(anyhow!("{}", USAGE))?};
+        // The snapshot contains the source image.
+        let input = if load_state.is_some() {String::new()} else {positional.pop().ok_or(anyhow!("{}", USAGE))?};
+        if !positional.is_empty(){
+            return Err(anyhow!("{}", USAGE));
+        }
+
+        Ok(Self{
+            input,
+            output,
+            steps,
+            paper,
+            paper_seed,
+            display_mode,
+            step_mode,
+            solver,
+            advection,
+            vorticity,
+            obstacles,
+            boundary,
+            periodic: periodic || check_tiling,
+            grid,
+            load_state,
+            save_state,
+            imports,
+            exports,
+            stats,
+            record,
+            record_interval,
+            fallback,
+            check_reference,
+            check_tiling,
+            tolerance,
+        })
+    }
+
+    fn load_paper(&self, size: [u32; 2]) -> Result<Paper>{
+        match &self.paper{
+            Some(path) => Paper::load_from_path(path, size),
+            None => Ok(Paper::generate(size, &PaperParams{
+                seed: self.paper_seed,
+                periodic: self.periodic,
+                ..Default::default()
+            })),
+        }
+    }
+
+    fn load_obstacles(&self, size: [u32; 2]) -> Result<Obstacles>{
+        match &self.obstacles{
+            Some(path) => Obstacles::load_from_path(path, size),
+            None => Ok(Obstacles::empty(size)),
+        }
+    }
+
+    ///
+    /// Creates the PaintSim from the input with the paper, obstacles and settings of the
+    /// arguments.
+    ///
+    fn create_paintsim(&self, state: &mut HeadlessState) -> Result<PaintSim>{
+        let mut paintsim = PaintSim::from_image(&state.device, &state.queue, &image::open(&self.input)?, self.periodic, self.grid)?;
+        let size = paintsim.tex_src.size;
+        paintsim.set_paper(&state.queue, self.load_paper(size)?)?;
+        paintsim.set_obstacles(&state.queue, self.load_obstacles(size)?)?;
+        paintsim.set_boundary(&mut state.queue, self.boundary);
+        paintsim.set_step_mode(self.step_mode);
+        paintsim.set_solver(&mut state.queue, self.solver);
+        paintsim.set_advection(&mut state.queue, self.advection);
+        paintsim.set_params(&mut state.queue, PaintParams{
+            vorticity: self.vorticity,
+            ..*paintsim.params()
+        });
+        Ok(paintsim)
+    }
+}
+
+///
+/// Parses "<field>:<path>" of --import and --export.
+///
+fn parse_field_path(arg: &str) -> Result<(SimField, String)>{
+    let (field, path) = arg.split_once(':').ok_or(anyhow!("expected <field>:<path> instead of {}\n{}", arg, USAGE))?;
+    Ok((field.parse()?, path.to_string()))
+}
+
+///
+/// Runs the simulation without a window and writes the displayed result to a png.
+///
+pub fn run(args: &HeadlessArgs) -> Result<()>{
+    let mut state = pollster::block_on(HeadlessState::new(args.fallback))?;
+
+    if args.check_reference{
+        return check_reference(args, &mut state);
+    }
+    if args.check_tiling{
+        return check_tiling(args, &mut state);
+    }
+
+    // A restored simulation keeps the paper, obstacles and settings of the snapshot.
+    let mut paintsim = match &args.load_state{
+        Some(path) => {
+            let mut paintsim = PaintSim::from_state(&state.device, &mut state.queue, path)?;
+            paintsim.set_step_mode(args.step_mode);
+            paintsim
+        }
+        None => args.create_paintsim(&mut state)?,
+    };
+    let size = paintsim.tex_src.size;
+
+    let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+    let display = Display::new(&state.device, format, size)?;
+    let target = BindGroup::new(Texture::new_black(size, &state.device, &state.queue, Some("HeadlessTarget"), format)?, &state.device);
+
+    let mut encoder = state.device.create_command_encoder(&wgpu::CommandEncoderDescriptor{
+        label: Some("PrepareEncoder"),
+    });
+    if args.load_state.is_none(){
+        paintsim.prepare(&mut encoder);
+    }
+    state.queue.submit(std::iter::once(encoder.finish()));
+    for (field, path) in &args.imports{
+        paintsim.import_field(&state.queue, *field, path)?;
+    }
+    paintsim.set_stats(&state.device, args.stats)?;
+
+    // The first frame of the time-lapse shows the state before the first step.
+    let mut recorder = match &args.record{
+        Some(path) => {
+            let mut recorder = Recorder::new(&state.device, &state.queue, &paintsim, args.record_interval, RecordOutput::from_path(path))?;
+            let mut encoder = state.device.create_command_encoder(&wgpu::CommandEncoderDescriptor{
+                label: Some("RecordEncoder"),
+            });
+            recorder.record(&state.device, &mut encoder, &paintsim, args.display_mode);
+            state.queue.submit(std::iter::once(encoder.finish()));
+            Some(recorder)
+        }
+        None => None,
+    };
+
+    // Submit every step on its own so uniform updates are applied in between.
+    for _ in 0..args.steps{
+        let mut encoder = state.device.create_command_encoder(&wgpu::CommandEncoderDescriptor{
+            label: Some("StepEncoder"),
+        });
+        paintsim.step(&mut state.queue, &mut encoder);
+        if let Some(recorder) = &mut recorder{
+            recorder.record(&state.device, &mut encoder, &paintsim, args.display_mode);
+        }
+        state.queue.submit(std::iter::once(encoder.finish()));
+        if let Some(stats) = paintsim.read_stats(&state.device){
+            println!("{}", stats);
+        }
+        if let Some(recorder) = &mut recorder{
+            recorder.poll(&state.device)?;
+        }
+    }
+    if let Some(recorder) = recorder{
+        recorder.finish(&state.device)?;
+    }
+
+    let mut encoder = state.device.create_command_encoder(&wgpu::CommandEncoderDescriptor{
+        label: Some("DisplayEncoder"),
+    });
+    display.render(&mut encoder, &target.view, &paintsim, args.display_mode);
+
+    // The painting is displayed at the size of the grid and resampled to the size of the input.
+    let output_size = paintsim.source_size();
+    let output = if output_size == size{
+        target
+    }
+    else{
+        let output = BindGroup::new(Texture::new_black(output_size, &state.device, &state.queue, Some("HeadlessOutput"), format)?, &state.device);
+        let address_mode = if paintsim.periodic() {wgpu::AddressMode::Repeat} else {wgpu::AddressMode::ClampToEdge};
+        Resample::new(&state.device, format, output_size, address_mode)?.resample(&mut encoder, &target, &output.view);
+        output
+    };
+    state.queue.submit(std::iter::once(encoder.finish()));
+
+    let data = output.read_blocking(&state.device, &state.queue);
+    let img = image::RgbaImage::from_raw(output_size[0], output_size[1], data).ok_or(anyhow!("readback has the wrong size"))?;
+    img.save(&args.output)?;
+
+    if let Some(path) = &args.save_state{
+        paintsim.save_state(&state.device, &state.queue, path)?;
+    }
+    for (field, path) in &args.exports{
+        paintsim.export_field(&state.device, &state.queue, *field, path)?;
+    }
+
+    Ok(())
+}
+
+///
+/// Runs the gpu simulation and the cpu reference side by side with a brush dab in the center
+/// during the first half of the steps and compares the resulting fields.
+///
+fn check_reference(args: &HeadlessArgs, state: &mut HeadlessState) -> Result<()>{
+    let mut paintsim = args.create_paintsim(state)?;
+    let size = paintsim.tex_src.size;
+
+    let mut reference = ReferenceSim::new(size, *paintsim.params());
+    reference.set_periodic(paintsim.periodic());
+    reference.set_paper(paintsim.paper());
+    reference.set_obstacles(paintsim.obstacles());
+    reference.pigments = paintsim.pigments().to_data();
+    reference.solver = paintsim.solver();
+
+    let brush = Brush{
+        pos: [size[0] as f32 / 2., size[1] as f32 / 2.],
+        ..Default::default()
+    };
+
+    for i in 0..args.steps{
+        let brush = if i < args.steps / 2 {Some(brush)} else {None};
+        paintsim.set_brush(&mut state.queue, brush);
+        reference.brush = brush.unwrap_or(bytemuck::Zeroable::zeroed());
+
+        let mut encoder = state.device.create_command_encoder(&wgpu::CommandEncoderDescriptor{
+            label: Some("StepEncoder"),
+        });
+        paintsim.step(&mut state.queue, &mut encoder);
+        state.queue.submit(std::iter::once(encoder.finish()));
+
+        reference.step();
+    }
+
+    let mut cpu = vec![&reference.tex_vpf, &reference.tex_sat, &reference.tex_pressure];
+    cpu.extend(reference.tex_color.iter());
+    cpu.extend(reference.tex_float.iter());
+
+    let mut ok = true;
+    for ((name, gpu), cpu) in read_fields(&paintsim, state)?.iter().zip(cpu){
+        let diff = gpu.max_abs_diff(cpu);
+        println!("{}: max abs diff {:?}", name, diff);
+        ok &= diff.iter().all(|d| *d <= args.tolerance);
+    }
+
+    if !ok{
+        return Err(anyhow!("gpu simulation differs from the cpu reference by more than {}", args.tolerance));
+    }
+    Ok(())
+}
+
+///
+/// Runs a periodic simulation with a brush dab on the corner of the canvas during the first half
+/// of the steps, and a second one with the paper, obstacles and dab moved by half the canvas.
+/// The seams only lie in the middle of the second one, so the fields of both have to be equal
+/// up to the translation if the edges match.
+///
+fn check_tiling(args: &HeadlessArgs, state: &mut HeadlessState) -> Result<()>{
+    let mut paintsim = args.create_paintsim(state)?;
+    let size = paintsim.tex_src.size;
+
+    // The red black iterations and the multigrid levels are only shift invariant for offsets
+    // that are a multiple of the cells of the coarsest level.
+    let align = match args.solver{
+        Solver::Multigrid{..} => 1 << Multigrid::level_sizes(size, MULTIGRID_MIN_SIZE).len(),
+        _ => 2,
+    };
+    let offset = [(size[0] / 2 / align * align) as i32, (size[1] / 2 / align * align) as i32];
+    if offset[0] == 0 || offset[1] == 0{
+        return Err(anyhow!("canvas of size {:?} is too small to check the tiling", size));
+    }
+
+    let translate = |data: &[f32]|{
+        let (w, h) = (size[0] as i32, size[1] as i32);
+        (0..h).flat_map(|y| (0..w).map(move |x| (x, y))).map(|(x, y)|{
+            data[((y - offset[1]).rem_euclid(h) * w + (x - offset[0]).rem_euclid(w)) as usize]
+        }).collect::<Vec<_>>()
+    };
+
+    let mut moved = args.create_paintsim(state)?;
+    moved.set_paper(&state.queue, Paper{
+        height: translate(&paintsim.paper().height),
+        ..paintsim.paper().clone()
+    })?;
+    moved.set_obstacles(&state.queue, Obstacles{
+        solid: translate(&paintsim.obstacles().solid),
+        ..paintsim.obstacles().clone()
+    })?;
+
+    for (paintsim, pos) in [(&mut paintsim, [0., 0.]), (&mut moved, [offset[0] as f32, offset[1] as f32])]{
+        let brush = Brush{
+            pos,
+            ..Default::default()
+        };
+        for i in 0..args.steps{
+            paintsim.set_brush(&mut state.queue, if i < args.steps / 2 {Some(brush)} else {None});
+
+            let mut encoder = state.device.create_command_encoder(&wgpu::CommandEncoderDescriptor{
+                label: Some("StepEncoder"),
+            });
+            paintsim.step(&mut state.queue, &mut encoder);
+            state.queue.submit(std::iter::once(encoder.finish()));
+        }
+    }
+
+    let mut ok = true;
+    for ((name, field), (_, moved)) in read_fields(&paintsim, state)?.iter().zip(read_fields(&moved, state)?.iter()){
+        let diff = field.translate(offset).max_abs_diff(moved);
+        println!("{}: max abs diff {:?}", name, diff);
+        ok &= diff.iter().all(|d| *d <= args.tolerance);
+    }
+
+    if !ok{
+        return Err(anyhow!("the edges of the periodic simulation differ from its inside by more than {}", args.tolerance));
+    }
+    Ok(())
+}
+
+///
+/// Reads back the simulation textures, the pigment layers are split into one Field each.
+///
+fn read_fields(paintsim: &PaintSim, state: &HeadlessState) -> Result<Vec<(String, Field)>>{
+    let size = paintsim.tex_src.size;
+    let mut fields = vec![
+        ("tex_vpf".to_string(), Field::from_bytes(size, &paintsim.tex_vpf.read_blocking(&state.device, &state.queue))?),
+        ("tex_sat".to_string(), Field::from_bytes(size, &paintsim.tex_sat.read_blocking(&state.device, &state.queue))?),
+        ("tex_pressure".to_string(), Field::from_bytes(size, &paintsim.tex_pressure.read_blocking(&state.device, &state.queue))?),
+    ];
+    let tex_color = Field::layers_from_bytes(size, paintsim.tex_color.layers, &paintsim.tex_color.read_blocking(&state.device, &state.queue))?;
+    for (layer, field) in tex_color.into_iter().enumerate(){
+        fields.push((format!("tex_color[{}]", layer), field));
+    }
+    let tex_float = Field::layers_from_bytes(size, paintsim.tex_float.layers, &paintsim.tex_float.read_blocking(&state.device, &state.queue))?;
+    for (layer, field) in tex_float.into_iter().enumerate(){
+        fields.push((format!("tex_float[{}]", layer), field));
+    }
+    Ok(fields)
+}
@@ -0,0 +1,25 @@
+#[macro_use]
+extern crate more_asserts;
+
+extern crate nalgebra_glm as glm;
+extern crate naga;
+
+pub mod wgpu_utils;
+pub mod paintsim;
+pub mod display;
+pub mod headless;
+pub mod reference;
+pub mod paper;
+pub mod obstacles;
+pub mod clock;
+pub mod snapshot;
+pub mod float_image;
+pub mod recorder;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GlobalShaderData{
+    size: [f32; 2],
+    time: f32,
+    _pad0: f32,
+}
@@ -1,139 +1,413 @@
 use bytemuck::Zeroable;
-use wgpu::RenderPipeline;
-use wgpu_utils::{framework::{State, Framework}, mesh::{Mesh, Drawable}, vert::Vert2, pipeline::{self, RenderPipelineBuilder, shader_with_shaderc, VertexStateBuilder, FragmentStateBuilder, PipelineLayoutBuilder, RenderPass, RenderPassBuilder}, render_target::ColorAttachment, uniform::{UniformBindGroup, Uniform}, binding::{GetBindGroupLayout, GetBindGroup, CreateBindGroupLayout, BindGroup}, texture::Texture, buffer::Buffer};
+use wgpu_utils::framework::{State, Framework};
 
-#[macro_use]
-extern crate more_asserts;
+use fluid01::{wgpu_utils, paintsim, display, headless, recorder};
 
-extern crate nalgebra_glm as glm;
-extern crate naga;
+// snapshot written with F5 and restored with F9.
+const STATE_PATH: &str = "paintsim.state";
 
-mod wgpu_utils;
-mod paintsim;
+// time-lapse recorded with R and the steps between its frames.
+const RECORD_PATH: &str = "timelapse.gif";
+const RECORD_INTERVAL: u64 = 36;
 
-#[repr(C)]
-#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-struct GlobalShaderData{
-    size: [f32; 2],
-    time: f32,
-    _pad0: f32,
-}
+// frames between the conservation diagnostics printed, reading them back waits for the GPU.
+const STATS_INTERVAL: u64 = 60;
+
+// radius of the obstacles painted with the right mouse button.
+const MASK_RADIUS: f32 = 8.;
 
 struct WinState{
-    mesh: Mesh<Vert2>,
-    display_rp: pipeline::RenderPipeline,
-    //global_uniform: UniformBindGroup<GlobalShaderData>,
-    global_uniform: UniformBindGroup<GlobalShaderData>,
+    display: display::Display,
 
     paintsim: paintsim::PaintSim,
 
-    fc: usize,
+    cursor: winit::dpi::PhysicalPosition<f64>,
+    painting: bool,
+    // index of the pigment used by the brush.
+    pigment: usize,
+    display_mode: display::DisplayMode,
+    // dry the current layer into a glaze in the next render.
+    glaze: bool,
+    // switch to the next solver in the next render.
+    next_solver: bool,
+    // switch to the next advection scheme in the next render.
+    next_advection: bool,
+    // switch to the next boundary condition in the next render.
+    next_boundary: bool,
+    // time of the last render, the simulation is advanced by the time passed since then.
+    last_frame: Option<std::time::Instant>,
+    // paint obstacles with the right mouse button in the next render.
+    masking: bool,
+    // whether masking adds or removes obstacles.
+    mask_solid: bool,
+    // write a snapshot of the simulation in the next render.
+    save_state: bool,
+    // restore the snapshot in the next render.
+    load_state: bool,
+    // enable or disable the conservation diagnostics in the next render.
+    toggle_stats: bool,
+    // frames rendered, the diagnostics are read every STATS_INTERVAL frames.
+    frames: u64,
+    // start or stop the time-lapse in the next render.
+    toggle_recording: bool,
+    recorder: Option<recorder::Recorder>,
+}
+
+impl WinState{
+    ///
+    /// Maps the cursor position to simulation coordinates.
+    ///
+    /// In debug mode every quadrant of the display shows the whole canvas so the position inside
+    /// the quadrant is used.
+    ///
+    fn canvas_pos(&self, app: &wgpu_utils::framework::AppState) -> [f32; 2]{
+        let scale = match self.display_mode{
+            display::DisplayMode::Debug => 2.,
+            display::DisplayMode::Composite => 1.,
+        };
+        let uv = [
+            (self.cursor.x / app.size.width as f64 * scale).fract() as f32,
+            (self.cursor.y / app.size.height as f64 * scale).fract() as f32,
+        ];
+        let size = self.paintsim.tex_vpf.size;
+        [uv[0] * size[0] as f32, uv[1] * size[1] as f32]
+    }
 }
 
 impl State for WinState{
     fn new(app: &mut wgpu_utils::framework::AppState) -> Self {
-        let mesh = Mesh::new(&app.device, &Vert2::QUAD_VERTS, &Vert2::QUAD_IDXS).unwrap();
-
-        let global_uniform = UniformBindGroup::<GlobalShaderData>::new(&app.device, GlobalShaderData{
-            size: [app.size.width as f32, app.size.height as f32],
-            time: 0.0,
-            _pad0: 0.0,
-        });
-
-        let paintsim = paintsim::PaintSim::new(&app.device, &app.queue, "assets/test03.jpg").unwrap();
+        // `--grid <scale | <width>x<height>>` sets the size of the simulation grid.
+        let grid = match std::env::args()
+            .skip_while(|arg| arg != "--grid")
+            .nth(1)
+            .map(|grid| grid.parse::<paintsim::GridSize>())
+            .transpose(){
+            Ok(grid) => grid.unwrap_or(paintsim::GridSize::Source),
+            Err(err) => {
+                eprintln!("error: --grid: {:?}", err);
+                std::process::exit(1);
+            }
+        };
+        let img = image::open("assets/test03.jpg").unwrap();
+        let paintsim = paintsim::PaintSim::from_image(&app.device, &app.queue, &img, false, grid).unwrap();
 
-        // Init display pipeline.
-        let display_vsh = shader_with_shaderc(&app.device, include_str!("shaders/vf_display.glsl"), shaderc::ShaderKind::Vertex, "main", None).unwrap();
-        let display_fsh = shader_with_shaderc(&app.device, include_str!("shaders/vf_display.glsl"), shaderc::ShaderKind::Fragment, "main", None).unwrap();
-
-        let display_vst = VertexStateBuilder::new(&display_vsh)
-            .push_vert_layout(mesh.vert_buffer_layout())
-            .build();
-
-        let display_fst = FragmentStateBuilder::new(&display_fsh)
-            .push_target_replace(app.config.format)
-            .build();
-
-        // TODO: put all textures together into one bindgroup.
-        let display_rpl = PipelineLayoutBuilder::new()
-            .push(global_uniform.get_bind_group_layout())
-            .push(&BindGroup::<Texture>::create_bind_group_layout(&app.device, None))
-            .push(&BindGroup::<Texture>::create_bind_group_layout(&app.device, None))
-            .push(&BindGroup::<Texture>::create_bind_group_layout(&app.device, None))
-            .create(&app.device, None);
-
-        let display_rp = RenderPipelineBuilder::new(display_vst, display_fst)
-            .set_layout(&display_rpl)
-            .build(&app.device);
+        let display = display::Display::new(&app.device, app.config.format, [app.size.width, app.size.height]).unwrap();
 
         Self{
-            mesh,
-            display_rp,
-            global_uniform,
+            display,
             paintsim,
-            fc: 0,
+            cursor: winit::dpi::PhysicalPosition::new(0., 0.),
+            painting: false,
+            pigment: 0,
+            display_mode: display::DisplayMode::Composite,
+            glaze: false,
+            next_solver: false,
+            next_advection: false,
+            next_boundary: false,
+            last_frame: None,
+            masking: false,
+            mask_solid: true,
+            save_state: false,
+            load_state: false,
+            toggle_stats: false,
+            frames: 0,
+            toggle_recording: false,
+            recorder: None,
         }
     }
 
-    fn render(&mut self, app: &mut wgpu_utils::framework::AppState, control_flow: &mut winit::event_loop::ControlFlow) -> Result<(), wgpu::SurfaceError> {
+    fn render(&mut self, app: &mut wgpu_utils::framework::AppState, _control_flow: &mut winit::event_loop::ControlFlow) -> Result<(), wgpu::SurfaceError> {
         let output = app.surface.get_current_texture()?;
         let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
         let mut encoder = app.device.create_command_encoder(&wgpu::CommandEncoderDescriptor{
             label: Some("Render Encoder"),
         });
 
-        for i in 0..6{
-            self.paintsim.step(&mut app.queue, &mut encoder, &app.device);
+        if self.glaze{
+            match self.paintsim.glaze(&mut encoder){
+                Ok(()) => println!("glazes: {}", self.paintsim.glazes()),
+                Err(err) => println!("{}", err),
+            }
+            self.glaze = false;
         }
 
-        // render result to view.
-        {
-            let mut render_pass = RenderPassBuilder::new()
-                .push_color_attachment(view.color_attachment_clear())
-                .begin(&mut encoder, None);
+        if self.next_solver{
+            let solver = self.paintsim.solver().next();
+            self.paintsim.set_solver(&mut app.queue, solver);
+            println!("solver: {:?}", solver);
+            self.next_solver = false;
+        }
+
+        if self.next_advection{
+            let advection = self.paintsim.advection().next();
+            self.paintsim.set_advection(&mut app.queue, advection);
+            println!("advection: {:?}", advection);
+            self.next_advection = false;
+        }
+
+        if self.next_boundary{
+            let boundary = self.paintsim.boundary().next();
+            self.paintsim.set_boundary(&mut app.queue, boundary);
+            println!("boundary: {:?}", boundary);
+            self.next_boundary = false;
+        }
+
+        if self.save_state{
+            match self.paintsim.save_state(&app.device, &app.queue, STATE_PATH){
+                Ok(()) => println!("saved state to {}", STATE_PATH),
+                Err(err) => println!("{}", err),
+            }
+            self.save_state = false;
+        }
+
+        if self.load_state{
+            match self.paintsim.load_state(&mut app.queue, STATE_PATH){
+                Ok(()) => println!("loaded state from {}", STATE_PATH),
+                Err(err) => println!("{}", err),
+            }
+            self.load_state = false;
+        }
 
-            let mut render_pass_pipeline = render_pass.set_pipeline(&self.display_rp);
-            render_pass_pipeline.set_bind_group(0, self.global_uniform.get_bind_group(), &[]);
-            render_pass_pipeline.set_bind_group(1, self.paintsim.tex_vpf.get_bind_group(), &[]);
-            render_pass_pipeline.set_bind_group(2, self.paintsim.tex_color.get_bind_group(), &[]);
-            render_pass_pipeline.set_bind_group(3, self.paintsim.tex_float.get_bind_group(), &[]);
+        if self.toggle_stats{
+            let enabled = !self.paintsim.stats_enabled();
+            if let Err(err) = self.paintsim.set_stats(&app.device, enabled){
+                println!("{}", err);
+            }
+            self.toggle_stats = false;
+        }
 
-            self.mesh.draw(&mut render_pass_pipeline);
+        if self.toggle_recording{
+            match self.recorder.take(){
+                Some(recorder) => {
+                    let frames = recorder.frames();
+                    match recorder.finish(&app.device){
+                        Ok(()) => println!("recorded {} frames to {}", frames, RECORD_PATH),
+                        Err(err) => println!("{}", err),
+                    }
+                }
+                None => {
+                    match recorder::Recorder::new(&app.device, &app.queue, &self.paintsim, RECORD_INTERVAL, recorder::RecordOutput::from_path(RECORD_PATH)){
+                        Ok(recorder) => {
+                            println!("recording to {}", RECORD_PATH);
+                            self.recorder = Some(recorder);
+                        }
+                        Err(err) => println!("{}", err),
+                    }
+                }
+            }
+            self.toggle_recording = false;
         }
 
-        self.fc += 1;
-        println!("time: {}", self.fc as f32/60.0);
-        self.global_uniform.content.borrow_ref(&mut app.queue).time = self.fc as f32 / 60.;
-        //self.global_uniform.content.get_content().time = self.fc as f32 / 60.0;
-        //self.global_uniform.content.update_int(&app.queue);
+        if self.masking{
+            let pos = self.canvas_pos(app);
+            self.paintsim.paint_obstacles(&app.queue, pos, MASK_RADIUS, self.mask_solid);
+        }
+
+        let brush = if self.painting{
+            Some(paintsim::Brush{
+                pos: self.canvas_pos(app),
+                pigment: Zeroable::zeroed(),
+                ..Default::default()
+            }.with_pigment(self.pigment, paintsim::Brush::PIGMENT_AMOUNT))
+        } else {None};
+        self.paintsim.set_brush(&mut app.queue, brush);
+
+        let now = std::time::Instant::now();
+        let elapsed = self.last_frame.map(|last| now - last).unwrap_or_default();
+        self.last_frame = Some(now);
+        self.paintsim.advance(&mut app.queue, &mut encoder, elapsed);
+
+        // render result to view.
+        self.display.render(&mut encoder, &view, &self.paintsim, self.display_mode);
+
+        if let Some(recorder) = &mut self.recorder{
+            recorder.record(&app.device, &mut encoder, &self.paintsim, self.display_mode);
+        }
 
         app.queue.submit(std::iter::once(encoder.finish()));
         output.present();
 
+        if let Some(recorder) = &mut self.recorder{
+            if let Err(err) = recorder.poll(&app.device){
+                println!("{}", err);
+                self.recorder = None;
+            }
+        }
+
+        if self.frames.is_multiple_of(STATS_INTERVAL){
+            if let Some(stats) = self.paintsim.read_stats(&app.device){
+                println!("{}", stats);
+            }
+        }
+        self.frames += 1;
+
         Ok(())
     }
 
-    fn pre_render(&mut self, app: &mut wgpu_utils::framework::AppState, control_flow: &mut winit::event_loop::ControlFlow) -> Result<(), wgpu::SurfaceError> {
+    fn pre_render(&mut self, app: &mut wgpu_utils::framework::AppState, _control_flow: &mut winit::event_loop::ControlFlow) -> Result<(), wgpu::SurfaceError> {
         let mut encoder = app.device.create_command_encoder(&wgpu::CommandEncoderDescriptor{
             label: Some("PreRenderEncoder"),
         });
 
-        let mut test = Buffer::new_storage(&app.device, None, &[0, 1, 2]);
-        test.slice(..).map_blocking_mut(&app.device)[0] = 3;
-        println!("{:?}", test.slice(..).map_blocking(&app.device)[0]);
-
-        self.paintsim.prepare(&app.queue, &mut encoder);
+        self.paintsim.prepare(&mut encoder);
 
         app.queue.submit(std::iter::once(encoder.finish()));
 
         Ok(())
     }
 
+    fn input(&mut self, event: &winit::event::WindowEvent) -> bool {
+        use winit::event::{WindowEvent, KeyboardInput, ElementState, VirtualKeyCode};
+        match event{
+            WindowEvent::KeyboardInput{
+                input: KeyboardInput{
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(key),
+                    ..
+                },
+                ..
+            } => {
+                // switch between the composited painting and the debug view.
+                if *key == VirtualKeyCode::Tab{
+                    self.display_mode = self.display_mode.toggle();
+                    println!("display: {:?}", self.display_mode);
+                    return true;
+                }
+
+                // switch between the fragment and compute shader implementation of the step.
+                if *key == VirtualKeyCode::C{
+                    let step_mode = self.paintsim.step_mode().toggle();
+                    self.paintsim.set_step_mode(step_mode);
+                    println!("step: {:?}", step_mode);
+                    return true;
+                }
+
+                if *key == VirtualKeyCode::G{
+                    self.glaze = true;
+                    return true;
+                }
+
+                // cycle through the solvers keeping the velocity free of divergence.
+                if *key == VirtualKeyCode::P{
+                    self.next_solver = true;
+                    return true;
+                }
+
+                // switch between adding and removing obstacles with the right mouse button.
+                if *key == VirtualKeyCode::O{
+                    self.mask_solid = !self.mask_solid;
+                    println!("obstacles: {}", if self.mask_solid {"add"} else {"remove"});
+                    return true;
+                }
+
+                // cycle through the boundary conditions at the edges of the canvas.
+                if *key == VirtualKeyCode::B{
+                    self.next_boundary = true;
+                    return true;
+                }
+
+                // pause and resume the simulation.
+                if *key == VirtualKeyCode::Space{
+                    self.paintsim.clock_mut().toggle_pause();
+                    println!("paused: {}", self.paintsim.clock().paused());
+                    return true;
+                }
+
+                // run the simulation faster or slower.
+                if *key == VirtualKeyCode::Equals || *key == VirtualKeyCode::NumpadAdd{
+                    let scale = self.paintsim.clock().scale() * 2.;
+                    self.paintsim.clock_mut().set_scale(scale);
+                    println!("time scale: {}", scale);
+                    return true;
+                }
+                if *key == VirtualKeyCode::Minus || *key == VirtualKeyCode::NumpadSubtract{
+                    let scale = self.paintsim.clock().scale() / 2.;
+                    self.paintsim.clock_mut().set_scale(scale);
+                    println!("time scale: {}", scale);
+                    return true;
+                }
+
+                // start and stop recording a time-lapse.
+                if *key == VirtualKeyCode::R{
+                    self.toggle_recording = true;
+                    return true;
+                }
+
+                // print the conservation diagnostics every STATS_INTERVAL frames.
+                if *key == VirtualKeyCode::I{
+                    self.toggle_stats = true;
+                    return true;
+                }
+
+                // save and restore the state of the simulation.
+                if *key == VirtualKeyCode::F5{
+                    self.save_state = true;
+                    return true;
+                }
+                if *key == VirtualKeyCode::F9{
+                    self.load_state = true;
+                    return true;
+                }
+
+                // cycle through the advection schemes.
+                if *key == VirtualKeyCode::A{
+                    self.next_advection = true;
+                    return true;
+                }
+
+                // select the pigment of the brush with the number keys.
+                let pigment = match key{
+                    VirtualKeyCode::Key1 => 0,
+                    VirtualKeyCode::Key2 => 1,
+                    VirtualKeyCode::Key3 => 2,
+                    VirtualKeyCode::Key4 => 3,
+                    VirtualKeyCode::Key5 => 4,
+                    VirtualKeyCode::Key6 => 5,
+                    VirtualKeyCode::Key7 => 6,
+                    VirtualKeyCode::Key8 => 7,
+                    _ => return false,
+                };
+                if let Some(p) = self.paintsim.pigments().get(pigment){
+                    println!("pigment: {}", p.name);
+                    self.pigment = pigment;
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn cursor_moved(&mut self, _app: &mut wgpu_utils::framework::AppState, _device_id: &winit::event::DeviceId, position: &winit::dpi::PhysicalPosition<f64>) {
+        self.cursor = *position;
+    }
+
+    fn mouse_input(&mut self, _app: &mut wgpu_utils::framework::AppState, _device_id: &winit::event::DeviceId, state: &winit::event::ElementState, button: &winit::event::MouseButton) {
+        if *button == winit::event::MouseButton::Left{
+            self.painting = *state == winit::event::ElementState::Pressed;
+        }
+        if *button == winit::event::MouseButton::Right{
+            self.masking = *state == winit::event::ElementState::Pressed;
+        }
+    }
+
     fn resize(&mut self, app: &mut wgpu_utils::framework::AppState, new_size: winit::dpi::PhysicalSize<u32>) {
-        self.global_uniform.borrow_ref(&mut app.queue).size = [new_size.width as f32, new_size.height as f32];
+        self.display.resize(&mut app.queue, [new_size.width, new_size.height]);
     }
 }
 
 fn main() {
-    let framework = Framework::<WinState>::new([800, 600]).run();
+    let args: Vec<String> = std::env::args().collect();
+
+    if args.get(1).map(|arg| arg.as_str()) == Some("--headless"){
+        env_logger::init();
+        let result = headless::HeadlessArgs::parse(&args[2..])
+            .and_then(|headless_args| headless::run(&headless_args));
+        if let Err(err) = result{
+            eprintln!("error: {:?}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    Framework::<WinState>::new([800, 600]).run();
 }
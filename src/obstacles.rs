@@ -0,0 +1,121 @@
+use anyhow::*;
+
+///
+/// Solid cells of the canvas that water and pigment can not enter, like masking fluid or tape.
+///
+/// Row y of the mask corresponds to row y of the simulation textures.
+///
+#[derive(Clone, Debug)]
+pub struct Obstacles{
+    pub size: [u32; 2],
+    // 1 for solid cells, 0 otherwise.
+    pub solid: Vec<f32>,
+}
+
+impl Obstacles{
+    pub fn empty(size: [u32; 2]) -> Self{
+        Self{
+            size,
+            solid: vec![0.; (size[0] * size[1]) as usize],
+        }
+    }
+
+    ///
+    /// Loads a mask image resized to size, dark pixels are solid.
+    ///
+    pub fn load_from_path(path: &str, size: [u32; 2]) -> Result<Self>{
+        let img = image::open(path)?;
+        // Flipped like Texture::from_image so the mask lines up with the source image.
+        let img = img.flipv().resize_exact(size[0], size[1], image::imageops::FilterType::Nearest).to_luma8();
+
+        Ok(Self{
+            size,
+            solid: img.pixels().map(|p| if p[0] < 128 {1.} else {0.}).collect(),
+        })
+    }
+
+    pub fn is_solid(&self, x: u32, y: u32) -> bool{
+        self.solid[(y * self.size[0] + x) as usize] >= 0.5
+    }
+
+    ///
+    /// Makes the cells within radius of pos solid or removes them.
+    ///
+    /// Returns the origin and size of the changed rectangle or None if it is outside of the mask.
+    ///
+    pub fn paint(&mut self, pos: [f32; 2], radius: f32, solid: bool) -> Option<([u32; 2], [u32; 2])>{
+        let min = [(pos[0] - radius).floor().max(0.) as u32, (pos[1] - radius).floor().max(0.) as u32];
+        let max = [
+            ((pos[0] + radius).ceil().max(0.) as u32).min(self.size[0]),
+            ((pos[1] + radius).ceil().max(0.) as u32).min(self.size[1]),
+        ];
+        if min[0] >= max[0] || min[1] >= max[1]{
+            return None;
+        }
+
+        for y in min[1]..max[1]{
+            for x in min[0]..max[0]{
+                let (dx, dy) = (x as f32 + 0.5 - pos[0], y as f32 + 0.5 - pos[1]);
+                if dx * dx + dy * dy <= radius * radius{
+                    self.solid[(y * self.size[0] + x) as usize] = if solid {1.} else {0.};
+                }
+            }
+        }
+        Some((min, [max[0] - min[0], max[1] - min[1]]))
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn solid_cells(obstacles: &Obstacles) -> Vec<[u32; 2]>{
+        (0..obstacles.size[1]).flat_map(|y| (0..obstacles.size[0]).map(move |x| [x, y]))
+            .filter(|&[x, y]| obstacles.is_solid(x, y))
+            .collect()
+    }
+
+    #[test]
+    fn paint_fills_the_cells_within_the_radius(){
+        let mut obstacles = Obstacles::empty([8, 8]);
+        assert_eq!(obstacles.paint([4., 4.], 1.5, true), Some(([2, 2], [4, 4])));
+        // the cell centers closer than the radius.
+        assert_eq!(solid_cells(&obstacles), vec![[3, 3], [4, 3], [3, 4], [4, 4]]);
+
+        assert_eq!(obstacles.paint([4., 4.], 1.5, false), Some(([2, 2], [4, 4])));
+        assert!(solid_cells(&obstacles).is_empty());
+    }
+
+    #[test]
+    fn paint_is_clipped_at_the_edges(){
+        let mut obstacles = Obstacles::empty([8, 6]);
+        assert_eq!(obstacles.paint([0., 0.], 1., true), Some(([0, 0], [1, 1])));
+        assert_eq!(obstacles.paint([8., 6.], 1., true), Some(([7, 5], [1, 1])));
+        assert_eq!(obstacles.paint([-0.5, 3.], 1.2, true), Some(([0, 1], [1, 4])));
+        assert_eq!(solid_cells(&obstacles), vec![[0, 0], [0, 2], [0, 3], [7, 5]]);
+    }
+
+    #[test]
+    fn paint_outside_of_the_mask_changes_nothing(){
+        let mut obstacles = Obstacles::empty([8, 6]);
+        // circles ending on or before the edges.
+        assert_eq!(obstacles.paint([-2., 3.], 2., true), None);
+        assert_eq!(obstacles.paint([4., -3.], 2., true), None);
+        assert_eq!(obstacles.paint([10., 3.], 2., true), None);
+        assert_eq!(obstacles.paint([4., 9.], 2., true), None);
+        assert!(solid_cells(&obstacles).is_empty());
+    }
+
+    #[test]
+    fn dark_pixels_of_a_mask_are_solid(){
+        let path = std::env::temp_dir().join(format!("fluid01_{}_mask.png", std::process::id()));
+        // the top row of the image is dark.
+        image::GrayImage::from_raw(2, 2, vec![0, 127, 128, 255]).unwrap().save(&path).unwrap();
+        let obstacles = Obstacles::load_from_path(path.to_str().unwrap(), [4, 4]);
+        std::fs::remove_file(&path).unwrap();
+        let obstacles = obstacles.unwrap();
+
+        // flipped so the top row of the image is the last row of the mask.
+        assert_eq!(solid_cells(&obstacles), vec![[0, 2], [1, 2], [2, 2], [3, 2], [0, 3], [1, 3], [2, 3], [3, 3]]);
+    }
+}
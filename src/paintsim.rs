@@ -1,118 +1,1060 @@
 use crate::wgpu_utils::binding::{GetBindGroupLayout, GetBindGroup, BindGroup};
-use crate::wgpu_utils::buffer::{Buffer, self};
-use crate::wgpu_utils::uniform::{self, UniformBindGroup, Uniform, UniformVec};
+use crate::wgpu_utils::uniform::Uniform;
 use crate::wgpu_utils::mesh::Drawable;
-use crate::wgpu_utils::pipeline::{shader_with_shaderc, VertexStateBuilder, FragmentStateBuilder, PipelineLayoutBuilder, RenderPipelineBuilder, RenderPassBuilder, PipelineLayout, ComputePipeline};
+use crate::wgpu_utils::pipeline::{shader_with_shaderc, shader_with_shaderc_defines, VertexStateBuilder, FragmentStateBuilder, PipelineLayoutBuilder, RenderPipelineBuilder, RenderPassBuilder, PipelineLayout, ComputePipeline};
 use crate::wgpu_utils::render_target::ColorAttachment;
-use crate::wgpu_utils::{texture::Texture, mesh::Mesh, vert::Vert2, pipeline};
+use crate::wgpu_utils::pingpong::PingPong;
+use crate::wgpu_utils::multigrid::Multigrid;
+use crate::wgpu_utils::reduction::{Reduction, ReduceInput};
+use crate::wgpu_utils::resample::Resample;
+use crate::wgpu_utils::{texture::{Texture, TextureArray, SampledTextureArray, StorageTexture, StorageTextureArray}, mesh::Mesh, vert::Vert2, pipeline};
 use crate::GlobalShaderData;
+use crate::paper::{Paper, PaperParams};
+use crate::obstacles::Obstacles;
+use crate::clock::Clock;
+use crate::snapshot::Snapshot;
+use crate::float_image::FloatImage;
 use crate::wgpu_utils::binding::CreateBindGroupLayout;
 use anyhow::*;
+use bytemuck::Zeroable;
+
+///
+/// Physical constants of the watercolor step.
+///
+/// Bound as a uniform next to the GlobalShaderData so they can be tuned between steps without
+/// recompiling the shader.
+///
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PaintParams{
+    // maximum velocity in x and y direction.
+    pub vmax: [f32; 2],
+    // time step.
+    pub dt: f32,
+    // density invariance coefficient (Guay et al.).
+    pub k: f32,
+    // viscosity of the velocity field.
+    pub nu: f32,
+    pub kappa: f32,
+    // evaporation rate at the edges of the wet area.
+    pub evap_nu: f32,
+    // minimal height of the fluid column.
+    pub hmin: f32,
+    // diffusion coefficient of the floating pigment in wet areas.
+    pub float_nu: f32,
+    // how strongly the slope of the paper pushes the water downhill.
+    pub paper_slope: f32,
+    // how fast granulating pigment settles into the valleys of the paper.
+    pub paper_settle: f32,
+    // water capacity of the paper at height 0 and 1.
+    pub cap_min: f32,
+    pub cap_max: f32,
+    // rate at which the paper absorbs water from the wet area.
+    pub cap_absorb: f32,
+    // saturation below which a cell does not pass water to its neighbours.
+    pub cap_epsilon: f32,
+    // saturation below which a cell does not receive water from its neighbours.
+    pub cap_delta: f32,
+    // saturation above which a cell becomes part of the wet area.
+    pub cap_sigma: f32,
+    // evaporation rate of the water in the paper outside the wet area.
+    pub cap_evap: f32,
+    // strength of the outward flow and evaporation at the edges of the wet area which piles up
+    // pigment there.
+    pub edge_darkening: f32,
+    // 1 if the pressure projection replaces the density invariance correction, set by
+    // PaintSim::set_solver.
+    pub projection: u32,
+    // advection scheme and limiter, set by PaintSim::set_advection.
+    pub advection: u32,
+    pub limiter: u32,
+    // strength of the vorticity confinement, 0 skips the pass.
+    pub vorticity: f32,
+    // boundary condition at the edges of the canvas, set by PaintSim::set_boundary.
+    pub boundary: u32,
+}
+
+// The uniform block of the shaders is rounded up to a multiple of 16 bytes, a field added without
+// padding would make the buffer smaller than the block.
+const _: () = assert!(std::mem::size_of::<PaintParams>().is_multiple_of(16));
+
+impl Default for PaintParams{
+    fn default() -> Self{
+        Self{
+            vmax: [5.0, 5.0],
+            dt: 0.15,
+            k: 0.3,
+            nu: 0.5,
+            kappa: 0.5,
+            evap_nu: 0.01,
+            hmin: 0.5,
+            float_nu: 0.001,
+            paper_slope: 1.0,
+            paper_settle: 2.0,
+            cap_min: 0.3,
+            cap_max: 0.7,
+            cap_absorb: 0.05,
+            cap_epsilon: 0.3,
+            cap_delta: 0.0,
+            cap_sigma: 0.6,
+            cap_evap: 0.01,
+            edge_darkening: 0.3,
+            projection: 0,
+            advection: 0,
+            limiter: 0,
+            vorticity: 0.0,
+            boundary: 0,
+        }
+    }
+}
+
+// Maximum number of simultaneously simulated pigments.
+pub const MAX_PIGMENTS: usize = 8;
+// Number of RGBA layers of the pigment texture arrays, every channel holds one pigment.
+// The step shaders have one output per layer.
+pub const PIGMENT_LAYERS: u32 = (MAX_PIGMENTS as u32).div_ceil(4);
+
+///
+/// Properties of a pigment as described by Curtis et al.
+///
+#[derive(Clone, Debug)]
+pub struct Pigment{
+    pub name: String,
+    // how fast the pigment settles onto the paper.
+    pub density: f32,
+    // how strongly deposited pigment resists being lifted back up.
+    pub staining: f32,
+    // how much the pigment collects in the valleys of the paper.
+    pub granulation: f32,
+    // Kubelka-Munk absorption coefficients per rgb channel.
+    pub absorption: [f32; 3],
+    // Kubelka-Munk scattering coefficients per rgb channel.
+    pub scattering: [f32; 3],
+}
+
+impl Pigment{
+    pub fn new(name: &str, absorption: [f32; 3], scattering: [f32; 3]) -> Self{
+        Self{
+            name: name.to_string(),
+            density: 0.02,
+            staining: 1.0,
+            granulation: 0.5,
+            absorption,
+            scattering,
+        }
+    }
+
+    pub fn set_density(mut self, density: f32) -> Self{
+        self.density = density;
+        self
+    }
+
+    pub fn set_staining(mut self, staining: f32) -> Self{
+        self.staining = staining;
+        self
+    }
+
+    pub fn set_granulation(mut self, granulation: f32) -> Self{
+        self.granulation = granulation;
+        self
+    }
+}
+
+///
+/// Pigment properties as they are laid out in the uniform buffer.
+///
+/// Scalar properties are packed like the pigment texture arrays: pigment i is stored in
+/// channel i % 4 of layer i / 4.
+///
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PigmentData{
+    pub density: [[f32; 4]; PIGMENT_LAYERS as usize],
+    pub staining: [[f32; 4]; PIGMENT_LAYERS as usize],
+    pub granulation: [[f32; 4]; PIGMENT_LAYERS as usize],
+    pub absorption: [[f32; 4]; MAX_PIGMENTS],
+    pub scattering: [[f32; 4]; MAX_PIGMENTS],
+    pub count: u32,
+    pub _pad0: [u32; 3],
+}
+
+///
+/// The pigments simulated by a PaintSim.
+///
+/// The index of a pigment in the registry determines where it is stored in the pigment texture
+/// arrays (see PigmentRegistry::location).
+///
+#[derive(Clone, Debug)]
+pub struct PigmentRegistry{
+    pigments: Vec<Pigment>,
+}
+
+impl PigmentRegistry{
+    pub fn new() -> Self{
+        Self{
+            pigments: Vec::new(),
+        }
+    }
+
+    ///
+    /// Add a pigment and return its index.
+    ///
+    pub fn push(&mut self, pigment: Pigment) -> Result<usize>{
+        if self.pigments.len() >= MAX_PIGMENTS{
+            return Err(anyhow!("at most {} pigments are supported", MAX_PIGMENTS));
+        }
+        self.pigments.push(pigment);
+        Ok(self.pigments.len() - 1)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Pigment>{
+        self.pigments.get(index)
+    }
+
+    pub fn len(&self) -> usize{
+        self.pigments.len()
+    }
+
+    pub fn is_empty(&self) -> bool{
+        self.pigments.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Pigment>{
+        self.pigments.iter()
+    }
+
+    ///
+    /// Index of the pigment with the given name.
+    ///
+    pub fn find(&self, name: &str) -> Option<usize>{
+        self.pigments.iter().position(|pigment| pigment.name == name)
+    }
+
+    ///
+    /// Layer and channel of the pigment textures that store the pigment with the given index.
+    ///
+    pub fn location(index: usize) -> (u32, usize){
+        ((index / 4) as u32, index % 4)
+    }
+
+    pub fn to_data(&self) -> PigmentData{
+        let mut data = PigmentData::zeroed();
+        for (i, pigment) in self.pigments.iter().enumerate(){
+            let (layer, channel) = Self::location(i);
+            data.density[layer as usize][channel] = pigment.density;
+            data.staining[layer as usize][channel] = pigment.staining;
+            data.granulation[layer as usize][channel] = pigment.granulation;
+            let [k0, k1, k2] = pigment.absorption;
+            data.absorption[i] = [k0, k1, k2, 0.0];
+            let [s0, s1, s2] = pigment.scattering;
+            data.scattering[i] = [s0, s1, s2, 0.0];
+        }
+        data.count = self.pigments.len() as u32;
+        data
+    }
+}
+
+impl Default for PigmentRegistry{
+    ///
+    /// Some of the pigments measured by Curtis et al.
+    ///
+    fn default() -> Self{
+        let mut registry = Self::new();
+        let pigments = [
+            Pigment::new("quinacridone_rose", [0.22, 1.47, 0.57], [0.05, 0.003, 0.03]),
+            Pigment::new("cadmium_yellow", [0.10, 0.36, 3.45], [0.97, 0.65, 0.007])
+                .set_granulation(0.2),
+            Pigment::new("french_ultramarine", [0.86, 0.86, 0.06], [0.005, 0.005, 0.09])
+                .set_granulation(0.9),
+            Pigment::new("indian_red", [0.46, 1.07, 1.50], [1.28, 0.38, 0.21])
+                .set_density(0.05),
+            Pigment::new("hookers_green", [1.62, 0.61, 1.64], [0.01, 0.012, 0.003])
+                .set_staining(2.0),
+            Pigment::new("cerulean_blue", [1.52, 0.32, 0.25], [0.06, 0.26, 0.40])
+                .set_granulation(0.8),
+            Pigment::new("burnt_umber", [0.74, 1.54, 2.10], [0.09, 0.09, 0.004])
+                .set_density(0.04),
+            Pigment::new("hansa_yellow", [0.06, 0.21, 1.78], [0.50, 0.88, 0.009]),
+        ];
+        for pigment in pigments{
+            registry.push(pigment).unwrap();
+        }
+        registry
+    }
+}
+
+// Pigments the cyan, magenta and yellow of the source image are painted with.
+pub const SOURCE_PIGMENTS: [&str; 3] = ["cerulean_blue", "quinacridone_rose", "hansa_yellow"];
+
+///
+/// Amount of every pigment deposited by PaintSim::prepare per unit of cyan, magenta and yellow
+/// of the source image, laid out like the pigment texture arrays.
+///
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct SourcePigments{
+    pub cyan: [[f32; 4]; PIGMENT_LAYERS as usize],
+    pub magenta: [[f32; 4]; PIGMENT_LAYERS as usize],
+    pub yellow: [[f32; 4]; PIGMENT_LAYERS as usize],
+}
+
+impl SourcePigments{
+    ///
+    /// Paints every colour with its pigment of SOURCE_PIGMENTS, colours whose pigment is not in
+    /// the registry are left out.
+    ///
+    pub fn from_registry(registry: &PigmentRegistry) -> Self{
+        let mut data = Self::zeroed();
+        for (amounts, name) in [&mut data.cyan, &mut data.magenta, &mut data.yellow].into_iter().zip(SOURCE_PIGMENTS){
+            if let Some(index) = registry.find(name){
+                let (layer, channel) = PigmentRegistry::location(index);
+                amounts[layer as usize][channel] = 1.0;
+            }
+        }
+        data
+    }
+}
+
+///
+/// A brush dab applied in every step while it is set.
+///
+/// A zeroed Brush (radius 0) does not add anything.
+///
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Brush{
+    // amount of every pigment added per step in the center of the brush, laid out like the
+    // pigment texture arrays.
+    pub pigment: [[f32; 4]; PIGMENT_LAYERS as usize],
+    // position in simulation coordinates (pixels).
+    pub pos: [f32; 2],
+    pub radius: f32,
+    // water height added per step in the center of the brush.
+    pub water: f32,
+}
+
+impl Default for Brush{
+    fn default() -> Self{
+        Self{
+            pigment: [[0.0; 4]; PIGMENT_LAYERS as usize],
+            pos: [0.0, 0.0],
+            radius: 7.0,
+            water: 2.0,
+        }.with_pigment(0, Self::PIGMENT_AMOUNT)
+    }
+}
+
+impl Brush{
+    // default amount of pigment added per step.
+    pub const PIGMENT_AMOUNT: f32 = 0.002;
+
+    pub fn with_pigment(mut self, index: usize, amount: f32) -> Self{
+        let (layer, channel) = PigmentRegistry::location(index);
+        self.pigment[layer as usize][channel] = amount;
+        self
+    }
+}
+
+// Maximum number of dried glazes below the wet layer.
+pub const MAX_GLAZES: u32 = 4;
+
+///
+/// MAX_PIGMENTS, PIGMENT_LAYERS and MAX_GLAZES as defines of the shaders sizing their arrays and
+/// outputs with them.
+///
+pub struct PigmentDefines{
+    max_pigments: String,
+    pigment_layers: String,
+    max_glazes: String,
+}
+
+impl Default for PigmentDefines{
+    fn default() -> Self{
+        Self::new()
+    }
+}
+
+impl PigmentDefines{
+    pub fn new() -> Self{
+        Self{
+            max_pigments: MAX_PIGMENTS.to_string(),
+            pigment_layers: PIGMENT_LAYERS.to_string(),
+            max_glazes: MAX_GLAZES.to_string(),
+        }
+    }
+
+    ///
+    /// The defines followed by the extra ones.
+    ///
+    pub fn with<'d>(&'d self, extra: &[(&'d str, &'d str)]) -> Vec<(&'d str, &'d str)>{
+        let mut defines = vec![
+            ("MAX_PIGMENTS", self.max_pigments.as_str()),
+            ("PIGMENT_LAYERS", self.pigment_layers.as_str()),
+            ("MAX_GLAZES", self.max_glazes.as_str()),
+        ];
+        defines.extend_from_slice(extra);
+        defines
+    }
+}
+
+///
+/// Uniforms and the paper shared by all passes of the simulation (set 0).
+///
+/// The paper is bound with the uniforms so the passes fit into the 4 bind groups every adapter
+/// supports.
+///
+pub type PaintUniforms = (Uniform<GlobalShaderData>, Uniform<PaintParams>, Uniform<Brush>, Uniform<PigmentData>, Texture);
+
+///
+/// The deposited and the floating pigment of one side of the ping pong texture arrays.
+///
+pub type PaintPigments = (SampledTextureArray, SampledTextureArray);
+
+///
+/// Storage textures written by the compute step: vpf, color layers, float layers.
+///
+pub type PaintStorage = (StorageTexture, StorageTextureArray, StorageTextureArray);
 
 #[allow(non_camel_case_types)]
 pub enum PaintPipelineLayout{
-    global_uniform = 0,
+    uniforms = 0,
     tex_vpf,
-    tex_color,
-    tex_float,
+    tex_pigments,
+    // only used by the compute step.
+    storage,
 }
 
 impl PaintPipelineLayout{
     pub fn create_pipeline_layout(device: &wgpu::Device) -> PipelineLayout{
         PipelineLayoutBuilder::new()
-            .push(&UniformBindGroup::<GlobalShaderData>::create_bind_group_layout(device, None))
-            .push(&BindGroup::<Texture>::create_bind_group_layout(device, None))
+            .push(&BindGroup::<PaintUniforms>::create_bind_group_layout(device, None))
             .push(&BindGroup::<Texture>::create_bind_group_layout(device, None))
+            .push(&BindGroup::<PaintPigments>::create_bind_group_layout(device, None))
+            .create(device, None)
+    }
+
+    pub fn create_compute_pipeline_layout(device: &wgpu::Device) -> PipelineLayout{
+        PipelineLayoutBuilder::new()
+            .push(&BindGroup::<PaintUniforms>::create_bind_group_layout(device, None))
             .push(&BindGroup::<Texture>::create_bind_group_layout(device, None))
+            .push(&BindGroup::<PaintPigments>::create_bind_group_layout(device, None))
+            .push(&BindGroup::<PaintStorage>::create_bind_group_layout(device, None))
             .create(device, None)
     }
 }
 
+///
+/// How PaintSim::step is executed on the gpu.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepMode{
+    // fragment shaders rendering a full screen quad into the textures.
+    Render,
+    // compute shaders writing to storage textures.
+    Compute,
+}
+
+impl StepMode{
+    pub fn toggle(self) -> Self{
+        match self{
+            Self::Render => Self::Compute,
+            Self::Compute => Self::Render,
+        }
+    }
+}
+
+///
+/// How the velocity field is kept free of divergence.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Solver{
+    // single pass density invariance correction (Guay et al.) in the step shader.
+    DensityInvariant,
+    // pressure projection solving the Poisson equation with Jacobi iterations.
+    Jacobi{iterations: u32},
+    // pressure projection solving the Poisson equation with red black Gauss-Seidel iterations.
+    GaussSeidel{iterations: u32},
+    // pressure projection solving the Poisson equation with multigrid V-cycles, these always run
+    // as render passes.
+    Multigrid{cycles: u32},
+}
+
+impl Solver{
+    // iterations and V-cycles used by next().
+    pub const DEFAULT_ITERATIONS: u32 = 40;
+    pub const DEFAULT_CYCLES: u32 = 2;
+
+    pub fn is_projection(self) -> bool{
+        self != Self::DensityInvariant
+    }
+
+    ///
+    /// Cycles through the solvers, the projections use DEFAULT_ITERATIONS or DEFAULT_CYCLES.
+    ///
+    pub fn next(self) -> Self{
+        match self{
+            Self::DensityInvariant => Self::Jacobi{iterations: Self::DEFAULT_ITERATIONS},
+            Self::Jacobi{..} => Self::GaussSeidel{iterations: Self::DEFAULT_ITERATIONS},
+            Self::GaussSeidel{..} => Self::Multigrid{cycles: Self::DEFAULT_CYCLES},
+            Self::Multigrid{..} => Self::DensityInvariant,
+        }
+    }
+}
+
+impl std::str::FromStr for Solver{
+    type Err = anyhow::Error;
+
+    ///
+    /// Parses "density-invariant", "jacobi[:<iterations>]", "gauss-seidel[:<iterations>]" or
+    /// "multigrid[:<cycles>]".
+    ///
+    fn from_str(s: &str) -> Result<Self>{
+        let (name, count) = match s.split_once(':'){
+            Some((name, count)) => (name, Some(count.parse()?)),
+            None => (s, None),
+        };
+        match name{
+            "density-invariant" => Ok(Self::DensityInvariant),
+            "jacobi" => Ok(Self::Jacobi{iterations: count.unwrap_or(Self::DEFAULT_ITERATIONS)}),
+            "gauss-seidel" => Ok(Self::GaussSeidel{iterations: count.unwrap_or(Self::DEFAULT_ITERATIONS)}),
+            "multigrid" => Ok(Self::Multigrid{cycles: count.unwrap_or(Self::DEFAULT_CYCLES)}),
+            _ => Err(anyhow!("unknown solver {}", s)),
+        }
+    }
+}
+
+///
+/// Limits the result of a higher order advection scheme to the range of the field around the
+/// backtraced position to avoid over- and undershoots at sharp edges.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limiter{
+    None,
+    // clamp to the range.
+    Clamp,
+    // fall back to the semi-Lagrangian result outside of the range.
+    Revert,
+}
+
+///
+/// How the velocity and the floating pigment are advected.
+///
+/// MacCormack and BFECC correct the error of the semi-Lagrangian step which keeps brush detail
+/// sharp at the cost of four and sixteen additional backtraces per value.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Advection{
+    // first order backtrace.
+    SemiLagrangian,
+    // backtrace with the velocity at the midpoint.
+    Rk2,
+    MacCormack{limiter: Limiter},
+    // back and forth error compensation and correction.
+    Bfecc{limiter: Limiter},
+}
+
+impl Advection{
+    ///
+    /// The values of PaintParams::advection and PaintParams::limiter.
+    ///
+    pub fn to_params(self) -> (u32, u32){
+        let limiter = |limiter| match limiter{
+            Limiter::None => 0,
+            Limiter::Clamp => 1,
+            Limiter::Revert => 2,
+        };
+        match self{
+            Self::SemiLagrangian => (0, 0),
+            Self::Rk2 => (1, 0),
+            Self::MacCormack{limiter: l} => (2, limiter(l)),
+            Self::Bfecc{limiter: l} => (3, limiter(l)),
+        }
+    }
+
+    pub fn from_params(advection: u32, limiter: u32) -> Self{
+        let limiter = match limiter{
+            1 => Limiter::Clamp,
+            2 => Limiter::Revert,
+            _ => Limiter::None,
+        };
+        match advection{
+            1 => Self::Rk2,
+            2 => Self::MacCormack{limiter},
+            3 => Self::Bfecc{limiter},
+            _ => Self::SemiLagrangian,
+        }
+    }
+
+    ///
+    /// Cycles through the schemes, the corrected ones with a clamping limiter.
+    ///
+    pub fn next(self) -> Self{
+        match self{
+            Self::SemiLagrangian => Self::Rk2,
+            Self::Rk2 => Self::MacCormack{limiter: Limiter::Clamp},
+            Self::MacCormack{..} => Self::Bfecc{limiter: Limiter::Clamp},
+            Self::Bfecc{..} => Self::SemiLagrangian,
+        }
+    }
+}
+
+impl std::str::FromStr for Advection{
+    type Err = anyhow::Error;
+
+    ///
+    /// Parses "semi-lagrangian", "rk2", "maccormack[:<limiter>]" or "bfecc[:<limiter>]" with the
+    /// limiter "none", "clamp" (default) or "revert".
+    ///
+    fn from_str(s: &str) -> Result<Self>{
+        let (name, limiter) = s.split_once(':').unwrap_or((s, "clamp"));
+        let limiter = match limiter{
+            "none" => Limiter::None,
+            "clamp" => Limiter::Clamp,
+            "revert" => Limiter::Revert,
+            _ => return Err(anyhow!("unknown limiter {}", limiter)),
+        };
+        match name{
+            "semi-lagrangian" => Ok(Self::SemiLagrangian),
+            "rk2" => Ok(Self::Rk2),
+            "maccormack" => Ok(Self::MacCormack{limiter}),
+            "bfecc" => Ok(Self::Bfecc{limiter}),
+            _ => Err(anyhow!("unknown advection scheme {}", s)),
+        }
+    }
+}
+
+///
+/// Boundary condition of the velocity at the edges of the canvas.
+///
+/// Obstacles are always free-slip walls, a periodic canvas has no edges.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Boundary{
+    // water flows over the edge.
+    Open,
+    // walls that only stop the flow into them.
+    FreeSlip,
+    // walls that stop all flow next to them.
+    NoSlip,
+}
+
+impl Boundary{
+    ///
+    /// The value of PaintParams::boundary.
+    ///
+    pub fn to_param(self) -> u32{
+        match self{
+            Self::Open => 0,
+            Self::FreeSlip => 1,
+            Self::NoSlip => 2,
+        }
+    }
+
+    pub fn from_param(boundary: u32) -> Self{
+        match boundary{
+            1 => Self::FreeSlip,
+            2 => Self::NoSlip,
+            _ => Self::Open,
+        }
+    }
+
+    pub fn next(self) -> Self{
+        match self{
+            Self::Open => Self::FreeSlip,
+            Self::FreeSlip => Self::NoSlip,
+            Self::NoSlip => Self::Open,
+        }
+    }
+}
+
+impl std::str::FromStr for Boundary{
+    type Err = anyhow::Error;
+
+    ///
+    /// Parses "open", "free-slip" or "no-slip".
+    ///
+    fn from_str(s: &str) -> Result<Self>{
+        match s{
+            "open" => Ok(Self::Open),
+            "free-slip" => Ok(Self::FreeSlip),
+            "no-slip" => Ok(Self::NoSlip),
+            _ => Err(anyhow!("unknown boundary {}", s)),
+        }
+    }
+}
+
+///
+/// Size of the simulation grid relative to the source image.
+///
+/// The parameters of the simulation and the brush are measured in cells, a coarser grid is
+/// faster but spreads the water and pigment over a larger part of the painting.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GridSize{
+    // one cell per pixel of the source image.
+    Source,
+    // the size of the source image scaled by a factor, 0.5 simulates a quarter of the cells.
+    Scale(f32),
+    // a fixed number of cells, the source image is stretched to it.
+    Fixed([u32; 2]),
+}
+
+impl GridSize{
+    ///
+    /// Number of cells for a source image of the given size.
+    ///
+    pub fn size(&self, src_size: [u32; 2]) -> [u32; 2]{
+        match self{
+            Self::Source => src_size,
+            Self::Scale(scale) => [
+                ((src_size[0] as f32 * scale).round() as u32).max(1),
+                ((src_size[1] as f32 * scale).round() as u32).max(1),
+            ],
+            Self::Fixed(size) => *size,
+        }
+    }
+}
+
+impl std::str::FromStr for GridSize{
+    type Err = anyhow::Error;
+
+    ///
+    /// Parses "source", a scale factor such as "0.5" or a size such as "512x512".
+    ///
+    fn from_str(s: &str) -> Result<Self>{
+        if s == "source"{
+            return Ok(Self::Source);
+        }
+        if let Some((width, height)) = s.split_once('x'){
+            let size = [width.parse()?, height.parse()?];
+            if size[0] == 0 || size[1] == 0{
+                return Err(anyhow!("grid size {} has no cells", s));
+            }
+            return Ok(Self::Fixed(size));
+        }
+        let scale: f32 = s.parse()?;
+        if scale.is_nan() || scale <= 0.{
+            return Err(anyhow!("grid scale {} has to be positive", s));
+        }
+        Ok(Self::Scale(scale))
+    }
+}
+
+///
+/// Float fields of the simulation that can be exported to and imported from .npy and OpenEXR
+/// files.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SimField{
+    // velocity, water height and wet area mask.
+    Vpf,
+    // pigment deposited on the paper.
+    Color,
+    // floating pigment.
+    Float,
+}
+
+impl SimField{
+    ///
+    /// Names of the channels of the field, the pigment of layer i is named pigment<i>.r to
+    /// pigment<i>.a.
+    ///
+    pub fn channels(&self) -> Vec<String>{
+        match self{
+            Self::Vpf => ["u", "v", "height", "wet"].iter().map(|c| c.to_string()).collect(),
+            Self::Color | Self::Float => (0..PIGMENT_LAYERS)
+                .flat_map(|layer| ["r", "g", "b", "a"].iter().map(move |c| format!("pigment{}.{}", layer, c)))
+                .collect(),
+        }
+    }
+}
+
+impl std::str::FromStr for SimField{
+    type Err = anyhow::Error;
+
+    ///
+    /// Parses "vpf", "color" or "float".
+    ///
+    fn from_str(s: &str) -> Result<Self>{
+        match s{
+            "vpf" => Ok(Self::Vpf),
+            "color" => Ok(Self::Color),
+            "float" => Ok(Self::Float),
+            _ => Err(anyhow!("unknown field {}", s)),
+        }
+    }
+}
+
+///
+/// Passes of vf_pressure.glsl, selected by a macro when compiling the shader.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PressurePass{
+    Divergence = 0,
+    Jacobi,
+    GaussSeidel,
+    Project,
+}
+
+impl PressurePass{
+    const ALL: [Self; 4] = [Self::Divergence, Self::Jacobi, Self::GaussSeidel, Self::Project];
+
+    fn define(self) -> &'static str{
+        match self{
+            Self::Divergence => "PASS_DIVERGENCE",
+            Self::Jacobi => "PASS_JACOBI",
+            Self::GaussSeidel => "PASS_GAUSS_SEIDEL",
+            Self::Project => "PASS_PROJECT",
+        }
+    }
+}
+
+// Workgroup size of the compute shaders in x and y.
+const WORKGROUP_SIZE: u32 = 8;
+// Minimal size of the coarsest level of the multigrid solver.
+pub const MULTIGRID_MIN_SIZE: u32 = 4;
+
+///
+/// Conservation diagnostics measured on the GPU after a step.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StepStats{
+    // steps run when the stats were measured.
+    pub steps: u64,
+    // water height above hmin summed over all cells.
+    pub water: f32,
+    // deposited and floating pigment summed over all cells and pigments.
+    pub pigment: f32,
+    pub max_velocity: f32,
+    // fraction of the cells in the wet area.
+    pub wet_fraction: f32,
+}
+
+impl std::fmt::Display for StepStats{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result{
+        write!(f, "step: {} water: {} pigment: {} max velocity: {} wet: {}", self.steps, self.water, self.pigment, self.max_velocity, self.wet_fraction)
+    }
+}
+
+///
+/// Reductions of the fields StepStats are computed from.
+///
+struct StatsReductions{
+    // speed in x, water height in z and 1 for the cells in the wet area in w.
+    vpf: Reduction,
+    color: Reduction,
+    float: Reduction,
+    // steps run when the reductions were recorded.
+    steps: u64,
+}
+
 pub struct PaintSim{
     // texture storing the velocity, preasure and fluidity.
-    pub tex_vpf: BindGroup<Texture>,
-    tex_vpf_tmp: Texture,
+    pub tex_vpf: PingPong<Texture>,
+
+    // texture array storing the pigment deposited on the paper.
+    pub tex_color: PingPong<TextureArray>,
 
-    // texture storing the color of the base layer.
-    pub tex_color: BindGroup<Texture>,
-    tex_color_tmp: Texture,
+    // texture array storing the floating pigment.
+    pub tex_float: PingPong<TextureArray>,
 
-    // texture stiring the floating particulate.
-    pub tex_float: BindGroup<Texture>,
-    tex_float_tmp: Texture,
+    // the color and float layers bound together, indexed by the read index of tex_color and
+    // tex_float which are swapped together.
+    tex_pigments: Vec<BindGroup<PaintPigments>>,
+
+    // texture array storing the pigment of the dried glazes, PIGMENT_LAYERS layers per glaze
+    // starting with the bottom most glaze.
+    pub tex_glazes: BindGroup<TextureArray>,
+    glazes: u32,
 
     // texture storing the initial image.
     pub tex_src: BindGroup<Texture>,
 
+    paper: Paper,
+    obstacles: Obstacles,
+    boundary: Boundary,
+    // whether the textures wrap around the edges of the canvas.
+    periodic: bool,
+
+    // texture storing the saturation and capacity of the paper.
+    pub tex_sat: PingPong<Texture>,
+
+    // texture storing the pressure and the divergence of the velocity of the projection.
+    pub tex_pressure: PingPong<Texture>,
+    solver: Solver,
+    multigrid: Multigrid,
+    advection: Advection,
+
     pipeline: pipeline::RenderPipeline,
     pipeline_blurwh: pipeline::RenderPipeline,
     pipeline_blurwv: pipeline::RenderPipeline,
+    pipeline_capillary: pipeline::RenderPipeline,
+    pipeline_vorticity: pipeline::RenderPipeline,
+    pipeline_boundary: pipeline::RenderPipeline,
     pipeline_src_to_color: pipeline::RenderPipeline,
+    pipeline_glaze: pipeline::RenderPipeline,
+    // pigments the source image is painted with by prepare.
+    src_pigments: BindGroup<Uniform<SourcePigments>>,
+    // indexed by PressurePass.
+    pipelines_pressure: Vec<pipeline::RenderPipeline>,
 
-    ppl_comp: ComputePipeline,
+    step_mode: StepMode,
+    cpipeline: ComputePipeline,
+    cpipeline_capillary: ComputePipeline,
+    cpipeline_blurwv: ComputePipeline,
+    cpipeline_blurwh: ComputePipeline,
+    cpipeline_vorticity: ComputePipeline,
+    cpipeline_boundary: ComputePipeline,
+    cpipelines_pressure: Vec<ComputePipeline>,
+    // storage views of the write sides of the ping pong textures written by the compute step,
+    // indexed by the write indices (see step_compute).
+    storage: Vec<BindGroup<PaintStorage>>,
+    storage_capillary: Vec<BindGroup<(StorageTexture, StorageTexture)>>,
+    storage_vpf: Vec<BindGroup<StorageTexture>>,
+    storage_pressure: Vec<BindGroup<StorageTexture>>,
 
-    global_uniform: uniform::UniformBindGroup<GlobalShaderData>,
-    in_buffer: BindGroup<Buffer<i32>>,
-    out_buffer: BindGroup<Buffer<i32>>,
+    // global data, parameters, brush and pigment properties shared with the display and the
+    // texture storing the paper height, its gradient and the obstacles.
+    pub uniforms: BindGroup<PaintUniforms>,
+    pigments: PigmentRegistry,
     
     mesh: Mesh<Vert2>,
 
-    sc: usize,
+    // decides how many steps advance runs and counts the steps.
+    clock: Clock,
+
+    // measures the StepStats after every step if enabled.
+    stats: Option<StatsReductions>,
+
+    // size of the image the simulation was created from, tex_src is resampled to the grid.
+    src_size: [u32; 2],
 }
 
 impl PaintSim{
     pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, path: &str) -> Result<Self>{
+        Self::from_image(device, queue, &image::open(path)?, false, GridSize::Source)
+    }
+
+    ///
+    /// A simulation on a torus, water and pigment leaving the canvas on one side enter it on the
+    /// opposite side so the painting tiles seamlessly.
+    ///
+    pub fn new_periodic(device: &wgpu::Device, queue: &wgpu::Queue, path: &str) -> Result<Self>{
+        Self::from_image(device, queue, &image::open(path)?, true, GridSize::Source)
+    }
+
+    ///
+    /// Creates a simulation with a grid of the given size, the image is resampled to it with
+    /// vf_resample.glsl and every other texture has the size of the grid.
+    ///
+    pub fn from_image(device: &wgpu::Device, queue: &wgpu::Queue, img: &image::DynamicImage, periodic: bool, grid: GridSize) -> Result<Self>{
         let mesh = Mesh::new(device, &Vert2::QUAD_VERTS, &Vert2::QUAD_IDXS)?;
 
-        let tex_src = BindGroup::new(Texture::load_from_path(device, queue, path, None, wgpu::TextureFormat::Rgba8Unorm)?, device);
+        let src = BindGroup::new(Texture::from_image(device, queue, img, None, wgpu::TextureFormat::Rgba8Unorm)?, device);
+        let src_size = src.size;
+        let grid_size = grid.size(src_size);
+        let tex_src = if grid_size == src_size{
+            src
+        }
+        else{
+            let tex_src = BindGroup::new(Texture::new_black(grid_size, device, queue, Some("SourceGrid"), wgpu::TextureFormat::Rgba8Unorm)?, device);
+            let address_mode = if periodic {wgpu::AddressMode::Repeat} else {wgpu::AddressMode::ClampToEdge};
+            let resample = Resample::new(device, wgpu::TextureFormat::Rgba8Unorm, grid_size, address_mode)?;
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor{
+                label: Some("ResampleSourceEncoder"),
+            });
+            resample.resample(&mut encoder, &src, &tex_src.view);
+            queue.submit(std::iter::once(encoder.finish()));
+            tex_src
+        };
+
+        // the simulation textures wrap around the edges of a periodic canvas.
+        let address_mode = if periodic {wgpu::AddressMode::Repeat} else {wgpu::AddressMode::ClampToEdge};
+        let periodic_define = ("PERIODIC", if periodic {"1"} else {"0"});
+
+        let tex_vpf = PingPong::new(
+            Texture::new_black_with_address_mode(tex_src.size, device, queue, None, wgpu::TextureFormat::Rgba32Float, address_mode)?,
+            Texture::new_black_with_address_mode(tex_src.size, device, queue, None, wgpu::TextureFormat::Rgba32Float, address_mode)?,
+            device
+        );
 
-        let tex_vpf = BindGroup::new(Texture::new_black(tex_src.size, device, queue, None, wgpu::TextureFormat::Rgba32Float)?, device);
-        let tex_vpf_tmp = Texture::new_black(tex_src.size, device, queue, None, wgpu::TextureFormat::Rgba32Float)?;
+        let tex_color = PingPong::new(
+            TextureArray::new_black_with_address_mode(tex_src.size, PIGMENT_LAYERS, device, None, wgpu::TextureFormat::Rgba32Float, address_mode)?,
+            TextureArray::new_black_with_address_mode(tex_src.size, PIGMENT_LAYERS, device, None, wgpu::TextureFormat::Rgba32Float, address_mode)?,
+            device
+        );
 
-        let tex_color = BindGroup::new(Texture::new_black(tex_src.size, device, queue, None, wgpu::TextureFormat::Rgba32Float)?, device); 
-        let tex_color_tmp = Texture::new_black(tex_src.size, device, queue, None, wgpu::TextureFormat::Rgba32Float)?; 
+        let tex_float = PingPong::new(
+            TextureArray::new_black_with_address_mode(tex_src.size, PIGMENT_LAYERS, device, None, wgpu::TextureFormat::Rgba32Float, address_mode)?,
+            TextureArray::new_black_with_address_mode(tex_src.size, PIGMENT_LAYERS, device, None, wgpu::TextureFormat::Rgba32Float, address_mode)?,
+            device
+        );
 
-        let tex_float = BindGroup::new(Texture::new_black(tex_src.size, device, queue, None, wgpu::TextureFormat::Rgba32Float)?, device); 
-        let tex_float_tmp = Texture::new_black(tex_src.size, device, queue, None, wgpu::TextureFormat::Rgba32Float)?; 
+        let tex_pigments = (0..2).map(|i|{
+            BindGroup::new((tex_color.get(i).sampled(device), tex_float.get(i).sampled(device)), device)
+        }).collect();
 
-        let global_uniform = UniformBindGroup::<GlobalShaderData>::new(device, GlobalShaderData{
-            size: [tex_src.size[0] as f32, tex_src.size[1] as f32],
-            time: 0.0,
-            _pad0: 0.0,
+        let tex_glazes = BindGroup::new(TextureArray::new_black_with_address_mode(tex_src.size, MAX_GLAZES * PIGMENT_LAYERS, device, None, wgpu::TextureFormat::Rgba32Float, address_mode)?, device);
+
+        let paper = Paper::generate(tex_src.size, &PaperParams{
+            periodic,
+            ..Default::default()
         });
+        let obstacles = Obstacles::empty(tex_src.size);
+        let tex_paper = Texture::new_black_with_address_mode(tex_src.size, device, queue, None, wgpu::TextureFormat::Rgba32Float, address_mode)?;
+        tex_paper.write_bytes(queue, bytemuck::cast_slice(&paper.to_texels()));
 
-        let comp_shader = shader_with_shaderc(device, include_str!("shaders/comp_test01.glsl"), shaderc::ShaderKind::Compute, "main", None)?;
+        let tex_sat = PingPong::new(
+            Texture::new_black_with_address_mode(tex_src.size, device, queue, None, wgpu::TextureFormat::Rgba32Float, address_mode)?,
+            Texture::new_black_with_address_mode(tex_src.size, device, queue, None, wgpu::TextureFormat::Rgba32Float, address_mode)?,
+            device
+        );
 
-        let in_buffer = BindGroup::new(Buffer::new_storage(device, None, &[0, 1, 2, 3]), device);
-        let out_buffer = BindGroup::new(Buffer::new_storage(device, None, &[0, 1, 2, 3]), device);
+        let tex_pressure = PingPong::new(
+            Texture::new_black_with_address_mode(tex_src.size, device, queue, None, wgpu::TextureFormat::Rgba32Float, address_mode)?,
+            Texture::new_black_with_address_mode(tex_src.size, device, queue, None, wgpu::TextureFormat::Rgba32Float, address_mode)?,
+            device
+        );
 
-        let comp_layout = PipelineLayoutBuilder::new()
-            .push(&BindGroup::<Buffer<i32>>::create_bind_group_layout(device, None))
-            .push(&BindGroup::<Buffer<i32>>::create_bind_group_layout(device, None))
-            .create(device, None);
+        // lapl(p) = div
+        let multigrid = Multigrid::new(device, queue, tex_src.size, MULTIGRID_MIN_SIZE, 0., -1., address_mode)?;
 
-        let ppl_comp = pipeline::ComputePipelineBuilder::new(&comp_shader)
-            .set_layout(&comp_layout)
-            .build(device);
+        let pigments = PigmentRegistry::default();
+
+        let uniforms = BindGroup::new((
+            Uniform::new(device, GlobalShaderData{
+                size: [tex_src.size[0] as f32, tex_src.size[1] as f32],
+                time: 0.0,
+                _pad0: 0.0,
+            }),
+            Uniform::new(device, PaintParams::default()),
+            Uniform::new(device, Brush::zeroed()),
+            Uniform::new(device, pigments.to_data()),
+            tex_paper,
+        ), device);
 
         // Simulation Pipeline:
-        let vert_shader = shader_with_shaderc(device, include_str!("shaders/vf_paint04.glsl"), shaderc::ShaderKind::Vertex, "main", None)?;
-        let frag_shader = shader_with_shaderc(device, include_str!("shaders/vf_paint04.glsl"), shaderc::ShaderKind::Fragment, "main", None)?;
+        let pigment_defines = PigmentDefines::new();
+        let paint_defines = pigment_defines.with(&[periodic_define]);
+        let vert_shader = shader_with_shaderc_defines(device, include_str!("shaders/vf_paint04.glsl"), shaderc::ShaderKind::Vertex, "main", &paint_defines, None)?;
+        let frag_shader = shader_with_shaderc_defines(device, include_str!("shaders/vf_paint04.glsl"), shaderc::ShaderKind::Fragment, "main", &paint_defines, None)?;
 
         let vert_state = VertexStateBuilder::new(&vert_shader)
             .push_vert_layout(mesh.vert_buffer_layout())
             .build();
 
-        let frag_state = FragmentStateBuilder::new(&frag_shader)
-            .push_target_replace(wgpu::TextureFormat::Rgba32Float)
-            .push_target_replace(wgpu::TextureFormat::Rgba32Float)
-            .push_target_replace(wgpu::TextureFormat::Rgba32Float)
-            .build();
+        // vpf, color layers, float layers
+        let mut frag_state = FragmentStateBuilder::new(&frag_shader)
+            .push_target_replace(wgpu::TextureFormat::Rgba32Float);
+        for _ in 0..2 * PIGMENT_LAYERS{
+            frag_state = frag_state.push_target_replace(wgpu::TextureFormat::Rgba32Float);
+        }
+        let frag_state = frag_state.build();
         
         let pipeline_layout = PaintPipelineLayout::create_pipeline_layout(device);
         /*
         let pipeline_layout = PipelineLayoutBuilder::new()
-            .push(global_uniform.get_bind_group_layout())
+            .push(uniforms.get_bind_group_layout())
             .push(tex_vpf.get_bind_group_layout())
             .push(tex_color.get_bind_group_layout())
             .push(tex_float.get_bind_group_layout())
@@ -136,7 +1078,7 @@ impl PaintSim{
             .build();
 
         let pipeline_layout = PipelineLayoutBuilder::new()
-            .push(global_uniform.get_bind_group_layout())
+            .push(uniforms.get_bind_group_layout())
             .push(tex_vpf.get_bind_group_layout())
             .create(device, None);
 
@@ -157,7 +1099,7 @@ impl PaintSim{
             .build();
 
         let pipeline_layout = PipelineLayoutBuilder::new()
-            .push(global_uniform.get_bind_group_layout())
+            .push(uniforms.get_bind_group_layout())
             .push(tex_vpf.get_bind_group_layout())
             .create(device, None);
 
@@ -165,20 +1107,117 @@ impl PaintSim{
             .set_layout(&pipeline_layout)
             .build(device);
 
+        // Vorticity Pipeline:
+        let vert_shader = shader_with_shaderc(device, include_str!("shaders/vf_vorticity.glsl"), shaderc::ShaderKind::Vertex, "main", None)?;
+        let frag_shader = shader_with_shaderc(device, include_str!("shaders/vf_vorticity.glsl"), shaderc::ShaderKind::Fragment, "main", None)?;
 
-        // Initialisation Pipeline:
-        let vert_shader = shader_with_shaderc(device, include_str!("shaders/vf_src_to_color.glsl"), shaderc::ShaderKind::Vertex, "main", None)?;
-        let frag_shader = shader_with_shaderc(device, include_str!("shaders/vf_src_to_color.glsl"), shaderc::ShaderKind::Fragment, "main", None)?;
+        let vert_state = VertexStateBuilder::new(&vert_shader)
+            .push_vert_layout(mesh.vert_buffer_layout())
+            .build();
+
+        let frag_state = FragmentStateBuilder::new(&frag_shader)
+            .push_target_replace(wgpu::TextureFormat::Rgba32Float)
+            .build();
+
+        let pipeline_layout = PipelineLayoutBuilder::new()
+            .push(uniforms.get_bind_group_layout())
+            .push(tex_vpf.get_bind_group_layout())
+            .create(device, None);
+
+        let pipeline_vorticity = RenderPipelineBuilder::new(vert_state, frag_state)
+            .set_layout(&pipeline_layout)
+            .build(device);
+
+        // Boundary Pipeline:
+        let vert_shader = shader_with_shaderc_defines(device, include_str!("shaders/vf_boundary.glsl"), shaderc::ShaderKind::Vertex, "main", &[periodic_define], None)?;
+        let frag_shader = shader_with_shaderc_defines(device, include_str!("shaders/vf_boundary.glsl"), shaderc::ShaderKind::Fragment, "main", &[periodic_define], None)?;
+
+        let vert_state = VertexStateBuilder::new(&vert_shader)
+            .push_vert_layout(mesh.vert_buffer_layout())
+            .build();
+
+        let frag_state = FragmentStateBuilder::new(&frag_shader)
+            .push_target_replace(wgpu::TextureFormat::Rgba32Float)
+            .build();
+
+        let pipeline_layout = PipelineLayoutBuilder::new()
+            .push(uniforms.get_bind_group_layout())
+            .push(tex_vpf.get_bind_group_layout())
+            .create(device, None);
+
+        let pipeline_boundary = RenderPipelineBuilder::new(vert_state, frag_state)
+            .set_layout(&pipeline_layout)
+            .build(device);
+
+        // Capillary Pipeline:
+        let vert_shader = shader_with_shaderc(device, include_str!("shaders/vf_capillary.glsl"), shaderc::ShaderKind::Vertex, "main", None)?;
+        let frag_shader = shader_with_shaderc(device, include_str!("shaders/vf_capillary.glsl"), shaderc::ShaderKind::Fragment, "main", None)?;
 
         let vert_state = VertexStateBuilder::new(&vert_shader)
             .push_vert_layout(mesh.vert_buffer_layout())
             .build();
 
+        // vpf, saturation
         let frag_state = FragmentStateBuilder::new(&frag_shader)
+            .push_target_replace(wgpu::TextureFormat::Rgba32Float)
             .push_target_replace(wgpu::TextureFormat::Rgba32Float)
             .build();
+
+        let pipeline_layout = PipelineLayoutBuilder::new()
+            .push(uniforms.get_bind_group_layout())
+            .push(tex_vpf.get_bind_group_layout())
+            .push(tex_sat.get_bind_group_layout())
+            .create(device, None);
+
+        let pipeline_capillary = RenderPipelineBuilder::new(vert_state, frag_state)
+            .set_layout(&pipeline_layout)
+            .build(device);
+
+        // Pressure Pipelines:
+        let pipeline_layout = PipelineLayoutBuilder::new()
+            .push(uniforms.get_bind_group_layout())
+            .push(tex_vpf.get_bind_group_layout())
+            .push(tex_pressure.get_bind_group_layout())
+            .create(device, None);
+
+        let mut pipelines_pressure = Vec::new();
+        for pass in PressurePass::ALL{
+            let defines = [(pass.define(), "1"), periodic_define];
+            let vert_shader = shader_with_shaderc_defines(device, include_str!("shaders/vf_pressure.glsl"), shaderc::ShaderKind::Vertex, "main", &defines, None)?;
+            let frag_shader = shader_with_shaderc_defines(device, include_str!("shaders/vf_pressure.glsl"), shaderc::ShaderKind::Fragment, "main", &defines, None)?;
+
+            let vert_state = VertexStateBuilder::new(&vert_shader)
+                .push_vert_layout(mesh.vert_buffer_layout())
+                .build();
+
+            let frag_state = FragmentStateBuilder::new(&frag_shader)
+                .push_target_replace(wgpu::TextureFormat::Rgba32Float)
+                .build();
+
+            pipelines_pressure.push(RenderPipelineBuilder::new(vert_state, frag_state)
+                .set_layout(&pipeline_layout)
+                .build(device));
+        }
+
+        // Initialisation Pipeline:
+        let src_pigments = BindGroup::new(Uniform::new(device, SourcePigments::from_registry(&pigments)), device);
+
+        let vert_shader = shader_with_shaderc_defines(device, include_str!("shaders/vf_src_to_color.glsl"), shaderc::ShaderKind::Vertex, "main", &pigment_defines.with(&[]), None)?;
+        let frag_shader = shader_with_shaderc_defines(device, include_str!("shaders/vf_src_to_color.glsl"), shaderc::ShaderKind::Fragment, "main", &pigment_defines.with(&[]), None)?;
+
+        let vert_state = VertexStateBuilder::new(&vert_shader)
+            .push_vert_layout(mesh.vert_buffer_layout())
+            .build();
+
+        // one target per color layer.
+        let mut frag_state = FragmentStateBuilder::new(&frag_shader);
+        for _ in 0..PIGMENT_LAYERS{
+            frag_state = frag_state.push_target_replace(wgpu::TextureFormat::Rgba32Float);
+        }
+        let frag_state = frag_state.build();
         
         let pipeline_layout = PipelineLayoutBuilder::new()
+            .push(src_pigments.get_bind_group_layout())
             .push(tex_src.get_bind_group_layout())
             .create(device, None);
 
@@ -186,120 +1225,925 @@ impl PaintSim{
             .set_layout(&pipeline_layout)
             .build(device);
 
-        
+        // Glaze Pipeline:
+        let vert_shader = shader_with_shaderc_defines(device, include_str!("shaders/vf_glaze.glsl"), shaderc::ShaderKind::Vertex, "main", &pigment_defines.with(&[]), None)?;
+        let frag_shader = shader_with_shaderc_defines(device, include_str!("shaders/vf_glaze.glsl"), shaderc::ShaderKind::Fragment, "main", &pigment_defines.with(&[]), None)?;
+
+        let vert_state = VertexStateBuilder::new(&vert_shader)
+            .push_vert_layout(mesh.vert_buffer_layout())
+            .build();
+
+        // one target per layer of the glaze.
+        let mut frag_state = FragmentStateBuilder::new(&frag_shader);
+        for _ in 0..PIGMENT_LAYERS{
+            frag_state = frag_state.push_target_replace(wgpu::TextureFormat::Rgba32Float);
+        }
+        let frag_state = frag_state.build();
+
+        let pipeline_layout = PipelineLayoutBuilder::new()
+            .push(&BindGroup::<PaintPigments>::create_bind_group_layout(device, None))
+            .create(device, None);
+
+        let pipeline_glaze = RenderPipelineBuilder::new(vert_state, frag_state)
+            .set_layout(&pipeline_layout)
+            .build(device);
+
+
+        // Compute Pipelines:
+        // The pigment layers are always swapped together so they share an index.
+        let mut storage = Vec::new();
+        let mut storage_capillary = Vec::new();
+        let mut storage_vpf = Vec::new();
+        let mut storage_pressure = Vec::new();
+        for vpf in 0..2{
+            for i in 0..2{
+                storage.push(BindGroup::new((
+                    tex_vpf.get(vpf).storage()?,
+                    tex_color.get(i).storage()?,
+                    tex_float.get(i).storage()?,
+                ), device));
+                storage_capillary.push(BindGroup::new((
+                    tex_vpf.get(vpf).storage()?,
+                    tex_sat.get(i).storage()?,
+                ), device));
+            }
+            storage_vpf.push(BindGroup::new(tex_vpf.get(vpf).storage()?, device));
+        }
+        for i in 0..2{
+            storage_pressure.push(BindGroup::new(tex_pressure.get(i).storage()?, device));
+        }
+
+        let comp_shader = shader_with_shaderc_defines(device, include_str!("shaders/vf_paint04.glsl"), shaderc::ShaderKind::Compute, "main", &paint_defines, None)?;
+        let pipeline_layout = PaintPipelineLayout::create_compute_pipeline_layout(device);
+        let cpipeline = pipeline::ComputePipelineBuilder::new(&comp_shader)
+            .set_layout(&pipeline_layout)
+            .build(device);
+
+        let comp_shader = shader_with_shaderc(device, include_str!("shaders/vf_capillary.glsl"), shaderc::ShaderKind::Compute, "main", None)?;
+        let pipeline_layout = PipelineLayoutBuilder::new()
+            .push(uniforms.get_bind_group_layout())
+            .push(tex_vpf.get_bind_group_layout())
+            .push(tex_sat.get_bind_group_layout())
+            .push(&BindGroup::<(StorageTexture, StorageTexture)>::create_bind_group_layout(device, None))
+            .create(device, None);
+        let cpipeline_capillary = pipeline::ComputePipelineBuilder::new(&comp_shader)
+            .set_layout(&pipeline_layout)
+            .build(device);
+
+        let pipeline_layout = PipelineLayoutBuilder::new()
+            .push(uniforms.get_bind_group_layout())
+            .push(tex_vpf.get_bind_group_layout())
+            .push(&BindGroup::<StorageTexture>::create_bind_group_layout(device, None))
+            .create(device, None);
+
+        let comp_shader = shader_with_shaderc(device, include_str!("shaders/vf_blurwv.glsl"), shaderc::ShaderKind::Compute, "main", None)?;
+        let cpipeline_blurwv = pipeline::ComputePipelineBuilder::new(&comp_shader)
+            .set_layout(&pipeline_layout)
+            .build(device);
+
+        let comp_shader = shader_with_shaderc(device, include_str!("shaders/vf_blurwh.glsl"), shaderc::ShaderKind::Compute, "main", None)?;
+        let cpipeline_blurwh = pipeline::ComputePipelineBuilder::new(&comp_shader)
+            .set_layout(&pipeline_layout)
+            .build(device);
+
+        let comp_shader = shader_with_shaderc(device, include_str!("shaders/vf_vorticity.glsl"), shaderc::ShaderKind::Compute, "main", None)?;
+        let cpipeline_vorticity = pipeline::ComputePipelineBuilder::new(&comp_shader)
+            .set_layout(&pipeline_layout)
+            .build(device);
+
+        let pipeline_layout = PipelineLayoutBuilder::new()
+            .push(uniforms.get_bind_group_layout())
+            .push(tex_vpf.get_bind_group_layout())
+            .push(&BindGroup::<StorageTexture>::create_bind_group_layout(device, None))
+            .create(device, None);
+
+        let comp_shader = shader_with_shaderc_defines(device, include_str!("shaders/vf_boundary.glsl"), shaderc::ShaderKind::Compute, "main", &[periodic_define], None)?;
+        let cpipeline_boundary = pipeline::ComputePipelineBuilder::new(&comp_shader)
+            .set_layout(&pipeline_layout)
+            .build(device);
+
+        let pipeline_layout = PipelineLayoutBuilder::new()
+            .push(uniforms.get_bind_group_layout())
+            .push(tex_vpf.get_bind_group_layout())
+            .push(tex_pressure.get_bind_group_layout())
+            .push(&BindGroup::<StorageTexture>::create_bind_group_layout(device, None))
+            .create(device, None);
+
+        let mut cpipelines_pressure = Vec::new();
+        for pass in PressurePass::ALL{
+            let comp_shader = shader_with_shaderc_defines(device, include_str!("shaders/vf_pressure.glsl"), shaderc::ShaderKind::Compute, "main", &[(pass.define(), "1"), periodic_define], None)?;
+            cpipelines_pressure.push(pipeline::ComputePipelineBuilder::new(&comp_shader)
+                .set_layout(&pipeline_layout)
+                .build(device));
+        }
 
         Ok(Self{
             mesh,
             tex_src,
             tex_vpf,
-            tex_vpf_tmp,
             tex_color,
-            tex_color_tmp,
             tex_float,
-            tex_float_tmp,
-            global_uniform,
+            tex_pigments,
+            tex_glazes,
+            glazes: 0,
+            paper,
+            obstacles,
+            boundary: Boundary::Open,
+            periodic,
+            tex_sat,
+            tex_pressure,
+            solver: Solver::DensityInvariant,
+            multigrid,
+            advection: Advection::SemiLagrangian,
+            uniforms,
+            pigments,
             pipeline,
             pipeline_blurwh,
             pipeline_blurwv,
+            pipeline_capillary,
+            pipeline_vorticity,
+            pipeline_boundary,
             pipeline_src_to_color,
-            ppl_comp,
-            in_buffer,
-            out_buffer,
-            sc: 0,
+            pipeline_glaze,
+            src_pigments,
+            pipelines_pressure,
+            step_mode: StepMode::Render,
+            cpipeline,
+            cpipeline_capillary,
+            cpipeline_blurwv,
+            cpipeline_blurwh,
+            cpipeline_vorticity,
+            cpipeline_boundary,
+            cpipelines_pressure,
+            storage,
+            storage_capillary,
+            storage_vpf,
+            storage_pressure,
+            clock: Clock::default(),
+            stats: None,
+            src_size,
         })
     }
 
-    pub fn prepare(&mut self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder){
+    pub fn step_mode(&self) -> StepMode{
+        self.step_mode
+    }
+
+    pub fn set_step_mode(&mut self, step_mode: StepMode){
+        self.step_mode = step_mode;
+    }
+
+    pub fn params(&self) -> &PaintParams{
+        self.uniforms.1.get_content()
+    }
+
+    ///
+    /// Replace the parameters, the projection flag, the advection scheme and the boundary are
+    /// kept in sync with the solver, advection and boundary of the PaintSim.
+    ///
+    pub fn set_params(&mut self, queue: &mut wgpu::Queue, params: PaintParams){
+        let (advection, limiter) = self.advection.to_params();
+        *self.uniforms.1.borrow_ref(queue) = PaintParams{
+            projection: self.solver.is_projection() as u32,
+            advection,
+            limiter,
+            boundary: self.boundary.to_param(),
+            ..params
+        };
+    }
+
+    pub fn solver(&self) -> Solver{
+        self.solver
+    }
+
+    pub fn set_solver(&mut self, queue: &mut wgpu::Queue, solver: Solver){
+        self.solver = solver;
+        self.uniforms.1.borrow_ref(queue).projection = solver.is_projection() as u32;
+    }
+
+    pub fn advection(&self) -> Advection{
+        self.advection
+    }
+
+    pub fn set_advection(&mut self, queue: &mut wgpu::Queue, advection: Advection){
+        self.advection = advection;
+        let (advection, limiter) = advection.to_params();
+        let mut params = self.uniforms.1.borrow_ref(queue);
+        params.advection = advection;
+        params.limiter = limiter;
+    }
+
+    pub fn pigments(&self) -> &PigmentRegistry{
+        &self.pigments
+    }
+
+    ///
+    /// Writes the simulation textures, parameters, pigments, step count, paper, obstacles and
+    /// source image to a snapshot file, the brush is not part of it.
+    ///
+    pub fn save_state(&self, device: &wgpu::Device, queue: &wgpu::Queue, path: &str) -> Result<()>{
+        Snapshot{
+            size: self.tex_src.size,
+            src_size: self.src_size,
+            periodic: self.periodic,
+            steps: self.clock.steps(),
+            solver: self.solver,
+            params: *self.params(),
+            pigments: self.pigments.clone(),
+            glazes: self.glazes,
+            src: self.tex_src.read_blocking(device, queue),
+            paper: self.paper.height.clone(),
+            paper_periodic: self.paper.periodic,
+            obstacles: self.obstacles.solid.clone(),
+            tex_vpf: self.tex_vpf.read_blocking(device, queue),
+            tex_sat: self.tex_sat.read_blocking(device, queue),
+            tex_pressure: self.tex_pressure.read_blocking(device, queue),
+            tex_color: self.tex_color.read_blocking(device, queue),
+            tex_float: self.tex_float.read_blocking(device, queue),
+            tex_glazes: self.tex_glazes.read_blocking(device, queue),
+        }.write_to_path(path)
+    }
+
+    ///
+    /// Restores a snapshot written by save_state, it has to have the grid size, source size and
+    /// periodicity of the simulation. prepare must not be called afterwards as it would overwrite the pigment.
+    ///
+    pub fn load_state(&mut self, queue: &mut wgpu::Queue, path: &str) -> Result<()>{
+        self.restore(queue, &Snapshot::read_from_path(path)?)
+    }
+
+    ///
+    /// Creates a simulation from a snapshot written by save_state.
+    ///
+    pub fn from_state(device: &wgpu::Device, queue: &mut wgpu::Queue, path: &str) -> Result<Self>{
+        let snapshot = Snapshot::read_from_path(path)?;
+        let img = image::RgbaImage::from_raw(snapshot.size[0], snapshot.size[1], snapshot.src.clone())
+            .ok_or(anyhow!("source image in snapshot {} has the wrong size", path))?;
+        // The texels are stored in texture order which from_image flips.
+        let img = image::DynamicImage::ImageRgba8(img).flipv();
+
+        // src is already resampled to the grid, only the size of the output is the one of the
+        // original source image.
+        let mut paintsim = Self::from_image(device, queue, &img, snapshot.periodic, GridSize::Fixed(snapshot.size))?;
+        paintsim.src_size = snapshot.src_size;
+        paintsim.restore(queue, &snapshot)?;
+        Ok(paintsim)
+    }
+
+    fn restore(&mut self, queue: &mut wgpu::Queue, snapshot: &Snapshot) -> Result<()>{
+        let size = self.tex_src.size;
+        if snapshot.size != size || snapshot.src_size != self.src_size || snapshot.periodic != self.periodic{
+            return Err(anyhow!("snapshot of size {:?} from a source of size {:?} (periodic: {}) does not match the simulation of size {:?} from a source of size {:?} (periodic: {})", snapshot.size, snapshot.src_size, snapshot.periodic, size, self.src_size, self.periodic));
+        }
+        if snapshot.glazes > MAX_GLAZES{
+            return Err(anyhow!("snapshot has {} glazes, at most {} are supported", snapshot.glazes, MAX_GLAZES));
+        }
+
+        let cells = (size[0] * size[1]) as usize;
+        let texel = std::mem::size_of::<[f32; 4]>();
+        for (name, len, expected) in [
+            ("src", snapshot.src.len(), cells * 4),
+            ("paper", snapshot.paper.len(), cells),
+            ("obstacles", snapshot.obstacles.len(), cells),
+            ("tex_vpf", snapshot.tex_vpf.len(), cells * texel),
+            ("tex_sat", snapshot.tex_sat.len(), cells * texel),
+            ("tex_pressure", snapshot.tex_pressure.len(), cells * texel),
+            ("tex_color", snapshot.tex_color.len(), cells * texel * self.tex_color.layers as usize),
+            ("tex_float", snapshot.tex_float.len(), cells * texel * self.tex_float.layers as usize),
+            ("tex_glazes", snapshot.tex_glazes.len(), cells * texel * self.tex_glazes.layers as usize),
+        ]{
+            if len != expected{
+                return Err(anyhow!("{} in snapshot has {} elements, expected {}", name, len, expected));
+            }
+        }
+
+        self.tex_src.write_bytes(queue, &snapshot.src);
+        self.tex_vpf.write_bytes(queue, &snapshot.tex_vpf);
+        self.tex_sat.write_bytes(queue, &snapshot.tex_sat);
+        self.tex_pressure.write_bytes(queue, &snapshot.tex_pressure);
+        self.tex_color.write_bytes(queue, &snapshot.tex_color);
+        self.tex_float.write_bytes(queue, &snapshot.tex_float);
+        self.tex_glazes.write_bytes(queue, &snapshot.tex_glazes);
+        self.glazes = snapshot.glazes;
+
+        self.paper = Paper{
+            size,
+            height: snapshot.paper.clone(),
+            periodic: snapshot.paper_periodic,
+        };
+        self.obstacles = Obstacles{
+            size,
+            solid: snapshot.obstacles.clone(),
+        };
+        self.write_paper(queue, [0, 0], size);
+
+        let params = snapshot.params;
+        self.set_solver(queue, snapshot.solver);
+        self.set_advection(queue, Advection::from_params(params.advection, params.limiter));
+        self.set_boundary(queue, Boundary::from_param(params.boundary));
+        self.set_params(queue, params);
+        self.set_pigments(queue, snapshot.pigments.clone());
+        self.clock.set_steps(snapshot.steps);
+        Ok(())
+    }
+
+    ///
+    /// Reads back the current state of a float field.
+    ///
+    pub fn read_field(&self, device: &wgpu::Device, queue: &wgpu::Queue, field: SimField) -> Result<FloatImage>{
+        match field{
+            SimField::Vpf => FloatImage::from_texture(&self.tex_vpf, device, queue, field.channels()),
+            SimField::Color => FloatImage::from_texture_array(&self.tex_color, device, queue, field.channels()),
+            SimField::Float => FloatImage::from_texture_array(&self.tex_float, device, queue, field.channels()),
+        }
+    }
+
+    ///
+    /// Writes a float field to a .npy or .exr file.
+    ///
+    pub fn export_field(&self, device: &wgpu::Device, queue: &wgpu::Queue, field: SimField, path: &str) -> Result<()>{
+        self.read_field(device, queue, field)?.write_to_path(path)
+    }
+
+    ///
+    /// Replaces a float field with the contents of a .npy or .exr file, for example as initial
+    /// condition after prepare.
+    ///
+    pub fn import_field(&mut self, queue: &wgpu::Queue, field: SimField, path: &str) -> Result<()>{
+        let img = FloatImage::read_from_path(path, &field.channels())?;
+        match field{
+            SimField::Vpf => img.write_to_texture(&self.tex_vpf, queue),
+            SimField::Color => img.write_to_texture_array(&self.tex_color, queue),
+            SimField::Float => img.write_to_texture_array(&self.tex_float, queue),
+        }
+    }
+
+    pub fn set_pigments(&mut self, queue: &mut wgpu::Queue, pigments: PigmentRegistry){
+        *self.uniforms.3.borrow_ref(queue) = pigments.to_data();
+        *self.src_pigments.borrow_ref(queue) = SourcePigments::from_registry(&pigments);
+        self.pigments = pigments;
+    }
+
+    ///
+    /// Number of dried glazes.
+    ///
+    pub fn glazes(&self) -> u32{
+        self.glazes
+    }
+
+    ///
+    /// Dries the current layer into a new glaze on top of the glaze stack and starts a fresh
+    /// layer on dry paper.
+    ///
+    /// The floating pigment settles and is added to the deposited pigment of the glaze, the
+    /// water and the saturation of the paper are reset.
+    ///
+    pub fn glaze(&mut self, encoder: &mut wgpu::CommandEncoder) -> Result<()>{
+        if self.glazes >= MAX_GLAZES{
+            return Err(anyhow!("the glaze stack is full ({} glazes)", MAX_GLAZES));
+        }
+
+        // Dry the deposited and floating pigment into the glaze.
         {
-            let mut render_pass = RenderPassBuilder::new()
-                .push_color_attachment(self.tex_color.view.color_attachment_clear())
-                .begin(encoder, None);
+            let first = (self.glazes * PIGMENT_LAYERS) as usize;
+            let mut render_pass = RenderPassBuilder::new();
+            for view in &self.tex_glazes.layer_views[first..first + PIGMENT_LAYERS as usize]{
+                render_pass = render_pass.push_color_attachment(view.color_attachment_clear());
+            }
+            let mut render_pass = render_pass.begin(encoder, None);
+
+            let mut render_pass_pipeline = render_pass.set_pipeline(&self.pipeline_glaze);
+
+            render_pass_pipeline.set_bind_group(0, self.tex_pigments().get_bind_group(), &[]);
+
+            self.mesh.draw(&mut render_pass_pipeline);
+        }
+        self.glazes += 1;
+
+        // Clear the pigment and the water.
+        let hmin = self.params().hmin as f64;
+        let mut render_pass_builder = RenderPassBuilder::new()
+            .push_color_attachment(self.tex_vpf.view.color_attachment_clear_with(wgpu::Color{r: 0., g: 0., b: hmin, a: 0.}))
+            .push_color_attachment(self.tex_sat.view.color_attachment_clear());
+        for layer_view in self.tex_color.layer_views.iter().chain(self.tex_float.layer_views.iter()){
+            render_pass_builder = render_pass_builder.push_color_attachment(layer_view.color_attachment_clear());
+        }
+        render_pass_builder.begin(encoder, None);
+
+        Ok(())
+    }
+
+    pub fn paper(&self) -> &Paper{
+        &self.paper
+    }
+
+    ///
+    /// Texture storing the paper height, its gradient and the obstacles, bound with the uniforms.
+    ///
+    pub fn tex_paper(&self) -> &Texture{
+        &self.uniforms.4
+    }
+
+    ///
+    /// Bind group of the current color and float layers.
+    ///
+    pub fn tex_pigments(&self) -> &BindGroup<PaintPigments>{
+        debug_assert_eq!(self.tex_color.read_index(), self.tex_float.read_index());
+        &self.tex_pigments[self.tex_color.read_index()]
+    }
+
+    ///
+    /// Replace the paper, it has to have the size of the simulation.
+    ///
+    pub fn set_paper(&mut self, queue: &wgpu::Queue, paper: Paper) -> Result<()>{
+        if paper.size != self.tex_paper().size{
+            return Err(anyhow!("paper of size {:?} does not match the simulation size {:?}", paper.size, self.tex_paper().size));
+        }
+        self.paper = paper;
+        self.write_paper(queue, [0, 0], self.paper.size);
+        Ok(())
+    }
+
+    pub fn obstacles(&self) -> &Obstacles{
+        &self.obstacles
+    }
+
+    ///
+    /// Replace the obstacles, they have to have the size of the simulation.
+    ///
+    pub fn set_obstacles(&mut self, queue: &wgpu::Queue, obstacles: Obstacles) -> Result<()>{
+        if obstacles.size != self.tex_paper().size{
+            return Err(anyhow!("obstacles of size {:?} do not match the simulation size {:?}", obstacles.size, self.tex_paper().size));
+        }
+        self.obstacles = obstacles;
+        self.write_paper(queue, [0, 0], self.obstacles.size);
+        Ok(())
+    }
+
+    ///
+    /// Paints obstacles into or erases them from the cells within radius of pos.
+    ///
+    pub fn paint_obstacles(&mut self, queue: &wgpu::Queue, pos: [f32; 2], radius: f32, solid: bool){
+        if let Some((origin, size)) = self.obstacles.paint(pos, radius, solid){
+            self.write_paper(queue, origin, size);
+        }
+    }
+
+    // uploads the paper and obstacles within the rectangle at origin of the given size.
+    fn write_paper(&self, queue: &wgpu::Queue, origin: [u32; 2], size: [u32; 2]){
+        let mut texels = Vec::with_capacity((size[0] * size[1]) as usize);
+        for y in origin[1]..origin[1] + size[1]{
+            for x in origin[0]..origin[0] + size[0]{
+                let [h, dx, dy, _] = self.paper.texel(x, y);
+                texels.push([h, dx, dy, if self.obstacles.is_solid(x, y) {1.} else {0.}]);
+            }
+        }
+        self.tex_paper().write_region(queue, origin, size, bytemuck::cast_slice(&texels));
+    }
+
+    pub fn periodic(&self) -> bool{
+        self.periodic
+    }
+
+    pub fn boundary(&self) -> Boundary{
+        self.boundary
+    }
+
+    pub fn set_boundary(&mut self, queue: &mut wgpu::Queue, boundary: Boundary){
+        self.boundary = boundary;
+        self.uniforms.1.borrow_ref(queue).boundary = boundary.to_param();
+    }
+
+    pub fn set_brush(&mut self, queue: &mut wgpu::Queue, brush: Option<Brush>){
+        *self.uniforms.2.borrow_ref(queue) = brush.unwrap_or(Brush::zeroed());
+    }
+
+    ///
+    /// Paints the source image onto clean paper as deposited pigment, see SourcePigments.
+    ///
+    pub fn prepare(&mut self, encoder: &mut wgpu::CommandEncoder){
+        {
+            let mut render_pass = RenderPassBuilder::new();
+            for view in &self.tex_color.layer_views{
+                render_pass = render_pass.push_color_attachment(view.color_attachment_clear());
+            }
+            let mut render_pass = render_pass.begin(encoder, None);
 
             let mut render_pass_pipeline = render_pass.set_pipeline(&self.pipeline_src_to_color);
 
-            render_pass_pipeline.set_bind_group(0, self.tex_src.get_bind_group(), &[]);
+            render_pass_pipeline.set_bind_group(0, self.src_pigments.get_bind_group(), &[]);
+            render_pass_pipeline.set_bind_group(1, self.tex_src.get_bind_group(), &[]);
             
             self.mesh.draw(&mut render_pass_pipeline);
         }
     }
 
-    pub fn step(&mut self, queue: &mut wgpu::Queue, encoder: &mut wgpu::CommandEncoder, device: &wgpu::Device){
-        self.global_uniform.borrow_ref(queue).time = self.sc as f32 /60.;
+    pub fn step(&mut self, queue: &mut wgpu::Queue, encoder: &mut wgpu::CommandEncoder){
+        self.uniforms.0.borrow_ref(queue).time = self.clock.time() as f32;
 
-        // test compute_shader
-        {
-            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor{
-                label: None,
+        match self.step_mode{
+            StepMode::Render => self.step_render(encoder),
+            StepMode::Compute => self.step_compute(encoder),
+        }
+
+        self.clock.tick();
+
+        if let Some(stats) = &mut self.stats{
+            stats.vpf.reduce(encoder, self.tex_vpf.read());
+            stats.color.reduce(encoder, self.tex_color.read());
+            stats.float.reduce(encoder, self.tex_float.read());
+            stats.steps = self.clock.steps();
+        }
+    }
+
+    ///
+    /// Enables or disables measuring the StepStats after every step.
+    ///
+    pub fn set_stats(&mut self, device: &wgpu::Device, enabled: bool) -> Result<()>{
+        if !enabled{
+            self.stats = None;
+        }
+        else if self.stats.is_none(){
+            let size = self.tex_vpf.size;
+            self.stats = Some(StatsReductions{
+                vpf: Reduction::new_texture(device, size, ReduceInput::Vpf)?,
+                color: Reduction::new_texture_array(device, size, self.tex_color.layers, ReduceInput::Texels)?,
+                float: Reduction::new_texture_array(device, size, self.tex_float.layers, ReduceInput::Texels)?,
+                steps: 0,
             });
+        }
+        Ok(())
+    }
 
-            cpass.set_pipeline(&self.ppl_comp.pipeline);
-            cpass.set_bind_group(0, self.in_buffer.get_bind_group(), &[]);
-            cpass.set_bind_group(1, self.out_buffer.get_bind_group(), &[]);
-            cpass.dispatch(self.in_buffer.len() as u32, 1, 1);
-            /*
-            let mapped_memory = self.out_buffer.content.buffer.slice(..);
-            let mapped_range = mapped_memory.get_mapped_range();
-            let res = mapped_range.first().unwrap();
-            println!("{:?}", res);
+    pub fn stats_enabled(&self) -> bool{
+        self.stats.is_some()
+    }
+
+    ///
+    /// Reads back the StepStats of the last step, the encoder it was recorded in has to be
+    /// submitted. Returns None if the stats are disabled or no step has run since enabling them.
+    ///
+    pub fn read_stats(&self, device: &wgpu::Device) -> Option<StepStats>{
+        let stats = self.stats.as_ref().filter(|stats| stats.steps > 0)?;
+        let size = self.tex_vpf.size;
+        let cells = (size[0] * size[1]) as f32;
+
+        let vpf = stats.vpf.read_blocking(device)[0];
+        let pigment = stats.color.read_blocking(device).iter()
+            .chain(stats.float.read_blocking(device).iter())
+            .map(|layer| layer.sum.iter().sum::<f32>())
+            .sum();
+
+        Some(StepStats{
+            steps: stats.steps,
+            water: vpf.sum[2] - self.params().hmin * cells,
+            pigment,
+            max_velocity: vpf.max[0],
+            wet_fraction: vpf.mean[3],
+        })
+    }
 
-            self.out_buffer.content.buffer.unmap();
-            */
+    ///
+    /// Runs the steps the clock schedules for the real time elapsed since the last call and
+    /// returns their number.
+    ///
+    pub fn advance(&mut self, queue: &mut wgpu::Queue, encoder: &mut wgpu::CommandEncoder, elapsed: std::time::Duration) -> u32{
+        let steps = self.clock.advance(elapsed);
+        for _ in 0..steps{
+            self.step(queue, encoder);
         }
-        println!("{:?}", self.out_buffer.slice(..).map_blocking(device).as_ref());
+        steps
+    }
+
+    ///
+    /// Size of the image the simulation was created from, outputs are upscaled to it. A
+    /// simulation restored from a snapshot only knows the size of its grid.
+    ///
+    pub fn source_size(&self) -> [u32; 2]{
+        self.src_size
+    }
+
+    ///
+    /// Number of cells of the simulation in x and y.
+    ///
+    pub fn grid_size(&self) -> [u32; 2]{
+        self.tex_src.size
+    }
+
+    pub fn clock(&self) -> &Clock{
+        &self.clock
+    }
+
+    pub fn clock_mut(&mut self) -> &mut Clock{
+        &mut self.clock
+    }
 
+    ///
+    /// Runs the passes of the step as fragment shaders, every pass writes the write side of the
+    /// ping pong textures and swaps them afterwards.
+    ///
+    fn step_render(&mut self, encoder: &mut wgpu::CommandEncoder){
         // Simulation step:
         {
             let mut render_pass = RenderPassBuilder::new()
-                .push_color_attachment(self.tex_vpf_tmp.view.color_attachment_clear())
-                .push_color_attachment(self.tex_color_tmp.view.color_attachment_clear())
-                .push_color_attachment(self.tex_float_tmp.view.color_attachment_clear())
-                .begin(encoder, None);
+                .push_color_attachment(self.tex_vpf.write().view.color_attachment_clear());
+            for view in self.tex_color.write().layer_views.iter().chain(self.tex_float.write().layer_views.iter()){
+                render_pass = render_pass.push_color_attachment(view.color_attachment_clear());
+            }
+            let mut render_pass = render_pass.begin(encoder, None);
 
             let mut render_pass_pipeline = render_pass.set_pipeline(&self.pipeline);
 
-            render_pass_pipeline.set_bind_group(PaintPipelineLayout::global_uniform as u32, self.global_uniform.get_bind_group(), &[]);
+            render_pass_pipeline.set_bind_group(PaintPipelineLayout::uniforms as u32, self.uniforms.get_bind_group(), &[]);
             render_pass_pipeline.set_bind_group(PaintPipelineLayout::tex_vpf as u32, self.tex_vpf.get_bind_group(), &[]);
-            render_pass_pipeline.set_bind_group(PaintPipelineLayout::tex_color as u32, self.tex_color.get_bind_group(), &[]);
-            render_pass_pipeline.set_bind_group(PaintPipelineLayout::tex_float as u32, self.tex_float.get_bind_group(), &[]);
+            render_pass_pipeline.set_bind_group(PaintPipelineLayout::tex_pigments as u32, self.tex_pigments().get_bind_group(), &[]);
 
             self.mesh.draw(&mut render_pass_pipeline);
         }
+        self.tex_vpf.swap();
+        self.tex_color.swap();
+        self.tex_float.swap();
+        // Vorticity confinement
+        if self.params().vorticity != 0.{
+            {
+                let mut render_pass = RenderPassBuilder::new()
+                    .push_color_attachment(self.tex_vpf.write().view.color_attachment_clear())
+                    .begin(encoder, None);
+
+                let mut render_pass_pipeline = render_pass.set_pipeline(&self.pipeline_vorticity);
+
+                render_pass_pipeline.set_bind_group(0, self.uniforms.get_bind_group(), &[]);
+                render_pass_pipeline.set_bind_group(1, self.tex_vpf.get_bind_group(), &[]);
+
+                self.mesh.draw(&mut render_pass_pipeline);
+            }
+            self.tex_vpf.swap();
+        }
+        // Pressure projection
+        self.project(encoder);
+        // Boundary conditions
+        if self.params().vorticity != 0. || self.solver.is_projection(){
+            {
+                let mut render_pass = RenderPassBuilder::new()
+                    .push_color_attachment(self.tex_vpf.write().view.color_attachment_clear())
+                    .begin(encoder, None);
+
+                let mut render_pass_pipeline = render_pass.set_pipeline(&self.pipeline_boundary);
+
+                render_pass_pipeline.set_bind_group(0, self.uniforms.get_bind_group(), &[]);
+                render_pass_pipeline.set_bind_group(1, self.tex_vpf.get_bind_group(), &[]);
+
+                self.mesh.draw(&mut render_pass_pipeline);
+            }
+            self.tex_vpf.swap();
+        }
+        // Capillary flow
+        {
+            let mut render_pass = RenderPassBuilder::new()
+                .push_color_attachment(self.tex_vpf.write().view.color_attachment_clear())
+                .push_color_attachment(self.tex_sat.write().view.color_attachment_clear())
+                .begin(encoder, None);
+
+            let mut render_pass_pipeline = render_pass.set_pipeline(&self.pipeline_capillary);
+
+            render_pass_pipeline.set_bind_group(0, self.uniforms.get_bind_group(), &[]);
+            render_pass_pipeline.set_bind_group(1, self.tex_vpf.get_bind_group(), &[]);
+            render_pass_pipeline.set_bind_group(2, self.tex_sat.get_bind_group(), &[]);
+
+            self.mesh.draw(&mut render_pass_pipeline);
+        }
+        self.tex_vpf.swap();
+        self.tex_sat.swap();
         // Blur Vertically
         {
-            self.tex_vpf_tmp.copy_all_to(&mut self.tex_vpf, encoder);
             let mut render_pass = RenderPassBuilder::new()
-                .push_color_attachment(self.tex_vpf_tmp.view.color_attachment_clear())
+                .push_color_attachment(self.tex_vpf.write().view.color_attachment_clear())
                 .begin(encoder, None);
 
             let mut render_pass_pipeline = render_pass.set_pipeline(&self.pipeline_blurwv);
 
-            render_pass_pipeline.set_bind_group(0, self.global_uniform.get_bind_group(), &[]);
+            render_pass_pipeline.set_bind_group(0, self.uniforms.get_bind_group(), &[]);
             render_pass_pipeline.set_bind_group(1, self.tex_vpf.get_bind_group(), &[]);
 
             self.mesh.draw(&mut render_pass_pipeline);
         }
+        self.tex_vpf.swap();
         // Blur Horizontally
         {
-            self.tex_vpf_tmp.copy_all_to(&mut self.tex_vpf, encoder);
             let mut render_pass = RenderPassBuilder::new()
-                .push_color_attachment(self.tex_vpf_tmp.view.color_attachment_clear())
+                .push_color_attachment(self.tex_vpf.write().view.color_attachment_clear())
                 .begin(encoder, None);
 
             let mut render_pass_pipeline = render_pass.set_pipeline(&self.pipeline_blurwh);
 
-            render_pass_pipeline.set_bind_group(0, self.global_uniform.get_bind_group(), &[]);
+            render_pass_pipeline.set_bind_group(0, self.uniforms.get_bind_group(), &[]);
+            render_pass_pipeline.set_bind_group(1, self.tex_vpf.get_bind_group(), &[]);
+
+            self.mesh.draw(&mut render_pass_pipeline);
+        }
+        self.tex_vpf.swap();
+    }
+
+    ///
+    /// Makes the velocity free of divergence if the solver is a pressure projection.
+    ///
+    fn project(&mut self, encoder: &mut wgpu::CommandEncoder){
+        if !self.solver.is_projection(){
+            return;
+        }
+
+        self.pressure_pass(encoder, PressurePass::Divergence);
+        match self.solver{
+            Solver::DensityInvariant => {}
+            Solver::Jacobi{iterations} => for _ in 0..iterations{
+                self.pressure_pass(encoder, PressurePass::Jacobi);
+            }
+            Solver::GaussSeidel{iterations} => for _ in 0..iterations{
+                self.pressure_pass(encoder, PressurePass::GaussSeidel);
+            }
+            Solver::Multigrid{cycles} => self.multigrid.solve(encoder, &mut self.tex_pressure, cycles),
+        }
+        self.pressure_pass(encoder, PressurePass::Project);
+    }
+
+    fn pressure_pass(&mut self, encoder: &mut wgpu::CommandEncoder, pass: PressurePass){
+        match self.step_mode{
+            StepMode::Render => self.pressure_pass_render(encoder, pass),
+            StepMode::Compute => self.pressure_pass_compute(encoder, pass),
+        }
+    }
+
+    ///
+    /// Renders one pass of vf_pressure.glsl into tex_pressure or tex_vpf for the projection and
+    /// swaps it.
+    ///
+    fn pressure_pass_render(&mut self, encoder: &mut wgpu::CommandEncoder, pass: PressurePass){
+        {
+            let target = if pass == PressurePass::Project {&self.tex_vpf.write().view} else {&self.tex_pressure.write().view};
+            let mut render_pass = RenderPassBuilder::new()
+                .push_color_attachment(target.color_attachment_clear())
+                .begin(encoder, None);
+
+            let mut render_pass_pipeline = render_pass.set_pipeline(&self.pipelines_pressure[pass as usize]);
+
+            render_pass_pipeline.set_bind_group(0, self.uniforms.get_bind_group(), &[]);
             render_pass_pipeline.set_bind_group(1, self.tex_vpf.get_bind_group(), &[]);
+            render_pass_pipeline.set_bind_group(2, self.tex_pressure.get_bind_group(), &[]);
 
             self.mesh.draw(&mut render_pass_pipeline);
         }
+        if pass == PressurePass::Project{
+            self.tex_vpf.swap();
+        }
+        else{
+            self.tex_pressure.swap();
+        }
+    }
 
-        // Copy back step:
+    ///
+    /// Compute version of pressure_pass_render.
+    ///
+    fn pressure_pass_compute(&mut self, encoder: &mut wgpu::CommandEncoder, pass: PressurePass){
+        let workgroups = self.workgroups();
         {
-            self.tex_vpf_tmp.copy_all_to(&mut self.tex_vpf, encoder);
-            self.tex_color_tmp.copy_all_to(&mut self.tex_color, encoder);
-            self.tex_float_tmp.copy_all_to(&mut self.tex_float, encoder);
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor{
+                label: Some("PressureCompute"),
+            });
+
+            let storage = if pass == PressurePass::Project{
+                &self.storage_vpf[self.tex_vpf.write_index()]
+            }
+            else{
+                &self.storage_pressure[self.tex_pressure.write_index()]
+            };
+
+            cpass.set_pipeline(&self.cpipelines_pressure[pass as usize].pipeline);
+            cpass.set_bind_group(0, self.uniforms.get_bind_group(), &[]);
+            cpass.set_bind_group(1, self.tex_vpf.get_bind_group(), &[]);
+            cpass.set_bind_group(2, self.tex_pressure.get_bind_group(), &[]);
+            cpass.set_bind_group(3, storage.get_bind_group(), &[]);
+            cpass.dispatch(workgroups[0], workgroups[1], 1);
+        }
+        if pass == PressurePass::Project{
+            self.tex_vpf.swap();
         }
+        else{
+            self.tex_pressure.swap();
+        }
+    }
+
+    ///
+    /// Number of workgroups covering the simulation in x and y.
+    ///
+    fn workgroups(&self) -> [u32; 2]{
+        let size = self.tex_vpf.size;
+        [
+            size[0].div_ceil(WORKGROUP_SIZE),
+            size[1].div_ceil(WORKGROUP_SIZE),
+        ]
+    }
 
-        self.sc += 1;
+    ///
+    /// Runs the passes of the step as compute shaders writing to storage views of the write
+    /// sides of the ping pong textures and swaps them after every pass.
+    ///
+    fn step_compute(&mut self, encoder: &mut wgpu::CommandEncoder){
+        let workgroups = self.workgroups();
+
+        debug_assert_eq!(self.tex_color.write_index(), self.tex_float.write_index());
+
+        // Simulation step:
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor{
+                label: Some("StepCompute"),
+            });
+
+            let storage = &self.storage[self.tex_vpf.write_index() * 2 + self.tex_color.write_index()];
+
+            cpass.set_pipeline(&self.cpipeline.pipeline);
+            cpass.set_bind_group(PaintPipelineLayout::uniforms as u32, self.uniforms.get_bind_group(), &[]);
+            cpass.set_bind_group(PaintPipelineLayout::tex_vpf as u32, self.tex_vpf.get_bind_group(), &[]);
+            cpass.set_bind_group(PaintPipelineLayout::tex_pigments as u32, self.tex_pigments().get_bind_group(), &[]);
+            cpass.set_bind_group(PaintPipelineLayout::storage as u32, storage.get_bind_group(), &[]);
+            cpass.dispatch(workgroups[0], workgroups[1], 1);
+        }
+        self.tex_vpf.swap();
+        self.tex_color.swap();
+        self.tex_float.swap();
+        // Vorticity confinement
+        if self.params().vorticity != 0.{
+            {
+                let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor{
+                    label: Some("VorticityCompute"),
+                });
+
+                cpass.set_pipeline(&self.cpipeline_vorticity.pipeline);
+                cpass.set_bind_group(0, self.uniforms.get_bind_group(), &[]);
+                cpass.set_bind_group(1, self.tex_vpf.get_bind_group(), &[]);
+                cpass.set_bind_group(2, self.storage_vpf[self.tex_vpf.write_index()].get_bind_group(), &[]);
+                cpass.dispatch(workgroups[0], workgroups[1], 1);
+            }
+            self.tex_vpf.swap();
+        }
+        // Pressure projection
+        self.project(encoder);
+        // Boundary conditions
+        if self.params().vorticity != 0. || self.solver.is_projection(){
+            {
+                let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor{
+                    label: Some("BoundaryCompute"),
+                });
+
+                cpass.set_pipeline(&self.cpipeline_boundary.pipeline);
+                cpass.set_bind_group(0, self.uniforms.get_bind_group(), &[]);
+                cpass.set_bind_group(1, self.tex_vpf.get_bind_group(), &[]);
+                cpass.set_bind_group(2, self.storage_vpf[self.tex_vpf.write_index()].get_bind_group(), &[]);
+                cpass.dispatch(workgroups[0], workgroups[1], 1);
+            }
+            self.tex_vpf.swap();
+        }
+        // Capillary flow
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor{
+                label: Some("CapillaryCompute"),
+            });
+
+            let storage = &self.storage_capillary[self.tex_vpf.write_index() * 2 + self.tex_sat.write_index()];
+
+            cpass.set_pipeline(&self.cpipeline_capillary.pipeline);
+            cpass.set_bind_group(0, self.uniforms.get_bind_group(), &[]);
+            cpass.set_bind_group(1, self.tex_vpf.get_bind_group(), &[]);
+            cpass.set_bind_group(2, self.tex_sat.get_bind_group(), &[]);
+            cpass.set_bind_group(3, storage.get_bind_group(), &[]);
+            cpass.dispatch(workgroups[0], workgroups[1], 1);
+        }
+        self.tex_vpf.swap();
+        self.tex_sat.swap();
+        // Blur Vertically
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor{
+                label: Some("BlurWVCompute"),
+            });
+
+            cpass.set_pipeline(&self.cpipeline_blurwv.pipeline);
+            cpass.set_bind_group(0, self.uniforms.get_bind_group(), &[]);
+            cpass.set_bind_group(1, self.tex_vpf.get_bind_group(), &[]);
+            cpass.set_bind_group(2, self.storage_vpf[self.tex_vpf.write_index()].get_bind_group(), &[]);
+            cpass.dispatch(workgroups[0], workgroups[1], 1);
+        }
+        self.tex_vpf.swap();
+        // Blur Horizontally
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor{
+                label: Some("BlurWHCompute"),
+            });
+
+            cpass.set_pipeline(&self.cpipeline_blurwh.pipeline);
+            cpass.set_bind_group(0, self.uniforms.get_bind_group(), &[]);
+            cpass.set_bind_group(1, self.tex_vpf.get_bind_group(), &[]);
+            cpass.set_bind_group(2, self.storage_vpf[self.tex_vpf.write_index()].get_bind_group(), &[]);
+            cpass.dispatch(workgroups[0], workgroups[1], 1);
+        }
+        self.tex_vpf.swap();
     }
 }
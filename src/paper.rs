@@ -0,0 +1,309 @@
+use anyhow::*;
+
+///
+/// Parameters of the procedural paper generator.
+///
+#[derive(Clone, Copy, Debug)]
+pub struct PaperParams{
+    pub seed: u64,
+    // length of the fibers and period of the coarsest noise octave in pixels.
+    pub fiber_scale: f32,
+    // amplitude of the large scale fractal noise.
+    pub roughness: f32,
+    // amplitude of the per pixel noise.
+    pub grain: f32,
+    // generate paper that tiles seamlessly.
+    pub periodic: bool,
+}
+
+impl Default for PaperParams{
+    fn default() -> Self{
+        Self{
+            seed: 0,
+            fiber_scale: 8.0,
+            roughness: 0.5,
+            grain: 0.3,
+            periodic: false,
+        }
+    }
+}
+
+///
+/// Small deterministic random number generator (splitmix64) so that a seed produces the same
+/// paper on every platform.
+///
+struct Rng(u64);
+
+impl Rng{
+    fn next_u64(&mut self) -> u64{
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        hash(self.0)
+    }
+
+    // uniform in [0, 1).
+    fn next_f32(&mut self) -> f32{
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+fn hash(mut x: u64) -> u64{
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+// value in [0, 1) at the lattice point (x, y) of an octave.
+fn lattice(seed: u64, octave: u32, x: i64, y: i64) -> f32{
+    let h = hash(seed ^ hash((octave as u64) << 48 ^ (x as u64) << 24 ^ (y as u64 & 0xffffff)));
+    (h >> 40) as f32 / (1u64 << 24) as f32
+}
+
+// smoothly interpolated value noise with a lattice period of one, the lattice repeats after
+// cells points in each direction if given.
+fn value_noise(seed: u64, octave: u32, x: f32, y: f32, cells: Option<[i64; 2]>) -> f32{
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let (sx, sy) = (fx * fx * (3. - 2. * fx), fy * fy * (3. - 2. * fy));
+    let (x0, y0) = (x0 as i64, y0 as i64);
+
+    let at = |x: i64, y: i64| match cells{
+        Some(cells) => lattice(seed, octave, x.rem_euclid(cells[0]), y.rem_euclid(cells[1])),
+        None => lattice(seed, octave, x, y),
+    };
+    let top = at(x0, y0) * (1. - sx) + at(x0 + 1, y0) * sx;
+    let bottom = at(x0, y0 + 1) * (1. - sx) + at(x0 + 1, y0 + 1) * sx;
+    top * (1. - sy) + bottom * sy
+}
+
+///
+/// Height field of the paper in [0, 1] with one value per simulation cell.
+///
+/// Row y of the height field corresponds to row y of the simulation textures.
+///
+#[derive(Clone, Debug)]
+pub struct Paper{
+    pub size: [u32; 2],
+    pub height: Vec<f32>,
+    // whether the height wraps around the edges.
+    pub periodic: bool,
+}
+
+impl Paper{
+    ///
+    /// Flat paper without any structure.
+    ///
+    pub fn flat(size: [u32; 2]) -> Self{
+        Self{
+            size,
+            height: vec![0.5; (size[0] * size[1]) as usize],
+            periodic: false,
+        }
+    }
+
+    ///
+    /// Generates paper from fractal noise, randomly placed fibers and per pixel grain.
+    ///
+    /// Periodic paper stretches the noise octaves to a whole number of lattice cells and wraps
+    /// the fibers around the edges.
+    ///
+    pub fn generate(size: [u32; 2], params: &PaperParams) -> Self{
+        let (w, h) = (size[0] as usize, size[1] as usize);
+        let mut rng = Rng(params.seed);
+        let mut height = vec![0.0f32; w * h];
+
+        let fiber_scale = params.fiber_scale.max(1.);
+
+        // Large scale unevenness of the paper.
+        let octaves = 4;
+        for y in 0..h{
+            for x in 0..w{
+                let mut amplitude = 1.;
+                let mut period = fiber_scale * 4.;
+                let mut noise = 0.;
+                for octave in 0..octaves{
+                    let (periods, cells) = if params.periodic{
+                        let cells = [(w as f32 / period).round().max(1.), (h as f32 / period).round().max(1.)];
+                        ([w as f32 / cells[0], h as f32 / cells[1]], Some([cells[0] as i64, cells[1] as i64]))
+                    } else {([period, period], None)};
+                    noise += amplitude * value_noise(params.seed, octave, x as f32 / periods[0], y as f32 / periods[1], cells);
+                    amplitude *= 0.5;
+                    period *= 0.5;
+                }
+                height[y * w + x] = params.roughness * noise / 1.875;
+            }
+        }
+
+        // Fibers are short straight lines raising the paper along their length.
+        let fibers = (w * h) as f32 / (fiber_scale * fiber_scale) * 0.5;
+        let mut fiber_field = vec![0.0f32; w * h];
+        for _ in 0..fibers as usize{
+            let start = [rng.next_f32() * w as f32, rng.next_f32() * h as f32];
+            let angle = rng.next_f32() * std::f32::consts::PI;
+            let length = fiber_scale * (1. + 2. * rng.next_f32());
+            let dir = [angle.cos(), angle.sin()];
+
+            for i in 0..length as usize{
+                if params.periodic{
+                    let x = ((start[0] + dir[0] * i as f32).floor() as i64).rem_euclid(w as i64) as usize;
+                    let y = ((start[1] + dir[1] * i as f32).floor() as i64).rem_euclid(h as i64) as usize;
+                    fiber_field[y * w + x] += 1.;
+                    continue;
+                }
+                let x = (start[0] + dir[0] * i as f32) as usize;
+                let y = (start[1] + dir[1] * i as f32) as usize;
+                if x < w && y < h{
+                    fiber_field[y * w + x] += 1.;
+                }
+            }
+        }
+        // Give the fibers a soft profile.
+        let fiber_field = Self::blur3(&fiber_field, w, h, params.periodic);
+        for (height, fiber) in height.iter_mut().zip(fiber_field.iter()){
+            *height += 0.5 * fiber.min(1.);
+        }
+
+        // Grain of the paper surface.
+        let grain: Vec<f32> = (0..w * h).map(|_| rng.next_f32()).collect();
+        let grain = Self::blur3(&grain, w, h, params.periodic);
+        for (height, grain) in height.iter_mut().zip(grain.iter()){
+            *height += params.grain * grain;
+        }
+
+        let mut paper = Self{
+            size,
+            height,
+            periodic: params.periodic,
+        };
+        paper.normalize();
+        paper
+    }
+
+    ///
+    /// Loads a paper scan, its luminance is used as the height and it is resized to size.
+    ///
+    pub fn load_from_path(path: &str, size: [u32; 2]) -> Result<Self>{
+        let img = image::open(path)?;
+        // Flipped like Texture::from_image so the scan lines up with the source image.
+        let img = img.flipv().resize_exact(size[0], size[1], image::imageops::FilterType::Triangle).to_luma8();
+
+        let mut paper = Self{
+            size,
+            height: img.pixels().map(|p| p[0] as f32 / 255.).collect(),
+            periodic: false,
+        };
+        paper.normalize();
+        Ok(paper)
+    }
+
+    ///
+    /// Height and its central differences (h, dh/dx, dh/dy, 0) in the layout of a Rgba32Float
+    /// texture.
+    ///
+    pub fn to_texels(&self) -> Vec<[f32; 4]>{
+        let mut texels = Vec::with_capacity(self.height.len());
+        for y in 0..self.size[1]{
+            for x in 0..self.size[0]{
+                texels.push(self.texel(x, y));
+            }
+        }
+        texels
+    }
+
+    ///
+    /// The texel at (x, y) of to_texels.
+    ///
+    pub fn texel(&self, x: u32, y: u32) -> [f32; 4]{
+        let (w, h) = (self.size[0] as i32, self.size[1] as i32);
+        let get = |x: i32, y: i32| if self.periodic{
+            self.height[(y.rem_euclid(h) * w + x.rem_euclid(w)) as usize]
+        } else {
+            self.height[(y.clamp(0, h - 1) * w + x.clamp(0, w - 1)) as usize]
+        };
+        let (x, y) = (x as i32, y as i32);
+        [
+            get(x, y),
+            (get(x + 1, y) - get(x - 1, y)) / 2.,
+            (get(x, y + 1) - get(x, y - 1)) / 2.,
+            0.,
+        ]
+    }
+
+    // rescale the height to [0, 1].
+    fn normalize(&mut self){
+        let min = self.height.iter().cloned().fold(f32::INFINITY, f32::min);
+        let max = self.height.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        if max - min > 0.{
+            for height in self.height.iter_mut(){
+                *height = (*height - min) / (max - min);
+            }
+        }
+    }
+
+    // 3x3 box blur with clamped or wrapped edges.
+    fn blur3(data: &[f32], w: usize, h: usize, periodic: bool) -> Vec<f32>{
+        let mut res = vec![0.0; data.len()];
+        for y in 0..h as i32{
+            for x in 0..w as i32{
+                let mut sum = 0.;
+                for dy in -1..=1{
+                    for dx in -1..=1{
+                        let (sx, sy) = if periodic{
+                            ((x + dx).rem_euclid(w as i32) as usize, (y + dy).rem_euclid(h as i32) as usize)
+                        } else {
+                            ((x + dx).clamp(0, w as i32 - 1) as usize, (y + dy).clamp(0, h as i32 - 1) as usize)
+                        };
+                        sum += data[sy * w + sx];
+                    }
+                }
+                res[y as usize * w + x as usize] = sum / 9.;
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn params(periodic: bool) -> PaperParams{
+        PaperParams{
+            seed: 42,
+            periodic,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn same_seed_generates_the_same_paper(){
+        let a = Paper::generate([40, 24], &params(false));
+        let b = Paper::generate([40, 24], &params(false));
+        assert_eq!(a.height, b.height);
+    }
+
+    #[test]
+    fn height_is_normalized(){
+        let paper = Paper::generate([40, 24], &params(false));
+        assert_eq!(paper.height.len(), 40 * 24);
+        let min = paper.height.iter().cloned().fold(f32::INFINITY, f32::min);
+        let max = paper.height.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        assert_eq!(min, 0.);
+        assert_eq!(max, 1.);
+    }
+
+    #[test]
+    fn periodic_paper_wraps_around_the_edges(){
+        let (w, h) = (40, 24);
+        let paper = Paper::generate([w, h], &params(true));
+        assert!(paper.periodic);
+        let at = |x: u32, y: u32| paper.height[(y * w + x) as usize];
+        for y in 0..h{
+            assert_eq!(paper.texel(0, y)[1], (at(1, y) - at(w - 1, y)) / 2.);
+            assert_eq!(paper.texel(w - 1, y)[1], (at(0, y) - at(w - 2, y)) / 2.);
+        }
+        for x in 0..w{
+            assert_eq!(paper.texel(x, 0)[2], (at(x, 1) - at(x, h - 1)) / 2.);
+            assert_eq!(paper.texel(x, h - 1)[2], (at(x, 0) - at(x, h - 2)) / 2.);
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use crate::display::{Display, DisplayMode};
+use crate::paintsim::PaintSim;
+use crate::wgpu_utils::binding::BindGroup;
+use crate::wgpu_utils::buffer::Buffer;
+use crate::wgpu_utils::resample::Resample;
+use crate::wgpu_utils::texture::Texture;
+use anyhow::*;
+use image::codecs::gif::{GifEncoder, Repeat};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use std::thread::JoinHandle;
+
+// delay between the frames of an animated gif, 25 frames per second.
+const GIF_FRAME_DELAY_MS: u32 = 40;
+
+type MapFuture = Pin<Box<dyn Future<Output = std::result::Result<(), wgpu::BufferAsyncError>> + Send>>;
+
+///
+/// Where the frames of a time-lapse are written to.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RecordOutput{
+    // directory of numbered pngs.
+    Png(String),
+    // animated gif.
+    Gif(String),
+}
+
+impl RecordOutput{
+    ///
+    /// Paths ending in .gif are animated gifs, all other paths are directories of pngs.
+    ///
+    pub fn from_path(path: &str) -> Self{
+        if path.ends_with(".gif"){
+            Self::Gif(path.to_string())
+        }
+        else{
+            Self::Png(path.to_string())
+        }
+    }
+}
+
+enum SlotState{
+    Free,
+    // the copy of a frame is recorded but not yet submitted.
+    Copied,
+    Mapping(MapFuture),
+}
+
+///
+/// Staging buffer a frame is copied to and read back from.
+///
+struct Slot{
+    buffer: Buffer<u8>,
+    state: SlotState,
+}
+
+///
+/// Records a time-lapse of the displayed painting every interval steps.
+///
+/// The frames are rendered into a texture of the size of the grid, resampled to the size of the
+/// image the simulation was created from like the output of a headless run and copied to staging
+/// buffers which are mapped asynchronously, so reading them back does not wait for the GPU. A
+/// frame is passed to a writer thread encoding the pngs or the gif once its buffer is mapped,
+/// buffers are added when all of them are in use.
+///
+pub struct Recorder{
+    interval: u64,
+    display: Display,
+    target: BindGroup<Texture>,
+    // resamples the target to the output if the grid and the source have different sizes.
+    resample: Option<(Resample, Texture)>,
+
+    slots: Vec<Slot>,
+    // slots with frames in the order they were recorded.
+    pending: VecDeque<usize>,
+    // steps of the simulation when the last frame was recorded.
+    last_steps: Option<u64>,
+    frames: u64,
+
+    sender: Option<mpsc::Sender<image::RgbaImage>>,
+    writer: Option<JoinHandle<Result<()>>>,
+}
+
+impl Recorder{
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, paintsim: &PaintSim, interval: u64, output: RecordOutput) -> Result<Self>{
+        let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let size = paintsim.tex_src.size;
+        let display = Display::new(device, format, size)?;
+        let target = BindGroup::new(Texture::new_black(size, device, queue, Some("RecorderTarget"), format)?, device);
+
+        let output_size = paintsim.source_size();
+        let resample = if output_size == size{
+            None
+        }
+        else{
+            let address_mode = if paintsim.periodic() {wgpu::AddressMode::Repeat} else {wgpu::AddressMode::ClampToEdge};
+            Some((
+                Resample::new(device, format, output_size, address_mode)?,
+                Texture::new_black(output_size, device, queue, Some("RecorderOutput"), format)?,
+            ))
+        };
+
+        if let RecordOutput::Png(dir) = &output{
+            std::fs::create_dir_all(dir)?;
+        }
+        let (sender, receiver) = mpsc::channel();
+        let writer = std::thread::spawn(move || write_frames(output, receiver));
+
+        Ok(Self{
+            interval: interval.max(1),
+            display,
+            target,
+            resample,
+            slots: Vec::new(),
+            pending: VecDeque::new(),
+            last_steps: None,
+            frames: 0,
+            sender: Some(sender),
+            writer: Some(writer),
+        })
+    }
+
+    pub fn interval(&self) -> u64{
+        self.interval
+    }
+
+    ///
+    /// Frames recorded so far.
+    ///
+    pub fn frames(&self) -> u64{
+        self.frames
+    }
+
+    ///
+    /// Records a frame if the simulation passed a multiple of the interval since the last frame,
+    /// the first call always records one. poll has to be called after submitting the encoder.
+    ///
+    pub fn record(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, paintsim: &PaintSim, mode: DisplayMode){
+        let steps = paintsim.clock().steps();
+        if let Some(last_steps) = self.last_steps{
+            if steps / self.interval <= last_steps / self.interval{
+                return;
+            }
+        }
+        self.last_steps = Some(steps);
+
+        let slot = match self.slots.iter().position(|slot| matches!(slot.state, SlotState::Free)){
+            Some(slot) => slot,
+            None => {
+                let len = (self.frame().padded_bytes_per_row() * self.frame().size[1]) as usize;
+                self.slots.push(Slot{
+                    buffer: Buffer::new_empty(device, wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST, Some("RecorderStaging"), len),
+                    state: SlotState::Free,
+                });
+                self.slots.len() - 1
+            }
+        };
+
+        self.display.render(encoder, &self.target.view, paintsim, mode);
+        if let Some((resample, output)) = &self.resample{
+            resample.resample(encoder, &self.target, &output.view);
+        }
+        self.frame().copy_all_to_buffer(&self.slots[slot].buffer, encoder);
+        self.slots[slot].state = SlotState::Copied;
+        self.pending.push_back(slot);
+        self.frames += 1;
+    }
+
+    ///
+    /// Starts mapping the frames submitted since the last call and passes the frames that are
+    /// mapped to the writer without blocking.
+    ///
+    pub fn poll(&mut self, device: &wgpu::Device) -> Result<()>{
+        self.poll_frames(device, wgpu::Maintain::Poll)
+    }
+
+    ///
+    /// Waits for all frames and the writer, the output is complete afterwards.
+    ///
+    pub fn finish(mut self, device: &wgpu::Device) -> Result<()>{
+        while !self.pending.is_empty(){
+            self.poll_frames(device, wgpu::Maintain::Wait)?;
+        }
+        drop(self.sender.take());
+        self.writer.take()
+            .ok_or(anyhow!("recorder already finished"))?
+            .join()
+            .map_err(|_| anyhow!("time-lapse writer panicked"))?
+    }
+
+    ///
+    /// Texture the frames are read back from.
+    ///
+    fn frame(&self) -> &Texture{
+        match &self.resample{
+            Some((_, output)) => output,
+            None => &self.target,
+        }
+    }
+
+    fn poll_frames(&mut self, device: &wgpu::Device, maintain: wgpu::Maintain) -> Result<()>{
+        for slot in &mut self.slots{
+            if let SlotState::Copied = slot.state{
+                slot.state = SlotState::Mapping(Box::pin(slot.buffer.buffer.slice(..).map_async(wgpu::MapMode::Read)));
+            }
+        }
+        device.poll(maintain);
+
+        let size = self.frame().size;
+        let bytes_per_row = self.frame().bytes_per_row() as usize;
+        let padded_bytes_per_row = self.frame().padded_bytes_per_row() as usize;
+
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        // Frames are passed on in order so a later frame waits for the earlier ones.
+        while let Some(&index) = self.pending.front(){
+            let slot = &mut self.slots[index];
+            let ready = match &mut slot.state{
+                SlotState::Mapping(mapping) => match mapping.as_mut().poll(&mut cx){
+                    Poll::Ready(result) => {
+                        result.map_err(|_| anyhow!("mapping a time-lapse frame failed"))?;
+                        true
+                    }
+                    Poll::Pending => false,
+                },
+                _ => false,
+            };
+            if !ready{
+                break;
+            }
+
+            let mut data = Vec::with_capacity(bytes_per_row * size[1] as usize);
+            {
+                let mapped = slot.buffer.buffer.slice(..).get_mapped_range();
+                for row in mapped.chunks_exact(padded_bytes_per_row){
+                    data.extend_from_slice(&row[..bytes_per_row]);
+                }
+            }
+            slot.buffer.buffer.unmap();
+            slot.state = SlotState::Free;
+            self.pending.pop_front();
+
+            let img = image::RgbaImage::from_raw(size[0], size[1], data).ok_or(anyhow!("frame has the wrong size"))?;
+            self.sender.as_ref()
+                .ok_or(anyhow!("recorder already finished"))?
+                .send(img)
+                .map_err(|_| anyhow!("time-lapse writer stopped"))?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// Writes the frames received until the recorder is finished.
+///
+fn write_frames(output: RecordOutput, receiver: mpsc::Receiver<image::RgbaImage>) -> Result<()>{
+    match output{
+        RecordOutput::Png(dir) => {
+            for (i, img) in receiver.iter().enumerate(){
+                img.save(format!("{}/frame{:05}.png", dir, i))?;
+            }
+        }
+        RecordOutput::Gif(path) => {
+            let mut encoder = GifEncoder::new_with_speed(std::fs::File::create(path)?, 10);
+            encoder.set_repeat(Repeat::Infinite)?;
+            for img in receiver.iter(){
+                encoder.encode_frame(image::Frame::from_parts(img, 0, 0, image::Delay::from_numer_denom_ms(GIF_FRAME_DELAY_MS, 1)))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+///
+/// Waker for polling the mapping futures, wgpu completes them in Device::poll instead of waking.
+///
+fn noop_waker() -> Waker{
+    fn clone(_: *const ()) -> RawWaker{
+        RawWaker::new(std::ptr::null(), &VTABLE)
+    }
+    fn noop(_: *const ()){}
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+
+    // SAFETY: the waker does nothing and has no data.
+    unsafe{
+        Waker::from_raw(RawWaker::new(std::ptr::null(), &VTABLE))
+    }
+}
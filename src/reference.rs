@@ -0,0 +1,907 @@
+use crate::paintsim::{PaintParams, Brush, PigmentData, PigmentRegistry, Solver, Advection, Limiter, Boundary, PIGMENT_LAYERS, MULTIGRID_MIN_SIZE};
+use crate::wgpu_utils::multigrid::Multigrid;
+use crate::paper::Paper;
+use crate::obstacles::Obstacles;
+use anyhow::*;
+
+// Blur coefficients of vf_blurwh.glsl and vf_blurwv.glsl rounded to f32.
+const BLUR_COEFF: [f32; 5] = [
+    0.1985961,
+    0.17571363,
+    0.121702746,
+    0.065983966,
+    0.02800156,
+];
+
+///
+/// A RGBA float grid with the same layout as a Rgba32Float texture.
+///
+#[derive(Clone, Debug)]
+pub struct Field{
+    pub size: [u32; 2],
+    pub data: Vec<[f32; 4]>,
+    // wrap around the edges like a texture sampled with AddressMode::Repeat.
+    pub periodic: bool,
+}
+
+impl Field{
+    pub fn new(size: [u32; 2]) -> Self{
+        Self{
+            size,
+            data: vec![[0.0; 4]; (size[0] * size[1]) as usize],
+            periodic: false,
+        }
+    }
+
+    pub fn with_periodic(mut self, periodic: bool) -> Self{
+        self.periodic = periodic;
+        self
+    }
+
+    ///
+    /// Create a Field from the bytes read back from a Rgba32Float texture.
+    ///
+    pub fn from_bytes(size: [u32; 2], bytes: &[u8]) -> Result<Self>{
+        if bytes.len() != (size[0] * size[1]) as usize * std::mem::size_of::<[f32; 4]>(){
+            return Err(anyhow!("expected {}x{} Rgba32Float texels", size[0], size[1]));
+        }
+        Ok(Self{
+            size,
+            data: bytes.chunks_exact(std::mem::size_of::<[f32; 4]>()).map(|texel|{
+                let mut px = [0.0f32; 4];
+                bytemuck::bytes_of_mut(&mut px).copy_from_slice(texel);
+                px
+            }).collect(),
+            periodic: false,
+        })
+    }
+
+    ///
+    /// Create one Field per layer from the bytes read back from a Rgba32Float texture array.
+    ///
+    pub fn layers_from_bytes(size: [u32; 2], layers: u32, bytes: &[u8]) -> Result<Vec<Self>>{
+        let layer_bytes = (size[0] * size[1]) as usize * std::mem::size_of::<[f32; 4]>();
+        if bytes.len() != layer_bytes * layers as usize{
+            return Err(anyhow!("expected {} layers of {}x{} Rgba32Float texels", layers, size[0], size[1]));
+        }
+        bytes.chunks_exact(layer_bytes).map(|layer| Self::from_bytes(size, layer)).collect()
+    }
+
+    ///
+    /// Texel at (x, y) with the coordinates clamped to the edge or wrapped if periodic.
+    ///
+    pub fn get(&self, x: i32, y: i32) -> glm::Vec4{
+        let (w, h) = (self.size[0] as i32, self.size[1] as i32);
+        let (x, y) = if self.periodic{
+            (x.rem_euclid(w) as usize, y.rem_euclid(h) as usize)
+        } else {
+            (x.clamp(0, w - 1) as usize, y.clamp(0, h - 1) as usize)
+        };
+        glm::Vec4::from(self.data[y * self.size[0] as usize + x])
+    }
+
+    ///
+    /// Bilinear sample at pos in pixel coordinates, equivalent to texture(..., pos/size).
+    ///
+    pub fn sample(&self, pos: glm::Vec2) -> glm::Vec4{
+        let x = pos.x - 0.5;
+        let y = pos.y - 0.5;
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fx = x - x0;
+        let fy = y - y0;
+        let (x0, y0) = (x0 as i32, y0 as i32);
+
+        let top = self.get(x0, y0) * (1. - fx) + self.get(x0 + 1, y0) * fx;
+        let bottom = self.get(x0, y0 + 1) * (1. - fx) + self.get(x0 + 1, y0 + 1) * fx;
+        top * (1. - fy) + bottom * fy
+    }
+
+    ///
+    /// Create a Field from texels in row major order.
+    ///
+    pub fn from_texels(size: [u32; 2], data: Vec<[f32; 4]>) -> Self{
+        assert_eq!(data.len(), (size[0] * size[1]) as usize);
+        Self{
+            size,
+            data,
+            periodic: false,
+        }
+    }
+
+    ///
+    /// The Field moved by offset texels, texels leaving it on one side enter it on the opposite
+    /// side.
+    ///
+    pub fn translate(&self, offset: [i32; 2]) -> Field{
+        let (w, h) = (self.size[0] as i32, self.size[1] as i32);
+        self.map(|x, y|{
+            let (sx, sy) = ((x - offset[0]).rem_euclid(w), (y - offset[1]).rem_euclid(h));
+            glm::Vec4::from(self.data[(sy * w + sx) as usize])
+        })
+    }
+
+    ///
+    /// Maximum absolute difference per channel.
+    ///
+    pub fn max_abs_diff(&self, other: &Field) -> [f32; 4]{
+        assert_eq!(self.size, other.size);
+        let mut res = [0.0f32; 4];
+        for (a, b) in self.data.iter().zip(other.data.iter()){
+            for i in 0..4{
+                res[i] = res[i].max((a[i] - b[i]).abs());
+            }
+        }
+        res
+    }
+
+    fn map(&self, f: impl Fn(i32, i32) -> glm::Vec4) -> Field{
+        let mut data = Vec::with_capacity(self.data.len());
+        for y in 0..self.size[1] as i32{
+            for x in 0..self.size[0] as i32{
+                data.push(f(x, y).into());
+            }
+        }
+        Field{
+            size: self.size,
+            data,
+            periodic: self.periodic,
+        }
+    }
+}
+
+///
+/// Mirrors the advection schemes of vf_paint04.glsl for one field.
+///
+struct Advect<'a>{
+    field: &'a Field,
+    vpf: &'a Field,
+    dt: f32,
+    advection: Advection,
+}
+
+impl Advect<'_>{
+    fn vel(&self, pos: glm::Vec2) -> glm::Vec2{
+        self.vpf.sample(pos).xy()
+    }
+
+    fn trace(&self, pos: glm::Vec2, vel: glm::Vec2, dt: f32) -> glm::Vec2{
+        if self.advection == Advection::Rk2{
+            return pos - self.vel(pos - vel * (0.5 * dt)) * dt;
+        }
+        pos - vel * dt
+    }
+
+    // bilinear interpolation of f evaluated at the four cell centers around pos.
+    fn interp(pos: glm::Vec2, f: impl Fn(glm::Vec2) -> glm::Vec4) -> glm::Vec4{
+        let c0 = glm::vec2((pos.x - 0.5).floor() + 0.5, (pos.y - 0.5).floor() + 0.5);
+        let t = pos - c0;
+        let bottom = f(c0) * (1. - t.x) + f(c0 + glm::vec2(1., 0.)) * t.x;
+        let top = f(c0 + glm::vec2(0., 1.)) * (1. - t.x) + f(c0 + glm::vec2(1., 1.)) * t.x;
+        bottom * (1. - t.y) + top * t.y
+    }
+
+    fn sl(&self, pos: glm::Vec2) -> glm::Vec4{
+        self.field.sample(self.trace(pos, self.vel(pos), self.dt))
+    }
+
+    fn round_trip(&self, pos: glm::Vec2) -> glm::Vec4{
+        Self::interp(self.trace(pos, self.vel(pos), -self.dt), |c| self.sl(c))
+    }
+
+    fn limit(&self, res: glm::Vec4, pos: glm::Vec2, sl: glm::Vec4, limiter: Limiter) -> glm::Vec4{
+        if limiter == Limiter::None{
+            return res;
+        }
+
+        let c0 = glm::vec2((pos.x - 0.5).floor() + 0.5, (pos.y - 0.5).floor() + 0.5);
+        let values = [
+            self.field.sample(c0),
+            self.field.sample(c0 + glm::vec2(1., 0.)),
+            self.field.sample(c0 + glm::vec2(0., 1.)),
+            self.field.sample(c0 + glm::vec2(1., 1.)),
+        ];
+
+        let mut res = res;
+        for c in 0..4{
+            let lo = values.iter().map(|v| v[c]).fold(f32::INFINITY, f32::min);
+            let hi = values.iter().map(|v| v[c]).fold(f32::NEG_INFINITY, f32::max);
+            res[c] = match limiter{
+                Limiter::Clamp => res[c].clamp(lo, hi),
+                _ => if res[c] < lo || res[c] > hi {sl[c]} else {res[c]},
+            };
+        }
+        res
+    }
+
+    fn advect(&self, r: glm::Vec2, vel: glm::Vec2) -> glm::Vec4{
+        let back = self.trace(r, vel, self.dt);
+        let sl = self.field.sample(back);
+
+        match self.advection{
+            Advection::MacCormack{limiter} => {
+                let res = sl + (self.field.sample(r) - Self::interp(self.trace(r, vel, -self.dt), |c| self.sl(c))) * 0.5;
+                self.limit(res, back, sl, limiter)
+            }
+            Advection::Bfecc{limiter} => {
+                let res = Self::interp(back, |c| self.field.sample(c) * 1.5 - self.round_trip(c) * 0.5);
+                self.limit(res, back, sl, limiter)
+            }
+            _ => sl,
+        }
+    }
+}
+
+///
+/// CPU implementation of the watercolor step mirroring vf_paint04.glsl, vf_vorticity.glsl,
+/// vf_pressure.glsl, vf_multigrid.glsl, vf_boundary.glsl, vf_capillary.glsl, vf_blurwv.glsl and
+/// vf_blurwh.glsl.
+///
+/// It is slow and only meant to check that shader changes keep the physics correct by comparing
+/// GPU readbacks against it.
+///
+pub struct ReferenceSim{
+    pub tex_vpf: Field,
+    // one Field per pigment layer.
+    pub tex_color: Vec<Field>,
+    pub tex_float: Vec<Field>,
+    // paper height, gradient and obstacles as in PaintSim::tex_paper.
+    pub tex_paper: Field,
+    pub tex_sat: Field,
+    pub tex_pressure: Field,
+    // has to match params.projection like PaintSim::set_solver does.
+    pub solver: Solver,
+    pub params: PaintParams,
+    pub brush: Brush,
+    pub pigments: PigmentData,
+}
+
+impl ReferenceSim{
+    pub fn new(size: [u32; 2], params: PaintParams) -> Self{
+        Self{
+            tex_vpf: Field::new(size),
+            tex_color: vec![Field::new(size); PIGMENT_LAYERS as usize],
+            tex_float: vec![Field::new(size); PIGMENT_LAYERS as usize],
+            tex_paper: Field::from_texels(size, Paper::flat(size).to_texels()),
+            tex_sat: Field::new(size),
+            tex_pressure: Field::new(size),
+            solver: Solver::DensityInvariant,
+            params,
+            brush: bytemuck::Zeroable::zeroed(),
+            pigments: PigmentRegistry::default().to_data(),
+        }
+    }
+
+    ///
+    /// Make all fields wrap around the edges like the textures of PaintSim::new_periodic.
+    ///
+    pub fn set_periodic(&mut self, periodic: bool){
+        for field in [&mut self.tex_vpf, &mut self.tex_paper, &mut self.tex_sat, &mut self.tex_pressure]{
+            field.periodic = periodic;
+        }
+        for field in self.tex_color.iter_mut().chain(self.tex_float.iter_mut()){
+            field.periodic = periodic;
+        }
+    }
+
+    ///
+    /// Replace the paper keeping the obstacles.
+    ///
+    pub fn set_paper(&mut self, paper: &Paper){
+        let solid = self.tex_paper.data.iter().map(|t| t[3]).collect::<Vec<_>>();
+        self.tex_paper = Field::from_texels(paper.size, paper.to_texels()).with_periodic(self.tex_paper.periodic);
+        for (t, solid) in self.tex_paper.data.iter_mut().zip(solid){
+            t[3] = solid;
+        }
+    }
+
+    pub fn set_obstacles(&mut self, obstacles: &Obstacles){
+        for (t, solid) in self.tex_paper.data.iter_mut().zip(obstacles.solid.iter()){
+            t[3] = *solid;
+        }
+    }
+
+    pub fn step(&mut self){
+        self.paint_step();
+        if self.params.vorticity != 0.{
+            self.tex_vpf = Self::confine(&self.tex_vpf, &self.params);
+        }
+        self.project();
+        if self.params.vorticity != 0. || self.solver.is_projection(){
+            self.boundary();
+        }
+        self.capillary();
+        self.tex_vpf = Self::blur_w(&self.tex_vpf, glm::vec2(0., 1.));
+        self.tex_vpf = Self::blur_w(&self.tex_vpf, glm::vec2(1., 0.));
+    }
+
+    fn brush_falloff(&self, r: glm::Vec2) -> f32{
+        if self.brush.radius <= 0.{
+            return 0.;
+        }
+        let mut d = r - glm::Vec2::from(self.brush.pos);
+        if self.tex_paper.periodic{
+            // the dab wraps around the edges.
+            let size = glm::vec2(self.tex_paper.size[0] as f32, self.tex_paper.size[1] as f32);
+            d -= size.component_mul(&glm::round(&d.component_div(&size)));
+        }
+        (-d.dot(&d) / (self.brush.radius * self.brush.radius)).exp()
+    }
+
+    ///
+    /// Mirrors the fragment shader of vf_paint04.glsl.
+    ///
+    fn paint_step(&mut self){
+        let p = self.params;
+        let dt = p.dt;
+        let hmin = p.hmin;
+
+        let vpf = &self.tex_vpf;
+        let advection = Advection::from_params(p.advection, p.limiter);
+
+        let mut o_vpf = Field::new(vpf.size).with_periodic(vpf.periodic);
+        let mut o_color = vec![Field::new(vpf.size).with_periodic(vpf.periodic); PIGMENT_LAYERS as usize];
+        let mut o_float = vec![Field::new(vpf.size).with_periodic(vpf.periodic); PIGMENT_LAYERS as usize];
+
+        for y in 0..vpf.size[1] as i32{
+            for x in 0..vpf.size[0] as i32{
+                let r = glm::vec2(x as f32 + 0.5, y as f32 + 0.5);
+                let v0 = vpf.get(x, y);
+                let mut vo = v0;
+
+                let vpx = vpf.get(x + 1, y);
+                let vnx = vpf.get(x - 1, y);
+                let vpy = vpf.get(x, y + 1);
+                let vny = vpf.get(x, y - 1);
+
+                let dx = (vpx - vnx) / 2.;
+                let dy = (vpy - vny) / 2.;
+                let lapl = vpx + vnx + vpy + vny - v0 * 4.;
+                let div = dx.x + dy.y;
+
+                // mass conservation.
+                vo.z -= dt * (dx.z * vo.x + dy.z * vo.y + vo.z * div);
+
+                // advection.
+                let adv = Advect{
+                    field: vpf,
+                    vpf,
+                    dt,
+                    advection,
+                }.advect(r, glm::vec2(vo.x, vo.y));
+                vo.x = adv.x;
+                vo.y = adv.y;
+
+                // viscosity.
+                vo.x += dt * p.nu * lapl.x;
+                vo.y += dt * p.nu * lapl.y;
+
+                // paper slope.
+                let slope = self.tex_paper.sample(r);
+                let slope = glm::vec2(slope.y, slope.z);
+                vo.x -= dt * p.paper_slope * slope.x;
+                vo.y -= dt * p.paper_slope * slope.y;
+
+                // nullify divergence.
+                if p.projection == 0{
+                    vo.x -= p.k * dx.z;
+                    vo.y -= p.k * dy.z;
+                }
+
+                // brush source.
+                let b = self.brush_falloff(r);
+                vo.z += b * self.brush.water;
+
+                vo.x = vo.x.clamp(-p.vmax[0], p.vmax[0]);
+                vo.y = vo.y.clamp(-p.vmax[1], p.vmax[1]);
+                vo.z = vo.z.clamp(hmin, 3.);
+
+                // wet area mask.
+                vo.w = if vo.z >= hmin + 0.5 || vo.w >= 0.5 {1.} else {0.};
+                if vo.z < hmin{
+                    vo.w = 0.;
+                    vo.z = hmin;
+                }
+                if vo.w < 0.5{
+                    vo.x = 0.;
+                    vo.y = 0.;
+                }
+
+                // evaporation.
+                vo.z -= p.evap_nu * (1. - v0.w) * vo.w;
+
+                // edge darkening.
+                let edge = (1. - v0.w) * vo.w;
+                vo.x -= dt * p.edge_darkening * dx.w * vo.w;
+                vo.y -= dt * p.edge_darkening * dy.w * vo.w;
+                vo.z -= dt * p.edge_darkening * edge;
+
+                // boundary conditions.
+                let vel = self.apply_boundary(x, y, glm::vec2(vo.x, vo.y));
+                vo.x = vel.x;
+                vo.y = vel.y;
+
+                // obstacles stay dry and keep their deposited pigment, neither the transfer nor
+                // the brush changes it.
+                let i = (y as u32 * vpf.size[0] + x as u32) as usize;
+                if self.solid(x, y){
+                    o_vpf.data[i] = [0., 0., hmin, 0.];
+                    for layer in 0..PIGMENT_LAYERS as usize{
+                        o_color[layer].data[i] = self.tex_color[layer].get(x, y).into();
+                        o_float[layer].data[i] = [0.; 4];
+                    }
+                    continue;
+                }
+                o_vpf.data[i] = vo.into();
+
+                // pigment advection and diffusion.
+                let float_nu = if vo.z > hmin + 0.001 {p.float_nu} else {0.};
+                for (layer, float) in self.tex_float.iter().enumerate(){
+                    let fl = float.get(x, y);
+                    let (float_px, float_nx) = (float.get(x + 1, y), float.get(x - 1, y));
+                    let (float_py, float_ny) = (float.get(x, y + 1), float.get(x, y - 1));
+                    let float_lapl = float_px + float_nx + float_py + float_ny - fl * 4.;
+
+                    let mut of = Advect{
+                        field: float,
+                        vpf,
+                        dt,
+                        advection,
+                    }.advect(r, glm::vec2(vo.x, vo.y));
+
+                    // upwind drift of granulating pigment down the slope of the paper.
+                    for c in 0..4{
+                        let settle = p.paper_settle * self.pigments.granulation[layer][c];
+                        let drift = -slope * settle;
+                        let grad_x = if drift.x > 0. {fl[c] - float_nx[c]} else {float_px[c] - fl[c]};
+                        let grad_y = if drift.y > 0. {fl[c] - float_ny[c]} else {float_py[c] - fl[c]};
+                        of[c] -= dt * (drift.x * grad_x + drift.y * grad_y);
+                    }
+                    of += float_lapl * (dt * float_nu);
+                    of += glm::Vec4::from(self.brush.pigment[layer]) * (dt * b);
+
+                    // pigment transfer.
+                    let mut oc = self.tex_color[layer].get(x, y);
+                    self.transfer(layer, self.tex_paper.get(x, y).x, vo.w >= 0.5, &mut of, &mut oc);
+
+                    o_color[layer].data[i] = oc.into();
+                    o_float[layer].data[i] = of.into();
+                }
+            }
+        }
+
+        self.tex_vpf = o_vpf;
+        self.tex_color = o_color;
+        self.tex_float = o_float;
+    }
+
+    ///
+    /// Mirrors transfer() of vf_paint04.glsl.
+    ///
+    fn transfer(&self, layer: usize, h: f32, wet: bool, g: &mut glm::Vec4, d: &mut glm::Vec4){
+        if !wet{
+            *d += *g;
+            *g = glm::Vec4::zeros();
+            return;
+        }
+
+        for c in 0..4{
+            let gamma = self.pigments.granulation[layer][c];
+            let rho = self.pigments.density[layer][c];
+            let omega = self.pigments.staining[layer][c].max(1e-4);
+
+            let down = (g[c] * (1. - h * gamma) * rho).min((1. - d[c]).max(0.));
+            let up = (d[c] * (1. + (h - 1.) * gamma) * rho / omega).min((1. - g[c]).max(0.));
+
+            d[c] += down - up;
+            g[c] += up - down;
+        }
+    }
+
+    fn solid(&self, x: i32, y: i32) -> bool{
+        self.tex_paper.get(x, y).w >= 0.5
+    }
+
+    ///
+    /// Mirrors apply_boundary() of vf_paint04.glsl and vf_boundary.glsl.
+    ///
+    fn apply_boundary(&self, x: i32, y: i32, mut vel: glm::Vec2) -> glm::Vec2{
+        let boundary = Boundary::from_param(self.params.boundary);
+        let size = self.tex_paper.size;
+        for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)]{
+            let (nx, ny) = (x + dx, y + dy);
+            // a periodic canvas has no edges.
+            let edge = !self.tex_paper.periodic && (nx < 0 || ny < 0 || nx >= size[0] as i32 || ny >= size[1] as i32);
+            if edge && boundary == Boundary::NoSlip{
+                return glm::Vec2::zeros();
+            }
+            let wall = if edge {boundary != Boundary::Open} else {self.solid(nx, ny)};
+            if wall{
+                let n = glm::vec2(dx as f32, dy as f32);
+                vel -= n * vel.dot(&n).max(0.);
+            }
+        }
+        vel
+    }
+
+    ///
+    /// Mirrors vf_boundary.glsl.
+    ///
+    fn boundary(&mut self){
+        let vpf = &self.tex_vpf;
+        self.tex_vpf = vpf.map(|x, y|{
+            let mut vo = vpf.get(x, y);
+            let vel = if self.solid(x, y) {glm::Vec2::zeros()} else {self.apply_boundary(x, y, glm::vec2(vo.x, vo.y))};
+            vo.x = vel.x;
+            vo.y = vel.y;
+            vo
+        });
+    }
+
+    fn capacity(&self, x: i32, y: i32) -> f32{
+        let paper = self.tex_paper.get(x, y);
+        (paper.x * (self.params.cap_max - self.params.cap_min) + self.params.cap_min) * (1. - paper.w)
+    }
+
+    fn flow(&self, s0: f32, s1: f32, c1: f32) -> f32{
+        if s0 > self.params.cap_epsilon && s0 > s1 && s1 >= self.params.cap_delta{
+            return ((s0 - s1).min(c1 - s1) / 4.).max(0.);
+        }
+        0.
+    }
+
+    ///
+    /// Mirrors vf_vorticity.glsl.
+    ///
+    fn confine(vpf: &Field, p: &PaintParams) -> Field{
+        let curl = |x: i32, y: i32| (vpf.get(x + 1, y).y - vpf.get(x - 1, y).y - vpf.get(x, y + 1).x + vpf.get(x, y - 1).x) / 2.;
+        vpf.map(|x, y|{
+            let mut vo = vpf.get(x, y);
+            if vo.w < 0.5{
+                return vo;
+            }
+
+            let grad = glm::vec2(
+                curl(x + 1, y).abs() - curl(x - 1, y).abs(),
+                curl(x, y + 1).abs() - curl(x, y - 1).abs(),
+            ) / 2.;
+            let n = grad / (grad.norm() + 1e-5);
+
+            let w = curl(x, y);
+            vo.x = (vo.x + p.dt * p.vorticity * n.y * w).clamp(-p.vmax[0], p.vmax[0]);
+            vo.y = (vo.y - p.dt * p.vorticity * n.x * w).clamp(-p.vmax[1], p.vmax[1]);
+            vo
+        })
+    }
+
+    ///
+    /// Mirrors the passes of vf_pressure.glsl and the Multigrid run by PaintSim for the solver.
+    ///
+    fn project(&mut self){
+        if !self.solver.is_projection(){
+            return;
+        }
+
+        // divergence in the wet area, the last pressure is the initial guess.
+        let vpf = &self.tex_vpf;
+        self.tex_pressure = self.tex_pressure.map(|x, y|{
+            let mut div = 0.;
+            if vpf.get(x, y).w >= 0.5{
+                div = (vpf.get(x + 1, y).x - vpf.get(x - 1, y).x + vpf.get(x, y + 1).y - vpf.get(x, y - 1).y) / 2.;
+            }
+            glm::vec4(self.tex_pressure.get(x, y).x, div, 0., 0.)
+        });
+
+        // lapl(p) = div
+        match self.solver{
+            Solver::DensityInvariant => {}
+            Solver::Jacobi{iterations} => for _ in 0..iterations{
+                self.tex_pressure = Self::relax(&self.tex_pressure, 0., -1., false);
+            }
+            Solver::GaussSeidel{iterations} => for _ in 0..iterations{
+                self.tex_pressure = Self::relax(&self.tex_pressure, 0., -1., true);
+            }
+            Solver::Multigrid{cycles} => {
+                let sizes = Multigrid::level_sizes(self.tex_pressure.size, MULTIGRID_MIN_SIZE);
+                for _ in 0..cycles{
+                    self.tex_pressure = Self::v_cycle(&self.tex_pressure, &sizes, 0., -1.);
+                }
+            }
+        }
+
+        let p = &self.tex_pressure;
+        self.tex_vpf = self.tex_vpf.map(|x, y|{
+            let mut vo = self.tex_vpf.get(x, y);
+            if vo.w >= 0.5{
+                vo.x -= (p.get(x + 1, y).x - p.get(x - 1, y).x) / 2.;
+                vo.y -= (p.get(x, y + 1).x - p.get(x, y - 1).x) / 2.;
+            }
+            vo
+        });
+    }
+
+    fn neighbours(f: &Field, x: i32, y: i32) -> f32{
+        f.get(x + 1, y).x + f.get(x - 1, y).x + f.get(x, y + 1).x + f.get(x, y - 1).x
+    }
+
+    ///
+    /// One Jacobi or red black Gauss-Seidel iteration of alpha * x - beta * lapl(x) = b on a
+    /// field holding x and b.
+    ///
+    fn relax(f: &Field, alpha: f32, beta: f32, red_black: bool) -> Field{
+        let jacobi = |x: i32, y: i32| (f.get(x, y).y + beta * Self::neighbours(f, x, y)) / (alpha + 4. * beta);
+        f.map(|x, y|{
+            let t = f.get(x, y);
+            if !red_black || (x + y) % 2 == 0{
+                return glm::vec4(jacobi(x, y), t.y, 0., 0.);
+            }
+            // black cells use the updated red neighbours.
+            let mut sum = 0.;
+            for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)]{
+                let (nx, ny) = (x + dx, y + dy);
+                if !f.periodic && (nx < 0 || ny < 0 || nx >= f.size[0] as i32 || ny >= f.size[1] as i32){
+                    sum += t.x;
+                }
+                else{
+                    sum += jacobi(nx, ny);
+                }
+            }
+            glm::vec4((t.y + beta * sum) / (alpha + 4. * beta), t.y, 0., 0.)
+        })
+    }
+
+    ///
+    /// Mirrors Multigrid::solve with one V-cycle over the levels of the given sizes.
+    ///
+    fn v_cycle(f: &Field, sizes: &[[u32; 2]], alpha: f32, beta: f32) -> Field{
+        let (pre_smooth, post_smooth, coarse_smooth) = Multigrid::DEFAULT_SMOOTHING;
+        let mut f = f.clone();
+
+        if sizes.len() == 1{
+            for _ in 0..coarse_smooth{
+                f = Self::relax(&f, alpha, beta, true);
+            }
+            return f;
+        }
+
+        for _ in 0..pre_smooth{
+            f = Self::relax(&f, alpha, beta, true);
+        }
+
+        // restrict the residual.
+        let residual = |x: i32, y: i32|{
+            let t = f.get(x, y);
+            t.y - (alpha + 4. * beta) * t.x + beta * Self::neighbours(&f, x, y)
+        };
+        // along an odd size the last coarse cell only covers one fine cell.
+        let coarse = Field::new(sizes[1]).with_periodic(f.periodic).map(|x, y|{
+            let mut res = 0.;
+            let mut children = 0.;
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)]{
+                let (fx, fy) = (x * 2 + dx, y * 2 + dy);
+                if fx < f.size[0] as i32 && fy < f.size[1] as i32{
+                    res += residual(fx, fy);
+                    children += 1.;
+                }
+            }
+            glm::vec4(0., res / children, 0., 0.)
+        });
+
+        let e = Self::v_cycle(&coarse, &sizes[1..], alpha, beta / 4.);
+
+        // add the error of the coarser level.
+        let scale = glm::vec2(e.size[0] as f32 / f.size[0] as f32, e.size[1] as f32 / f.size[1] as f32);
+        let mut f = f.map(|x, y|{
+            let t = f.get(x, y);
+            let pos = glm::vec2(x as f32 + 0.5, y as f32 + 0.5).component_mul(&scale);
+            glm::vec4(t.x + e.sample(pos).x, t.y, 0., 0.)
+        });
+
+        for _ in 0..post_smooth{
+            f = Self::relax(&f, alpha, beta, true);
+        }
+        f
+    }
+
+    ///
+    /// Mirrors vf_capillary.glsl.
+    ///
+    fn capillary(&mut self){
+        let p = self.params;
+        let mut o_vpf = Field::new(self.tex_vpf.size).with_periodic(self.tex_vpf.periodic);
+        let mut o_sat = Field::new(self.tex_vpf.size).with_periodic(self.tex_vpf.periodic);
+
+        for y in 0..self.tex_vpf.size[1] as i32{
+            for x in 0..self.tex_vpf.size[0] as i32{
+                let mut vo = self.tex_vpf.get(x, y);
+                let s0 = self.tex_sat.get(x, y).x;
+                let c = self.capacity(x, y);
+
+                // diffusion.
+                let mut s = s0;
+                for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)]{
+                    let sn = self.tex_sat.get(x + dx, y + dy).x;
+                    s -= self.flow(s0, sn, self.capacity(x + dx, y + dy));
+                    s += self.flow(sn, s0, c);
+                }
+
+                // absorption.
+                if vo.w >= 0.5{
+                    let absorbed = (p.dt * p.cap_absorb).min((c - s).min(vo.z - p.hmin)).max(0.);
+                    s += absorbed;
+                    vo.z -= absorbed;
+                }
+
+                // drying and backruns.
+                if vo.w < 0.5{
+                    s = (s - p.dt * p.cap_evap).max(0.);
+                }
+                if self.solid(x, y){
+                    s = 0.;
+                }
+                if s > p.cap_sigma{
+                    vo.w = 1.;
+                }
+
+                let i = (y as u32 * self.tex_vpf.size[0] + x as u32) as usize;
+                o_vpf.data[i] = vo.into();
+                o_sat.data[i] = [s, c, 0., 0.];
+            }
+        }
+
+        self.tex_vpf = o_vpf;
+        self.tex_sat = o_sat;
+    }
+
+    ///
+    /// Mirrors vf_blurwv.glsl and vf_blurwh.glsl, blurring the wet mask in direction dir.
+    ///
+    fn blur_w(vpf: &Field, dir: glm::Vec2) -> Field{
+        vpf.map(|x, y|{
+            let mut o = vpf.get(x, y);
+            let mut w = o.w * BLUR_COEFF[0];
+            for i in 1..5{
+                let (dx, dy) = (dir.x as i32 * i, dir.y as i32 * i);
+                w += vpf.get(x + dx, y + dy).w * BLUR_COEFF[i as usize];
+                w += vpf.get(x - dx, y - dy).w * BLUR_COEFF[i as usize];
+            }
+            o.w = w;
+            o
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    const SIZE: [u32; 2] = [24, 24];
+
+    ///
+    /// Parameters without sources and sinks of water and with walls at the edges of the canvas.
+    ///
+    fn closed_params() -> PaintParams{
+        PaintParams{
+            evap_nu: 0.,
+            cap_absorb: 0.,
+            edge_darkening: 0.,
+            boundary: Boundary::FreeSlip.to_param(),
+            ..Default::default()
+        }
+    }
+
+    fn water(sim: &ReferenceSim) -> f32{
+        sim.tex_vpf.data.iter().map(|t| t[2]).sum()
+    }
+
+    fn pigment(sim: &ReferenceSim) -> [f32; 4]{
+        let mut sum = [0.; 4];
+        for field in sim.tex_color.iter().chain(sim.tex_float.iter()){
+            for t in &field.data{
+                for c in 0..4{
+                    sum[c] += t[c];
+                }
+            }
+        }
+        sum
+    }
+
+    ///
+    /// A wet disk of water piled up in the center of a canvas wet everywhere.
+    ///
+    fn wet_disk(sim: &mut ReferenceSim){
+        let hmin = sim.params.hmin;
+        let center = glm::vec2(SIZE[0] as f32 / 2., SIZE[1] as f32 / 2.);
+        sim.tex_vpf = sim.tex_vpf.map(|x, y|{
+            let d = (glm::vec2(x as f32 + 0.5, y as f32 + 0.5) - center).norm();
+            glm::vec4(0., 0., hmin + 1. + (-d * d / 16.).exp(), 1.)
+        });
+    }
+
+    #[test]
+    fn water_is_conserved_without_open_boundaries(){
+        let mut sim = ReferenceSim::new(SIZE, closed_params());
+        wet_disk(&mut sim);
+
+        let before = water(&sim);
+        for _ in 0..40{
+            sim.step();
+        }
+        let after = water(&sim);
+
+        assert!(sim.tex_vpf.data.iter().any(|t| t[0] != 0. || t[1] != 0.), "the water did not flow");
+        assert!((after - before).abs() <= 1e-3 * before, "water changed from {} to {}", before, after);
+    }
+
+    ///
+    /// Semi-Lagrangian advection does not conserve the pigment in a diverging flow, so the water
+    /// stands still and only the diffusion and the transfer move the pigment.
+    ///
+    #[test]
+    fn pigment_is_conserved_without_open_boundaries(){
+        let mut sim = ReferenceSim::new(SIZE, closed_params());
+        let hmin = sim.params.hmin;
+        sim.tex_vpf = sim.tex_vpf.map(|_, _| glm::vec4(0., 0., hmin + 1., 1.));
+        for layer in 0..PIGMENT_LAYERS as usize{
+            sim.tex_float[layer] = sim.tex_float[layer].map(|x, y|{
+                if (x - 12).abs() < 4 && (y - 10).abs() < 4 {glm::vec4(0.5, 0.25, 0.1, 0.05)} else {glm::Vec4::zeros()}
+            });
+        }
+
+        let before = pigment(&sim);
+        for _ in 0..40{
+            sim.step();
+        }
+        let after = pigment(&sim);
+
+        assert!(sim.tex_color.iter().any(|field| field.data.iter().any(|t| t[0] > 0.)), "no pigment was deposited");
+        for c in 0..4{
+            assert!((after[c] - before[c]).abs() <= 1e-4 * before[c], "pigment {} changed from {} to {}", c, before[c], after[c]);
+        }
+    }
+
+    ///
+    /// On a periodic canvas a wash on the corner, which flows across the edges, has to evolve
+    /// like the same wash moved into the middle of the canvas.
+    ///
+    #[test]
+    fn periodic_wash_flows_across_the_edges(){
+        // a multiple of 2 keeps the red black parity.
+        let offset = [8, 12];
+
+        let mut corner = ReferenceSim::new(SIZE, PaintParams::default());
+        corner.set_periodic(true);
+        let hmin = corner.params.hmin;
+        let (w, h) = (SIZE[0] as i32, SIZE[1] as i32);
+        // squared distance to the corner across the edges.
+        let d2 = |x: i32, y: i32|{
+            let dx = (x.min(w - x)) as f32;
+            let dy = (y.min(h - y)) as f32;
+            dx * dx + dy * dy
+        };
+        corner.tex_vpf = corner.tex_vpf.map(|x, y| glm::vec4(0., 0., hmin + 0.5 + (-d2(x, y) / 8.).exp(), 1.));
+        corner.tex_float[0] = corner.tex_float[0].map(|x, y| glm::vec4(1., 0., 0., 0.) * (-d2(x, y) / 4.).exp());
+
+        let mut moved = ReferenceSim::new(SIZE, PaintParams::default());
+        moved.tex_vpf = corner.tex_vpf.translate(offset);
+        moved.tex_float[0] = corner.tex_float[0].translate(offset);
+        moved.set_periodic(true);
+
+        for _ in 0..20{
+            corner.step();
+            moved.step();
+        }
+
+        // the water flowed out of the corner into both the first and the last column.
+        let vx = |x: i32| (0..h).map(|y| corner.tex_vpf.get(x, y).x.abs()).sum::<f32>();
+        assert!(vx(0) > 0. && vx(w - 1) > 0., "no water flowed across the edges");
+
+        let fields = [(&corner.tex_vpf, &moved.tex_vpf), (&corner.tex_sat, &moved.tex_sat)].into_iter()
+            .chain(corner.tex_color.iter().zip(moved.tex_color.iter()))
+            .chain(corner.tex_float.iter().zip(moved.tex_float.iter()));
+        for (field, moved) in fields{
+            let diff = field.translate(offset).max_abs_diff(moved);
+            assert!(diff.iter().all(|d| *d <= 1e-5), "the periodic wash differs from the moved one by {:?}", diff);
+        }
+    }
+}
@@ -0,0 +1,136 @@
+#version 460
+// #############################################################################
+// Parallel reduction of the channels of a texture to their sum, minimum and maximum.
+//
+// PASS_TEXELS reduces every 16x16 tile of every layer to a partial result, PASS_PARTIALS reduces
+// the partial results of every layer with one workgroup per layer.
+// #############################################################################
+
+#define GROUP_SIZE 256
+#define INF 3.4e38
+
+struct Partial{
+    vec4 sum;
+    vec4 min;
+    vec4 max;
+};
+
+shared vec4 s_sum[GROUP_SIZE];
+shared vec4 s_min[GROUP_SIZE];
+shared vec4 s_max[GROUP_SIZE];
+
+#if PASS_TEXELS
+#define BUFFER_SET 1
+
+#if TEXTURE_ARRAY
+layout(set = 0, binding = 0) uniform texture2DArray t_tex;
+#else
+layout(set = 0, binding = 0) uniform texture2D t_tex;
+#endif
+layout(set = 0, binding = 1) uniform sampler s_tex;
+
+#else
+#define BUFFER_SET 0
+#endif
+
+layout(set = BUFFER_SET, binding = 0) buffer Partials{
+    Partial partials[];
+};
+
+layout(set = BUFFER_SET, binding = 1) buffer Results{
+    Partial results[];
+};
+
+void reduce(uint i){
+    memoryBarrierShared();
+    barrier();
+    for(uint s = GROUP_SIZE / 2; s > 0; s >>= 1){
+        if(i < s){
+            s_sum[i] += s_sum[i + s];
+            s_min[i] = min(s_min[i], s_min[i + s]);
+            s_max[i] = max(s_max[i], s_max[i + s]);
+        }
+        memoryBarrierShared();
+        barrier();
+    }
+}
+
+#if PASS_TEXELS
+
+layout(local_size_x = 16, local_size_y = 16) in;
+
+void main(){
+    uint i = gl_LocalInvocationIndex;
+    uint layer = gl_WorkGroupID.z;
+    ivec2 id = ivec2(gl_GlobalInvocationID.xy);
+
+#if TEXTURE_ARRAY
+    ivec2 size = textureSize(sampler2DArray(t_tex, s_tex), 0).xy;
+#else
+    ivec2 size = textureSize(sampler2D(t_tex, s_tex), 0);
+#endif
+
+    if(all(lessThan(id, size))){
+#if TEXTURE_ARRAY
+        vec4 x = texelFetch(sampler2DArray(t_tex, s_tex), ivec3(id, int(layer)), 0);
+#else
+        vec4 x = texelFetch(sampler2D(t_tex, s_tex), id, 0);
+#endif
+#if VPF
+        // speed in x and 1 in w for the cells where the blurred wet area mask is at least 0.5.
+        x.x = length(x.xy);
+        x.w = step(0.5, x.w);
+#endif
+        s_sum[i] = x;
+        s_min[i] = x;
+        s_max[i] = x;
+    }
+    else{
+        s_sum[i] = vec4(0.);
+        s_min[i] = vec4(INF);
+        s_max[i] = vec4(-INF);
+    }
+
+    reduce(i);
+
+    if(i == 0){
+        uint group = (layer * gl_NumWorkGroups.y + gl_WorkGroupID.y) * gl_NumWorkGroups.x + gl_WorkGroupID.x;
+        partials[group].sum = s_sum[0];
+        partials[group].min = s_min[0];
+        partials[group].max = s_max[0];
+    }
+}
+
+#endif
+#if PASS_PARTIALS
+
+layout(local_size_x = GROUP_SIZE) in;
+
+void main(){
+    uint i = gl_LocalInvocationIndex;
+    uint layer = gl_WorkGroupID.x;
+    uint n = partials.length() / gl_NumWorkGroups.x;
+
+    vec4 sum = vec4(0.);
+    vec4 mi = vec4(INF);
+    vec4 ma = vec4(-INF);
+    for(uint j = i; j < n; j += GROUP_SIZE){
+        Partial p = partials[layer * n + j];
+        sum += p.sum;
+        mi = min(mi, p.min);
+        ma = max(ma, p.max);
+    }
+    s_sum[i] = sum;
+    s_min[i] = mi;
+    s_max[i] = ma;
+
+    reduce(i);
+
+    if(i == 0){
+        results[layer].sum = s_sum[0];
+        results[layer].min = s_min[0];
+        results[layer].max = s_max[0];
+    }
+}
+
+#endif
@@ -22,13 +22,7 @@ void main(){
 }
 
 #endif
-#if FRAGMENT_SHADER
-
-layout(location = 0) in vec2 f_pos;
-layout(location = 1) in vec2 f_uv;
-layout(location = 2) in vec2 r;
-
-layout(location = 0) out vec4 o;
+#if FRAGMENT_SHADER || COMPUTE_SHADER
 
 layout(set = 0, binding = 0) uniform GlobalData{
     vec2 size;
@@ -62,9 +56,40 @@ vec4 blur5(vec2 r, vec2 dir, texture2D t, sampler s){
     return res;
 }
 
-void main(){
+// Blurs the wet mask at r.
+vec4 blur_w(vec2 r){
+    vec4 o;
     o.xyz = tex(r, tex_vpf).xyz;
     o.w = blur5(r, vec2(0, 1), tex_vpf).w;
+    return o;
+}
+
+#endif
+#if FRAGMENT_SHADER
+
+layout(location = 0) in vec2 f_pos;
+layout(location = 1) in vec2 f_uv;
+layout(location = 2) in vec2 r;
+
+layout(location = 0) out vec4 o;
+
+void main(){
+    o = blur_w(r);
+}
+
+#endif
+#if COMPUTE_SHADER
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+layout(set = 2, binding = 0, rgba32f) uniform writeonly image2D i_vpf;
+
+void main(){
+    ivec2 id = ivec2(gl_GlobalInvocationID.xy);
+    if(any(greaterThanEqual(id, ivec2(global_data.size))))
+        return;
+
+    imageStore(i_vpf, id, blur_w(vec2(id) + 0.5));
 }
 
 #endif
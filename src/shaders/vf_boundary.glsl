@@ -0,0 +1,136 @@
+#version 460
+#if VERTEX_SHADER
+
+layout(location = 0) in vec2 i_pos;
+layout(location = 1) in vec2 i_uv;
+
+layout(location = 0) out vec2 f_pos;
+layout(location = 1) out vec2 f_uv;
+layout(location = 2) out vec2 r;
+
+layout(set = 0, binding = 0) uniform GlobalData{
+    vec2 size;
+    float time;
+} global_data;
+
+void main(){
+    f_pos = i_pos;
+    f_uv = i_uv;
+    r = i_uv * global_data.size;
+
+    gl_Position = vec4(i_pos, 0.0, 1.0);
+}
+
+#endif
+#if FRAGMENT_SHADER || COMPUTE_SHADER
+// #############################################################################
+// Reapplies the boundary conditions of vf_paint04.glsl after the passes that
+// change the velocity field following the main step.
+// #############################################################################
+
+layout(set = 0, binding = 0) uniform GlobalData{
+    vec2 size;
+    float time;
+} global_data;
+
+layout(set = 0, binding = 1) uniform PaintParams{
+    vec2 vmax;
+    float dt;
+    float K;
+    float nu;
+    float kappa;
+    float evap_nu;
+    float hmin;
+    float float_nu;
+    float paper_slope;
+    float paper_settle;
+    float cap_min;
+    float cap_max;
+    float cap_absorb;
+    float cap_epsilon;
+    float cap_delta;
+    float cap_sigma;
+    float cap_evap;
+    float edge_darkening;
+    uint projection;
+    uint advection;
+    uint limiter;
+    float vorticity;
+    uint boundary;
+} params;
+
+layout(set = 1, binding = 0) uniform texture2D t_tex_vpf;
+layout(set = 1, binding = 1) uniform sampler s_tex_vpf;
+// w: 1 inside of obstacles.
+layout(set = 0, binding = 4) uniform texture2D t_tex_paper;
+layout(set = 0, binding = 5) uniform sampler s_tex_paper;
+
+#define tex_vpf t_tex_vpf, s_tex_vpf
+#define tex_paper t_tex_paper, s_tex_paper
+
+#define BOUNDARY_OPEN 0
+#define BOUNDARY_FREE_SLIP 1
+#define BOUNDARY_NO_SLIP 2
+
+vec4 tex(vec2 pos, texture2D t, sampler s){
+    return textureLod(sampler2D(t, s), pos/global_data.size, 0);
+}
+
+// A periodic canvas (PERIODIC is 1) has no edges.
+bool outside(vec2 pos){
+    return PERIODIC == 0 && (any(lessThan(pos, vec2(0.))) || any(greaterThanEqual(pos, global_data.size)));
+}
+
+bool solid(vec2 pos){
+    return tex(pos, tex_paper).w >= 0.5;
+}
+
+// Removes the velocity at r flowing into walls next to it.
+vec2 apply_boundary(vec2 r, vec2 vel){
+    vec2 n[4] = {vec2(1., 0.), vec2(-1., 0.), vec2(0., 1.), vec2(0., -1.)};
+    for(int i = 0; i < 4; i++){
+        vec2 pos = r + n[i];
+        bool edge = outside(pos);
+        if(edge && params.boundary == BOUNDARY_NO_SLIP)
+            return vec2(0.);
+        if(edge? params.boundary != BOUNDARY_OPEN: solid(pos))
+            vel -= max(dot(vel, n[i]), 0.) * n[i];
+    }
+    return vel;
+}
+
+vec4 boundary(vec2 r){
+    vec4 vo = tex(r, tex_vpf);
+    vo.xy = solid(r)? vec2(0.): apply_boundary(r, vo.xy);
+    return vo;
+}
+
+#endif
+#if FRAGMENT_SHADER
+
+layout(location = 0) in vec2 f_pos;
+layout(location = 1) in vec2 f_uv;
+layout(location = 2) in vec2 r;
+
+layout(location = 0) out vec4 o;
+
+void main(){
+    o = boundary(r);
+}
+
+#endif
+#if COMPUTE_SHADER
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+layout(set = 2, binding = 0, rgba32f) uniform writeonly image2D i_vpf;
+
+void main(){
+    ivec2 id = ivec2(gl_GlobalInvocationID.xy);
+    if(any(greaterThanEqual(id, ivec2(global_data.size))))
+        return;
+
+    imageStore(i_vpf, id, boundary(vec2(id) + 0.5));
+}
+
+#endif
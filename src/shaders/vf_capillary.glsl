@@ -0,0 +1,161 @@
+#version 460
+#if VERTEX_SHADER
+
+layout(location = 0) in vec2 i_pos;
+layout(location = 1) in vec2 i_uv;
+
+layout(location = 0) out vec2 f_pos;
+layout(location = 1) out vec2 f_uv;
+layout(location = 2) out vec2 r;
+
+layout(set = 0, binding = 0) uniform GlobalData{
+    vec2 size;
+    float time;
+} global_data;
+
+void main(){
+    f_pos = i_pos;
+    f_uv = i_uv;
+    r = i_uv * global_data.size;
+
+    gl_Position = vec4(i_pos, 0.0, 1.0);
+}
+
+#endif
+#if FRAGMENT_SHADER || COMPUTE_SHADER
+
+layout(set = 0, binding = 0) uniform GlobalData{
+    vec2 size;
+    float time;
+} global_data;
+
+layout(set = 0, binding = 1) uniform PaintParams{
+    vec2 vmax;
+    float dt;
+    float K;
+    float nu;
+    float kappa;
+    float evap_nu;
+    float hmin;
+    float float_nu;
+    float paper_slope;
+    float paper_settle;
+    float cap_min;
+    float cap_max;
+    float cap_absorb;
+    float cap_epsilon;
+    float cap_delta;
+    float cap_sigma;
+    float cap_evap;
+} params;
+
+layout(set = 1, binding = 0) uniform texture2D t_tex_vpf;
+layout(set = 1, binding = 1) uniform sampler s_tex_vpf;
+// x: saturation of the paper.
+layout(set = 2, binding = 0) uniform texture2D t_tex_sat;
+layout(set = 2, binding = 1) uniform sampler s_tex_sat;
+layout(set = 0, binding = 4) uniform texture2D t_tex_paper;
+layout(set = 0, binding = 5) uniform sampler s_tex_paper;
+
+#define tex_vpf t_tex_vpf, s_tex_vpf
+#define tex_sat t_tex_sat, s_tex_sat
+#define tex_paper t_tex_paper, s_tex_paper
+
+vec4 tex(vec2 pos, texture2D t, sampler s){
+    return textureLod(sampler2D(t, s), pos/global_data.size, 0);
+}
+
+// Water capacity of the paper, higher where the paper is thick and zero inside of obstacles.
+float capacity(vec2 pos){
+    vec4 paper = tex(pos, tex_paper);
+    return (paper.x * (params.cap_max - params.cap_min) + params.cap_min) * (1. - paper.w);
+}
+
+// Water flowing from a cell with saturation s0 to a neighbour with saturation s1 and capacity c1.
+float flow(float s0, float s1, float c1){
+    if(s0 > params.cap_epsilon && s0 > s1 && s1 >= params.cap_delta)
+        return max(0., min(s0 - s1, c1 - s1) / 4.);
+    return 0.;
+}
+
+// Capillary flow at the cell center r.
+void capillary(vec2 r, out vec4 vo, out vec4 o_sat){
+    float dt = params.dt;
+
+    vo = tex(r, tex_vpf);
+    float s0 = tex(r, tex_sat).x;
+    float c = capacity(r);
+
+    // -----------------------------------------------------------------------------
+    // Diffusion:
+    // water moves through the paper from saturated to less saturated cells (Curtis et al.).
+    vec2 n[4] = {vec2(1., 0.), vec2(-1., 0.), vec2(0., 1.), vec2(0., -1.)};
+    float s = s0;
+    for(int i = 0; i < 4; i++){
+        float sn = tex(r + n[i], tex_sat).x;
+        s -= flow(s0, sn, capacity(r + n[i]));
+        s += flow(sn, s0, c);
+    }
+
+    // -----------------------------------------------------------------------------
+    // Absorption:
+    // the paper absorbs water from the shallow water layer up to its capacity.
+    if(vo.w >= 0.5){
+        float absorbed = max(0., min(dt * params.cap_absorb, min(c - s, vo.z - params.hmin)));
+        s += absorbed;
+        vo.z -= absorbed;
+    }
+
+    // -----------------------------------------------------------------------------
+    // Drying and backruns:
+    if(vo.w < 0.5){
+        s = max(s - dt * params.cap_evap, 0.);
+    }
+    // Obstacles hold no water.
+    if(tex(r, tex_paper).w >= 0.5){
+        s = 0.;
+    }
+    // Saturated paper becomes part of the wet area which lets water flow into damp regions.
+    if(s > params.cap_sigma){
+        vo.w = 1.;
+    }
+
+    o_sat = vec4(s, c, 0., 0.);
+}
+
+#endif
+#if FRAGMENT_SHADER
+
+layout(location = 0) in vec2 f_pos;
+layout(location = 1) in vec2 f_uv;
+layout(location = 2) in vec2 r;
+
+layout(location = 0) out vec4 vo;
+layout(location = 1) out vec4 o_sat;
+
+void main(){
+    capillary(r, vo, o_sat);
+}
+
+#endif
+#if COMPUTE_SHADER
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+layout(set = 3, binding = 0, rgba32f) uniform writeonly image2D i_vpf;
+layout(set = 3, binding = 1, rgba32f) uniform writeonly image2D i_sat;
+
+void main(){
+    ivec2 id = ivec2(gl_GlobalInvocationID.xy);
+    if(any(greaterThanEqual(id, ivec2(global_data.size))))
+        return;
+
+    vec4 vo;
+    vec4 o_sat;
+    capillary(vec2(id) + 0.5, vo, o_sat);
+
+    imageStore(i_vpf, id, vo);
+    imageStore(i_sat, id, o_sat);
+}
+
+#endif
@@ -0,0 +1,132 @@
+#version 460
+#if VERTEX_SHADER
+
+layout(location = 0) in vec2 i_pos;
+layout(location = 1) in vec2 i_uv;
+
+layout(location = 0) out vec2 f_pos;
+layout(location = 1) out vec2 f_uv;
+
+void main(){
+    f_pos = i_pos;
+    f_uv = i_uv;
+
+    gl_Position = vec4(i_pos, 0.0, 1.0);
+}
+#endif
+#if FRAGMENT_SHADER
+
+layout(location = 0) in vec2 f_pos;
+layout(location = 1) in vec2 f_uv;
+
+layout(location = 0) out vec4 o_color;
+
+// MAX_PIGMENTS, PIGMENT_LAYERS and MAX_GLAZES are defined by PigmentDefines.
+
+layout(set = 0, binding = 3) uniform Pigments{
+    vec4 density[PIGMENT_LAYERS];
+    vec4 staining[PIGMENT_LAYERS];
+    vec4 granulation[PIGMENT_LAYERS];
+    vec4 absorption[MAX_PIGMENTS];
+    vec4 scattering[MAX_PIGMENTS];
+    uint count;
+} pigments;
+
+layout(set = 1, binding = 0) uniform CompositeParams{
+    vec4 paper_color;
+    float thickness_scale;
+} composite;
+
+layout(set = 2, binding = 0) uniform texture2DArray t_tex_color;
+layout(set = 2, binding = 1) uniform sampler s_tex_color;
+layout(set = 2, binding = 2) uniform texture2DArray t_tex_float;
+layout(set = 2, binding = 3) uniform sampler s_tex_float;
+// PIGMENT_LAYERS layers per glaze starting with the bottom most glaze.
+layout(set = 3, binding = 0) uniform texture2DArray t_tex_glazes;
+layout(set = 3, binding = 1) uniform sampler s_tex_glazes;
+
+vec4 texa(vec2 uv, int layer, texture2DArray t, sampler s){
+    return texture(sampler2DArray(t, s), vec3(uv, layer));
+}
+
+// Reflectance R and transmittance T of a layer with thickness x (Kubelka-Munk).
+void km(vec3 K, vec3 S, float x, out vec3 R, out vec3 T){
+    // Avoid the singularity of pigments without scattering.
+    S = max(S, vec3(1e-4));
+
+    vec3 a = (S + K) / S;
+    vec3 b = sqrt(a * a - 1.);
+    // sinh and cosh overflow for thick layers, R and T have converged long before.
+    vec3 bsx = min(b * S * x, vec3(20.));
+    vec3 sh = sinh(bsx);
+    vec3 ch = cosh(bsx);
+    vec3 c = a * sh + b * ch;
+
+    R = sh / c;
+    T = b / c;
+}
+
+// Composite a layer (R1, T1) on top of a stack (R, T) (Kubelka-Munk).
+void km_composite(vec3 R1, vec3 T1, inout vec3 R, inout vec3 T){
+    vec3 d = 1. - R1 * R;
+    R = R1 + T1 * T1 * R / d;
+    T = T1 * T / d;
+}
+
+// Mix the 4 pigments stored in one layer (layer * 4 + channel) into the absorption K and
+// scattering S of a wash, weighted by their amount.
+void km_mix_layer(int layer, vec4 amount, inout vec3 K, inout vec3 S, inout float total){
+    for(int c = 0; c < 4; c++){
+        uint i = uint(layer * 4 + c);
+        if(i >= pigments.count || amount[c] <= 0.)
+            continue;
+
+        K += amount[c] * pigments.absorption[i].rgb;
+        S += amount[c] * pigments.scattering[i].rgb;
+        total += amount[c];
+    }
+}
+
+// Composite a wash with the mixed K and S on top of the stack. The amounts are already part of
+// K and S, so a single pigment gives the same layer as with the thickness scaled by its amount.
+void km_composite_wash(vec3 K, vec3 S, float total, inout vec3 R, inout vec3 T){
+    if(total <= 0.)
+        return;
+
+    vec3 R1;
+    vec3 T1;
+    km(K, S, composite.thickness_scale, R1, T1);
+    km_composite(R1, T1, R, T);
+}
+
+void main(){
+    vec3 R = composite.paper_color.rgb;
+    vec3 T = vec3(0.);
+
+    // The dried glazes from bottom to top, the pigments of a glaze are mixed into one layer on
+    // top of the paper.
+    for(int glaze = 0; glaze < MAX_GLAZES; glaze++){
+        vec3 K = vec3(0.);
+        vec3 S = vec3(0.);
+        float total = 0.;
+        for(int layer = 0; layer < PIGMENT_LAYERS; layer++){
+            vec4 amount = texa(f_uv, glaze * PIGMENT_LAYERS + layer, t_tex_glazes, s_tex_glazes);
+            km_mix_layer(layer, amount, K, S, total);
+        }
+        km_composite_wash(K, S, total, R, T);
+    }
+
+    // The wet layer on top.
+    vec3 K = vec3(0.);
+    vec3 S = vec3(0.);
+    float total = 0.;
+    for(int layer = 0; layer < PIGMENT_LAYERS; layer++){
+        vec4 amount = texa(f_uv, layer, t_tex_color, s_tex_color) + texa(f_uv, layer, t_tex_float, s_tex_float);
+        km_mix_layer(layer, amount, K, S, total);
+    }
+    km_composite_wash(K, S, total, R, T);
+
+    // The render target is expected to be sRGB so the reflectance is written linear.
+    o_color = vec4(clamp(R, 0., 1.), 1.);
+}
+#endif
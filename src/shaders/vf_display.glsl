@@ -27,18 +27,23 @@ layout(set = 0, binding = 0) uniform GlobalData{
 
 layout(set = 1, binding = 0) uniform texture2D t_tex_vpf;
 layout(set = 1, binding = 1) uniform sampler s_tex_vpf;
-layout(set = 2, binding = 0) uniform texture2D t_tex_color;
+layout(set = 2, binding = 0) uniform texture2DArray t_tex_color;
 layout(set = 2, binding = 1) uniform sampler s_tex_color;
-layout(set = 3, binding = 0) uniform texture2D t_tex_float;
+layout(set = 3, binding = 0) uniform texture2DArray t_tex_float;
 layout(set = 3, binding = 1) uniform sampler s_tex_float;
 
+// first layer of the pigment texture arrays.
+vec4 layer0(texture2DArray t, sampler s, vec2 uv){
+    return texture(sampler2DArray(t, s), vec3(uv, 0.));
+}
+
 void main(){
     o_color = vec4(0.0, 0.0, 0.0, 1.0);
 
     vec4 tex_vpf = texture(sampler2D(t_tex_vpf, s_tex_vpf), f_uv * 2. - vec2(0., 0.));
-    vec4 tex_color = texture(sampler2D(t_tex_color, s_tex_color), f_uv * 2. - vec2(1., 0.));
-    vec4 tex_float = texture(sampler2D(t_tex_float, s_tex_float), f_uv * 2. - vec2(0., 1.));
-    vec4 tex_cf = texture(sampler2D(t_tex_color, s_tex_color), f_uv * 2. - vec2(1., 0.)) + texture(sampler2D(t_tex_float, s_tex_float), f_uv * 2. - vec2(1., 0.));
+    vec4 tex_color = layer0(t_tex_color, s_tex_color, f_uv * 2. - vec2(1., 0.));
+    vec4 tex_float = layer0(t_tex_float, s_tex_float, f_uv * 2. - vec2(0., 1.));
+    vec4 tex_cf = layer0(t_tex_color, s_tex_color, f_uv * 2. - vec2(1., 0.)) + layer0(t_tex_float, s_tex_float, f_uv * 2. - vec2(1., 0.));
     float tex_f = texture(sampler2D(t_tex_vpf, s_tex_vpf), f_uv * 2. - vec2(1., 1.)).w;
 
     if(f_uv.x < 0.5 && f_uv.y < 0.5){
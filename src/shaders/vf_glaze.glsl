@@ -0,0 +1,59 @@
+#version 460
+#if VERTEX_SHADER
+
+layout(location = 0) in vec2 i_pos;
+layout(location = 1) in vec2 i_uv;
+
+layout(location = 0) out vec2 f_pos;
+layout(location = 1) out vec2 f_uv;
+
+void main(){
+    f_pos = i_pos;
+    f_uv = i_uv;
+
+    gl_Position = vec4(i_pos, 0.0, 1.0);
+}
+#endif
+#if FRAGMENT_SHADER
+// #############################################################################
+// Dries the current layer into a glaze: the floating pigment settles onto the paper and is
+// added to the deposited pigment.
+// #############################################################################
+
+layout(location = 0) in vec2 f_pos;
+layout(location = 1) in vec2 f_uv;
+
+// one output per layer of the glaze.
+layout(location = 0) out vec4 o_glaze0;
+#if PIGMENT_LAYERS > 1
+layout(location = 1) out vec4 o_glaze1;
+#endif
+#if PIGMENT_LAYERS > 2
+layout(location = 2) out vec4 o_glaze2;
+#endif
+#if PIGMENT_LAYERS > 3
+#error at most 3 pigment layers are supported
+#endif
+
+layout(set = 0, binding = 0) uniform texture2DArray t_tex_color;
+layout(set = 0, binding = 1) uniform sampler s_tex_color;
+layout(set = 0, binding = 2) uniform texture2DArray t_tex_float;
+layout(set = 0, binding = 3) uniform sampler s_tex_float;
+
+vec4 dry(ivec2 p, int layer){
+    return texelFetch(sampler2DArray(t_tex_color, s_tex_color), ivec3(p, layer), 0)
+        + texelFetch(sampler2DArray(t_tex_float, s_tex_float), ivec3(p, layer), 0);
+}
+
+void main(){
+    ivec2 p = ivec2(gl_FragCoord.xy);
+
+    o_glaze0 = dry(p, 0);
+#if PIGMENT_LAYERS > 1
+    o_glaze1 = dry(p, 1);
+#endif
+#if PIGMENT_LAYERS > 2
+    o_glaze2 = dry(p, 2);
+#endif
+}
+#endif
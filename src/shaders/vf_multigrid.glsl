@@ -0,0 +1,129 @@
+#version 460
+#if VERTEX_SHADER
+
+layout(location = 0) in vec2 i_pos;
+layout(location = 1) in vec2 i_uv;
+
+layout(location = 0) out vec2 f_pos;
+layout(location = 1) out vec2 f_uv;
+layout(location = 2) out vec2 r;
+
+layout(set = 0, binding = 0) uniform Level{
+    vec2 size;
+    float alpha;
+    float beta;
+} level;
+
+void main(){
+    f_pos = i_pos;
+    f_uv = i_uv;
+    r = i_uv * level.size;
+
+    gl_Position = vec4(i_pos, 0.0, 1.0);
+}
+
+#endif
+#if FRAGMENT_SHADER
+// #############################################################################
+// Passes of the multigrid solver for alpha * x - beta * lapl(x) = b, selected
+// by one of the macros PASS_SMOOTH, PASS_RESTRICT or PASS_PROLONGATE. The
+// levels wrap around their borders if PERIODIC is 1.
+// #############################################################################
+
+layout(location = 0) in vec2 f_pos;
+layout(location = 1) in vec2 f_uv;
+layout(location = 2) in vec2 r;
+
+layout(location = 0) out vec4 o;
+
+// Level written by the pass, beta is already scaled to its grid spacing.
+layout(set = 0, binding = 0) uniform Level{
+    vec2 size;
+    float alpha;
+    float beta;
+} level;
+
+// x: solution, y: right hand side.
+layout(set = 1, binding = 0) uniform texture2D t_tex;
+layout(set = 1, binding = 1) uniform sampler s_tex;
+// next coarser level, only read by PASS_PROLONGATE.
+layout(set = 2, binding = 0) uniform texture2D t_tex_coarse;
+layout(set = 2, binding = 1) uniform sampler s_tex_coarse;
+
+// Texel of tex clamped to the edge which gives a zero gradient across the border, or wrapped
+// around it.
+vec4 fetch(ivec2 id){
+    ivec2 size = textureSize(sampler2D(t_tex, s_tex), 0);
+#if PERIODIC
+    // ids are at most one level size outside of the texture.
+    return texelFetch(sampler2D(t_tex, s_tex), (id + size) % size, 0);
+#else
+    return texelFetch(sampler2D(t_tex, s_tex), clamp(id, ivec2(0), size - 1), 0);
+#endif
+}
+
+float neighbours(ivec2 id){
+    return fetch(id + ivec2(1, 0)).x
+        + fetch(id + ivec2(-1, 0)).x
+        + fetch(id + ivec2(0, 1)).x
+        + fetch(id + ivec2(0, -1)).x;
+}
+
+// Jacobi update of the solution at id.
+float relax(ivec2 id, float alpha, float beta){
+    return (fetch(id).y + beta * neighbours(id)) / (alpha + 4. * beta);
+}
+
+float residual(ivec2 id, float alpha, float beta){
+    vec4 t = fetch(id);
+    return t.y - (alpha + 4. * beta) * t.x + beta * neighbours(id);
+}
+
+void main(){
+    ivec2 id = ivec2(floor(r));
+#if PASS_SMOOTH
+    // One red black Gauss-Seidel iteration, black cells recompute the updated values of their
+    // red neighbours (see vf_pressure.glsl).
+    vec4 t = fetch(id);
+    if((id.x + id.y) % 2 == 0){
+        o = vec4(relax(id, level.alpha, level.beta), t.y, 0., 0.);
+        return;
+    }
+
+    ivec2 size = textureSize(sampler2D(t_tex, s_tex), 0);
+    ivec2 n[4] = {ivec2(1, 0), ivec2(-1, 0), ivec2(0, 1), ivec2(0, -1)};
+    float sum = 0.;
+    for(int i = 0; i < 4; i++){
+        ivec2 nid = id + n[i];
+        if(PERIODIC == 0 && (any(lessThan(nid, ivec2(0))) || any(greaterThanEqual(nid, size))))
+            sum += t.x;
+        else
+            sum += relax(nid, level.alpha, level.beta);
+    }
+    o = vec4((t.y + level.beta * sum) / (level.alpha + 4. * level.beta), t.y, 0., 0.);
+#elif PASS_RESTRICT
+    // tex is the finer level with half the grid spacing.
+    float beta = 4. * level.beta;
+    ivec2 size = textureSize(sampler2D(t_tex, s_tex), 0);
+    // Along an odd size the last coarse cell only covers one fine cell.
+    float res = 0.;
+    float children = 0.;
+    for(int y = 0; y < 2; y++){
+        for(int x = 0; x < 2; x++){
+            ivec2 fid = id * 2 + ivec2(x, y);
+            if(all(lessThan(fid, size))){
+                res += residual(fid, level.alpha, beta);
+                children += 1.;
+            }
+        }
+    }
+    // The error is solved for starting from zero.
+    o = vec4(0., res / children, 0., 0.);
+#elif PASS_PROLONGATE
+    vec4 t = fetch(id);
+    float e = textureLod(sampler2D(t_tex_coarse, s_tex_coarse), r/level.size, 0).x;
+    o = vec4(t.x + e, t.y, 0., 0.);
+#endif
+}
+
+#endif
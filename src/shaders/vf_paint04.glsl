@@ -25,48 +25,96 @@ void main(){
 }
 
 #endif
-#if FRAGMENT_SHADER
+#if FRAGMENT_SHADER || COMPUTE_SHADER
 // #############################################################################
-// FragmentShader:
+// Simulation step shared by the fragment and compute shader:
 // #############################################################################
 
-layout(location = 0) in vec2 f_pos;
-layout(location = 1) in vec2 f_uv;
-layout(location = 2) in vec2 r;
-
-layout(location = 0) out vec4 vo;
-layout(location = 1) out vec4 o_color;
-layout(location = 2) out vec4 o_float;
-
 layout(set = 0, binding = 0) uniform GlobalData{
     vec2 size;
     float time;
 } global_data;
 
+layout(set = 0, binding = 1) uniform PaintParams{
+    vec2 vmax;
+    float dt;
+    float K;
+    float nu;
+    float kappa;
+    float evap_nu;
+    float hmin;
+    float float_nu;
+    float paper_slope;
+    float paper_settle;
+    float cap_min;
+    float cap_max;
+    float cap_absorb;
+    float cap_epsilon;
+    float cap_delta;
+    float cap_sigma;
+    float cap_evap;
+    float edge_darkening;
+    // 1 if the pressure projection passes replace the density invariance correction.
+    uint projection;
+    // ADVECTION_* and LIMITER_* below.
+    uint advection;
+    uint limiter;
+    float vorticity;
+    // BOUNDARY_* below.
+    uint boundary;
+} params;
+
+// MAX_PIGMENTS and PIGMENT_LAYERS are defined by PigmentDefines.
+
+layout(set = 0, binding = 2) uniform Brush{
+    vec4 pigment[PIGMENT_LAYERS];
+    vec2 pos;
+    float radius;
+    float water;
+} brush;
+
+// Pigment i is stored in channel i % 4 of layer i / 4.
+layout(set = 0, binding = 3) uniform Pigments{
+    vec4 density[PIGMENT_LAYERS];
+    vec4 staining[PIGMENT_LAYERS];
+    vec4 granulation[PIGMENT_LAYERS];
+    vec4 absorption[MAX_PIGMENTS];
+    vec4 scattering[MAX_PIGMENTS];
+    uint count;
+} pigments;
+
 layout(set = 1, binding = 0) uniform texture2D t_tex_vpf;
 layout(set = 1, binding = 1) uniform sampler s_tex_vpf;
-layout(set = 2, binding = 0) uniform texture2D t_tex_color;
+layout(set = 2, binding = 0) uniform texture2DArray t_tex_color;
 layout(set = 2, binding = 1) uniform sampler s_tex_color;
-layout(set = 3, binding = 0) uniform texture2D t_tex_float;
-layout(set = 3, binding = 1) uniform sampler s_tex_float;
+layout(set = 2, binding = 2) uniform texture2DArray t_tex_float;
+layout(set = 2, binding = 3) uniform sampler s_tex_float;
+// x: paper height, yz: gradient of the height, w: 1 inside of obstacles.
+layout(set = 0, binding = 4) uniform texture2D t_tex_paper;
+layout(set = 0, binding = 5) uniform sampler s_tex_paper;
 
-#define VMAXX 5.0
-#define VMAXY 5.0
-#define VMAX sqrt(VMAXX * VMAXX + VMAXY * VMAXY)
-#define HMIN 0.5
+#define VMAXX params.vmax.x
+#define VMAXY params.vmax.y
+#define VMAX length(params.vmax)
+#define HMIN params.hmin
 
 #define tex_vpf t_tex_vpf, s_tex_vpf
 #define tex_color t_tex_color, s_tex_color
 #define tex_float t_tex_float, s_tex_float
+#define tex_paper t_tex_paper, s_tex_paper
 
 vec4 v(vec2 pos){
-    return texture(sampler2D(t_tex_vpf, s_tex_vpf), pos/global_data.size);
+    return textureLod(sampler2D(t_tex_vpf, s_tex_vpf), pos/global_data.size, 0);
 }
 
 vec4 tex(vec2 pos, texture2D t, sampler s){
     return textureLod(sampler2D(t, s), pos/global_data.size, 0);
 }
 
+vec4 texa(vec2 pos, int layer, texture2DArray t, sampler s){
+    return textureLod(sampler2DArray(t, s), vec3(pos/global_data.size, layer), 0);
+}
+
 vec3 to_ymc(vec3 rgb){
     return vec3(1., 1., 1.) - rgb;
 }
@@ -75,10 +123,210 @@ vec3 to_rgb(vec3 ymc){
     return vec3(1., 1., 1.) - ymc;
 }
 
-vec2 pen(float t){
-    //return vec2(300, 300);
-    //return vec2(cos(t/4.) * 200, sin(t/4.) * 200) * cos(t/10.) + vec2(300, 300);
-    return vec2(cos(t/4.) * 50, sin(t/4.) * 50) * cos(t/10.) + vec2(300, 300);
+// Boundary conditions at the edges of the canvas selected by params.boundary, obstacles
+// always act as free-slip walls:
+#define BOUNDARY_OPEN 0
+#define BOUNDARY_FREE_SLIP 1
+#define BOUNDARY_NO_SLIP 2
+
+// A periodic canvas (PERIODIC is 1) has no edges.
+bool outside(vec2 pos){
+    return PERIODIC == 0 && (any(lessThan(pos, vec2(0.))) || any(greaterThanEqual(pos, global_data.size)));
+}
+
+bool solid(vec2 pos){
+    return tex(pos, tex_paper).w >= 0.5;
+}
+
+// Removes the velocity at r flowing into walls next to it.
+vec2 apply_boundary(vec2 r, vec2 vel){
+    vec2 n[4] = {vec2(1., 0.), vec2(-1., 0.), vec2(0., 1.), vec2(0., -1.)};
+    for(int i = 0; i < 4; i++){
+        vec2 pos = r + n[i];
+        bool edge = outside(pos);
+        if(edge && params.boundary == BOUNDARY_NO_SLIP)
+            return vec2(0.);
+        if(edge? params.boundary != BOUNDARY_OPEN: solid(pos))
+            vel -= max(dot(vel, n[i]), 0.) * n[i];
+    }
+    return vel;
+}
+
+// Gaussian falloff of the brush dab at r.
+float brush_falloff(vec2 r){
+    if(brush.radius <= 0.)
+        return 0.;
+    vec2 d = r - brush.pos;
+#if PERIODIC
+    // the dab wraps around the edges.
+    d -= global_data.size * round(d / global_data.size);
+#endif
+    return exp(-dot(d, d)/(brush.radius * brush.radius));
+}
+
+// -----------------------------------------------------------------------------
+// Advection schemes selected by params.advection:
+#define ADVECTION_SEMI_LAGRANGIAN 0
+// backtrace with the velocity at the midpoint.
+#define ADVECTION_RK2 1
+#define ADVECTION_MACCORMACK 2
+#define ADVECTION_BFECC 3
+// Limiters of the MacCormack and BFECC result selected by params.limiter:
+#define LIMITER_NONE 0
+// clamp to the range of the field around the backtraced position.
+#define LIMITER_CLAMP 1
+// fall back to the semi-Lagrangian result where it would be clamped.
+#define LIMITER_REVERT 2
+
+// Field advected by advect(): -1 for tex_vpf, otherwise the layer of tex_float.
+int advect_layer;
+
+vec4 advect_field(vec2 pos){
+    if(advect_layer < 0)
+        return v(pos);
+    return texa(pos, advect_layer, tex_float);
+}
+
+vec2 advect_vel(vec2 pos){
+    return v(pos).xy;
+}
+
+// Traces pos with the velocity vel at pos over dt, backwards for a positive dt.
+vec2 trace(vec2 pos, vec2 vel, float dt){
+    if(params.advection == ADVECTION_RK2)
+        return pos - dt * advect_vel(pos - 0.5 * dt * vel);
+    return pos - dt * vel;
+}
+
+// Center of the lower left of the four cells around pos and the bilinear weights.
+void cells(vec2 pos, out vec2 c0, out vec2 f){
+    c0 = floor(pos - 0.5) + 0.5;
+    f = pos - c0;
+}
+
+vec4 bilinear(vec4 a, vec4 b, vec4 c, vec4 d, vec2 f){
+    return mix(mix(a, b, f.x), mix(c, d, f.x), f.y);
+}
+
+// Semi-Lagrangian step of the field at the cell center pos.
+vec4 advect_sl(vec2 pos){
+    return advect_field(trace(pos, advect_vel(pos), params.dt));
+}
+
+// Semi-Lagrangian step of the field interpolated at pos.
+vec4 advect_sl_at(vec2 pos){
+    vec2 c0, f;
+    cells(pos, c0, f);
+    return bilinear(advect_sl(c0), advect_sl(c0 + vec2(1., 0.)), advect_sl(c0 + vec2(0., 1.)), advect_sl(c0 + vec2(1., 1.)), f);
+}
+
+// Field advected forwards and back again at the cell center pos, the difference to the field
+// is twice the error of the semi-Lagrangian step.
+vec4 advect_round_trip(vec2 pos){
+    return advect_sl_at(trace(pos, advect_vel(pos), -params.dt));
+}
+
+// Error compensated field of BFECC at the cell center pos.
+vec4 bfecc_corrected(vec2 pos){
+    return 1.5 * advect_field(pos) - 0.5 * advect_round_trip(pos);
+}
+
+vec4 bfecc_corrected_at(vec2 pos){
+    vec2 c0, f;
+    cells(pos, c0, f);
+    return bilinear(bfecc_corrected(c0), bfecc_corrected(c0 + vec2(1., 0.)), bfecc_corrected(c0 + vec2(0., 1.)), bfecc_corrected(c0 + vec2(1., 1.)), f);
+}
+
+// Limits res to the range of the field around the backtraced position pos (Selle et al.).
+vec4 limit(vec4 res, vec2 pos, vec4 sl){
+    if(params.limiter == LIMITER_NONE)
+        return res;
+
+    vec2 c0, f;
+    cells(pos, c0, f);
+    vec4 a = advect_field(c0);
+    vec4 b = advect_field(c0 + vec2(1., 0.));
+    vec4 c = advect_field(c0 + vec2(0., 1.));
+    vec4 d = advect_field(c0 + vec2(1., 1.));
+    vec4 lo = min(min(a, b), min(c, d));
+    vec4 hi = max(max(a, b), max(c, d));
+
+    if(params.limiter == LIMITER_CLAMP)
+        return clamp(res, lo, hi);
+    return mix(res, sl, max(vec4(lessThan(res, lo)), vec4(greaterThan(res, hi))));
+}
+
+// Advects the field selected by advect_layer to the cell center r where the velocity is vel.
+vec4 advect(vec2 r, vec2 vel){
+    float dt = params.dt;
+    vec2 back = trace(r, vel, dt);
+    vec4 sl = advect_field(back);
+
+    if(params.advection == ADVECTION_MACCORMACK){
+        vec4 res = sl + 0.5 * (advect_field(r) - advect_sl_at(trace(r, vel, -dt)));
+        return limit(res, back, sl);
+    }
+    if(params.advection == ADVECTION_BFECC)
+        return limit(bfecc_corrected_at(back), back, sl);
+    return sl;
+}
+
+// Advection and diffusion of one layer of the floating pigment.
+vec4 step_float(vec2 r, int layer, vec2 vel, float float_nu, float b){
+    float dt = params.dt;
+
+    vec4 fl = texa(r, layer, tex_float);
+    vec4 float_px = texa(r + vec2(1., 0.), layer, tex_float); 
+    vec4 float_nx = texa(r + vec2(-1., 0.), layer, tex_float);
+    vec4 float_py = texa(r + vec2(0., 1.), layer, tex_float); 
+    vec4 float_ny = texa(r + vec2(0., -1.), layer, tex_float);
+
+    vec4 float_lapl = (float_px + float_nx + float_py + float_ny - 4.*fl);
+
+    // advection of the floating pigment with the velocity of the water.
+    advect_layer = layer;
+    vec4 o = advect(r, vel);
+
+    // Granulating pigment additionally drifts down the slope of the paper. The drift only
+    // differs in its speed between the channels, so it is applied per channel with an upwind
+    // difference after advecting the layer once.
+    vec2 slope = tex(r, tex_paper).yz;
+    vec4 settle = params.paper_settle * pigments.granulation[layer];
+    vec4 drift_x = -settle * slope.x;
+    vec4 drift_y = -settle * slope.y;
+    vec4 grad_x = mix(float_px - fl, fl - float_nx, greaterThan(drift_x, vec4(0.)));
+    vec4 grad_y = mix(float_py - fl, fl - float_ny, greaterThan(drift_y, vec4(0.)));
+    o -= dt * (drift_x * grad_x + drift_y * grad_y);
+    // diffusion
+    o += dt * float_nu * float_lapl;
+
+    o += brush.pigment[layer] * dt * b;
+    return o;
+}
+
+// Adsorption of floating pigment g onto the paper and desorption of deposited pigment d of one
+// layer (Curtis et al. TransferPigment).
+void transfer(int layer, float h, bool wet, inout vec4 g, inout vec4 d){
+    // Floating pigment dries into the paper once the water is gone.
+    if(!wet){
+        d += g;
+        g = vec4(0.);
+        return;
+    }
+
+    vec4 gamma = pigments.granulation[layer];
+    vec4 rho = pigments.density[layer];
+    vec4 omega = max(pigments.staining[layer], vec4(1e-4));
+
+    // Granulating pigment settles in the valleys and is lifted from the peaks of the paper.
+    vec4 down = g * (1. - h * gamma) * rho;
+    vec4 up = d * (1. + (h - 1.) * gamma) * rho / omega;
+
+    down = min(down, max(1. - d, vec4(0.)));
+    up = min(up, max(1. - g, vec4(0.)));
+
+    d += down - up;
+    g += up - down;
 }
 
 float gaus(float x){
@@ -105,7 +353,8 @@ vec4 blur(vec2 r, vec2 scale, float cutoff, texture2D t, sampler s){
     return res;
 }
 
-void main(){
+// One step at the cell center r, one color and float output per pigment layer.
+void paint_step(vec2 r, out vec4 vo, out vec4 o_color[PIGMENT_LAYERS], out vec4 o_float[PIGMENT_LAYERS]){
     /*
     sampler2D tex_vpf = sampler2D(t_tex_vpf, s_tex_vpf);
     sampler2D tex_color = sampler2D(t_tex_color, s_tex_color);
@@ -116,15 +365,10 @@ void main(){
     // z: preasure field,
     // w: fluidity
 
-    float dt = 0.15;
-    //float K = 0.2;
-    float K = 0.3;
-    float nu = 0.5;
-    float kappa = 0.5;
-
-    if(f_uv.y > 0.5){
-        //K = 0.01;
-    }
+    float dt = params.dt;
+    float K = params.K;
+    float nu = params.nu;
+    float kappa = params.kappa;
 
     vo = v(r);
 
@@ -157,10 +401,11 @@ void main(){
     vo.z -= dt * (dx.z * vo.x + dy.z * vo.y + vo.z * div );
 
     // -----------------------------------------------------------------------------
-    // Semi-Langrangian Advection:
+    // Advection:
     //
-    // semi-Langrangian advection for velocity field (shift the field allong the field)
-    vo.xy = v(r - dt * vo.xy).xy;
+    // advection for velocity field (shift the field allong the field)
+    advect_layer = -1;
+    vo.xy = advect(r, vo.xy).xy;
 
 
     // -----------------------------------------------------------------------------
@@ -168,25 +413,23 @@ void main(){
     // for velocity field:
     vo.xy += dt * vec2(nu, nu) * lapl.xy;
 
+    // -----------------------------------------------------------------------------
+    // Paper:
+    // water flows down the slope of the paper.
+    vo.xy -= dt * params.paper_slope * tex(r, tex_paper).yz;
+
 
     // -----------------------------------------------------------------------------
     // Nullify Divergence:
-    vo.xy -= K * vec2(dx.z, dy.z);
+    if(params.projection == 0)
+        vo.xy -= K * vec2(dx.z, dy.z);
 
-    // DEBUG:
-    o_color.r = dx.z;
-    o_color.g = dy.z;
 
     // -----------------------------------------------------------------------------
     // External Sources:
-    // pen source: 
-    vec2 m = pen(global_data.time);
-    //vo.xyw += dt * exp(-(dot(r-m, r-m))/50.) * vec3(m - pen(global_data.time-0.1), 2.);
-    if(global_data.time < 15.){
-        vo.z += exp(-(dot(r-m, r-m))/50.) * 2.;
-    }
-    //vo.xy += dt * exp(-(dot(r-m, r-m))/50.) * vec2(m - pen(global_data.time-0.1));
-    //vo.z += exp(-(dot(r-m, r-m))/50.);
+    // brush source: 
+    float b = brush_falloff(r);
+    vo.z += b * brush.water;
 
     vo.xyz = clamp(vo.xyz, vec3(-VMAXX, -VMAXY, HMIN), vec3(VMAXX, VMAXY, 3.));
 
@@ -216,54 +459,126 @@ void main(){
     
     
     // Evapuration:
-    float evap_nu = 0.01;
-    //float evap_nu = 0.0;
+    float evap_nu = params.evap_nu;
     vo.z = vo.z - evap_nu * (1 - v(r).w)*vo.w;
 
-    // DEBUG:
-    o_color.b = (1 - v(r).w)*vo.w;
-    
-
-    vec4 brush_color = vec4(1.0, 0.0, 0.0, 0.1);
-
-    vec4 fl = tex(r, tex_float);
-    vec4 float_px = tex(r + vec2(1., 0.), tex_float); 
-    vec4 float_nx = tex(r + vec2(-1., 0.), tex_float);
-    vec4 float_py = tex(r + vec2(0., 1.), tex_float); 
-    vec4 float_ny = tex(r + vec2(0., -1.), tex_float);
-
-    vec4 float_dx = (float_px - float_nx)/2.0;
-    vec4 float_dy = (float_py - float_ny)/2.0;
-
-    vec4 float_lapl = (float_px + float_nx + float_py + float_ny - 4.*fl);
+    // -----------------------------------------------------------------------------
+    // Edge Darkening:
+    // w of the input is the wet mask blurred by vf_blurwv and vf_blurwh. Near the edges of the
+    // wet area water flows outwards down the blurred mask and evaporates faster, carrying
+    // pigment to the edge (Curtis et al. FlowOutward).
+    float edge = (1. - v(r).w) * vo.w;
+    vo.xy -= dt * params.edge_darkening * vec2(dx.w, dy.w) * vo.w;
+    vo.z -= dt * params.edge_darkening * edge;
 
-    float float_div = float_dx.x + float_dy.y;
+    // -----------------------------------------------------------------------------
+    // Boundary Conditions:
+    vo.xy = apply_boundary(r, vo.xy);
 
     // Adjust the diffusion coefficient of the pigment according to the height of the fluid.
     //float float_nu = (vo.z - HMIN) / 2.5 * 2.;
     float float_nu = 0.0;
     // Diffusion coefficient should never be over 1.
     if(vo.z > HMIN + 0.001){
-        float_nu = 0.001;
+        float_nu = params.float_nu;
     }
     //float_nu = 1.0;
-    
 
-    // advection: for some reason no pigment is carried away to the edges of the liquid.
-    // Wtf... why do I need this multiplicand (3.)? Implies that pigment moves faster than liquid.
-    vec2 vo_s = vo.xy;
-    o_float = tex(r - dt * vo_s, tex_float);
-    //o_float = tex(r - dt * vo.xy, tex_float);
-    // diffusion
-    o_float += dt * float_nu * float_lapl;
+    // Obstacles stay dry and keep the pigment deposited before they were placed, neither the
+    // transfer nor the brush changes it.
+    if(solid(r)){
+        vo = vec4(0., 0., HMIN, 0.);
+        for(int layer = 0; layer < PIGMENT_LAYERS; layer++){
+            o_color[layer] = texa(r, layer, tex_color);
+            o_float[layer] = vec4(0.);
+        }
+        return;
+    }
 
-    if(global_data.time < 10.)
-        o_float += brush_color * dt * exp(-(dot(r-m, r-m))/50.) * 0.002;
-    
-    /* DEBUG:
-    if(length(r - vec2(300, 300)) < 10 && global_data.time < 5){
-        o_float = vec4(10, 0, 0, 1);
+    // Pigment transfer between the floating and deposited layers.
+    float h = tex(r, tex_paper).x;
+    bool wet = vo.w >= 0.5;
+    for(int layer = 0; layer < PIGMENT_LAYERS; layer++){
+        o_color[layer] = texa(r, layer, tex_color);
+        o_float[layer] = step_float(r, layer, vo.xy, float_nu, b);
+        transfer(layer, h, wet, o_float[layer], o_color[layer]);
+    }
+}
+#endif
+#if FRAGMENT_SHADER
+// #############################################################################
+// FragmentShader:
+// #############################################################################
+
+layout(location = 0) in vec2 f_pos;
+layout(location = 1) in vec2 f_uv;
+layout(location = 2) in vec2 r;
+
+// vpf followed by one output per color layer and one per float layer, at most 8 in total.
+layout(location = 0) out vec4 vo;
+layout(location = 1) out vec4 o_color0;
+#if PIGMENT_LAYERS > 1
+layout(location = 2) out vec4 o_color1;
+#endif
+#if PIGMENT_LAYERS > 2
+layout(location = 3) out vec4 o_color2;
+#endif
+#if PIGMENT_LAYERS > 3
+#error at most 3 pigment layers are supported
+#endif
+layout(location = 1 + PIGMENT_LAYERS) out vec4 o_float0;
+#if PIGMENT_LAYERS > 1
+layout(location = 2 + PIGMENT_LAYERS) out vec4 o_float1;
+#endif
+#if PIGMENT_LAYERS > 2
+layout(location = 3 + PIGMENT_LAYERS) out vec4 o_float2;
+#endif
+
+void main(){
+    vec4 o_color[PIGMENT_LAYERS];
+    vec4 o_float[PIGMENT_LAYERS];
+    paint_step(r, vo, o_color, o_float);
+
+    o_color0 = o_color[0];
+    o_float0 = o_float[0];
+#if PIGMENT_LAYERS > 1
+    o_color1 = o_color[1];
+    o_float1 = o_float[1];
+#endif
+#if PIGMENT_LAYERS > 2
+    o_color2 = o_color[2];
+    o_float2 = o_float[2];
+#endif
+}
+#endif
+#if COMPUTE_SHADER
+// #############################################################################
+// ComputeShader:
+// #############################################################################
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+layout(set = 3, binding = 0, rgba32f) uniform writeonly image2D i_vpf;
+layout(set = 3, binding = 1, rgba32f) uniform writeonly image2DArray i_color;
+layout(set = 3, binding = 2, rgba32f) uniform writeonly image2DArray i_float;
+
+void main(){
+    ivec2 id = ivec2(gl_GlobalInvocationID.xy);
+    if(any(greaterThanEqual(id, ivec2(global_data.size))))
+        return;
+
+    // the cell center like r of the fragment shader.
+    vec2 r = vec2(id) + 0.5;
+
+    vec4 vo;
+    vec4 o_color[PIGMENT_LAYERS];
+    vec4 o_float[PIGMENT_LAYERS];
+    paint_step(r, vo, o_color, o_float);
+
+    imageStore(i_vpf, id, vo);
+    for(int layer = 0; layer < PIGMENT_LAYERS; layer++){
+        imageStore(i_color, ivec3(id, layer), o_color[layer]);
+        imageStore(i_float, ivec3(id, layer), o_float[layer]);
     }
-    */
 }
 #endif
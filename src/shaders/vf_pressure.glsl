@@ -0,0 +1,138 @@
+#version 460
+#if VERTEX_SHADER
+
+layout(location = 0) in vec2 i_pos;
+layout(location = 1) in vec2 i_uv;
+
+layout(location = 0) out vec2 f_pos;
+layout(location = 1) out vec2 f_uv;
+layout(location = 2) out vec2 r;
+
+layout(set = 0, binding = 0) uniform GlobalData{
+    vec2 size;
+    float time;
+} global_data;
+
+void main(){
+    f_pos = i_pos;
+    f_uv = i_uv;
+    r = i_uv * global_data.size;
+
+    gl_Position = vec4(i_pos, 0.0, 1.0);
+}
+
+#endif
+#if FRAGMENT_SHADER || COMPUTE_SHADER
+// #############################################################################
+// Pressure projection of the velocity field, the pass is selected by one of the
+// macros PASS_DIVERGENCE, PASS_JACOBI, PASS_GAUSS_SEIDEL or PASS_PROJECT.
+// PERIODIC is 1 if the textures wrap around the edges of the canvas.
+// #############################################################################
+
+layout(set = 0, binding = 0) uniform GlobalData{
+    vec2 size;
+    float time;
+} global_data;
+
+layout(set = 1, binding = 0) uniform texture2D t_tex_vpf;
+layout(set = 1, binding = 1) uniform sampler s_tex_vpf;
+// x: pressure, y: divergence of the velocity.
+layout(set = 2, binding = 0) uniform texture2D t_tex_pressure;
+layout(set = 2, binding = 1) uniform sampler s_tex_pressure;
+
+#define tex_vpf t_tex_vpf, s_tex_vpf
+#define tex_pressure t_tex_pressure, s_tex_pressure
+
+vec4 tex(vec2 pos, texture2D t, sampler s){
+    return textureLod(sampler2D(t, s), pos/global_data.size, 0);
+}
+
+// Sum of the pressure of the four neighbours, clamping to the edge gives a zero pressure
+// gradient across the border of the canvas unless it is periodic.
+float neighbours(vec2 pos){
+    return tex(pos + vec2(1., 0.), tex_pressure).x
+        + tex(pos + vec2(-1., 0.), tex_pressure).x
+        + tex(pos + vec2(0., 1.), tex_pressure).x
+        + tex(pos + vec2(0., -1.), tex_pressure).x;
+}
+
+// Jacobi update of the pressure at pos from the pressure of the previous iteration.
+float jacobi(vec2 pos){
+    return (neighbours(pos) - tex(pos, tex_pressure).y) / 4.;
+}
+
+vec4 pressure_pass(vec2 r){
+#if PASS_DIVERGENCE
+    vec4 vo = tex(r, tex_vpf);
+    float div = 0.;
+    // Only the wet area is incompressible.
+    if(vo.w >= 0.5){
+        div = (tex(r + vec2(1., 0.), tex_vpf).x - tex(r + vec2(-1., 0.), tex_vpf).x
+            + tex(r + vec2(0., 1.), tex_vpf).y - tex(r + vec2(0., -1.), tex_vpf).y) / 2.;
+    }
+    // The pressure of the last step is used as initial guess.
+    return vec4(tex(r, tex_pressure).x, div, 0., 0.);
+#elif PASS_JACOBI
+    return vec4(jacobi(r), tex(r, tex_pressure).y, 0., 0.);
+#elif PASS_GAUSS_SEIDEL
+    // One red black Gauss-Seidel iteration in a single pass:
+    // red cells ((x + y) even) are updated from their black neighbours, black cells from the
+    // updated values of their red neighbours which are recomputed here.
+    vec4 p = tex(r, tex_pressure);
+    ivec2 id = ivec2(floor(r));
+    if((id.x + id.y) % 2 == 0)
+        return vec4(jacobi(r), p.y, 0., 0.);
+
+    vec2 n[4] = {vec2(1., 0.), vec2(-1., 0.), vec2(0., 1.), vec2(0., -1.)};
+    float sum = 0.;
+    for(int i = 0; i < 4; i++){
+        vec2 pos = r + n[i];
+        // Outside of the canvas the clamped neighbour is the cell itself.
+        if(PERIODIC == 0 && (any(lessThan(pos, vec2(0.))) || any(greaterThanEqual(pos, global_data.size))))
+            sum += p.x;
+        else
+            sum += jacobi(pos);
+    }
+    return vec4((sum - p.y) / 4., p.y, 0., 0.);
+#elif PASS_PROJECT
+    vec4 vo = tex(r, tex_vpf);
+    if(vo.w >= 0.5){
+        vo.xy -= vec2(
+            tex(r + vec2(1., 0.), tex_pressure).x - tex(r + vec2(-1., 0.), tex_pressure).x,
+            tex(r + vec2(0., 1.), tex_pressure).x - tex(r + vec2(0., -1.), tex_pressure).x
+        ) / 2.;
+    }
+    return vo;
+#endif
+}
+
+#endif
+#if FRAGMENT_SHADER
+
+layout(location = 0) in vec2 f_pos;
+layout(location = 1) in vec2 f_uv;
+layout(location = 2) in vec2 r;
+
+layout(location = 0) out vec4 o;
+
+void main(){
+    o = pressure_pass(r);
+}
+
+#endif
+#if COMPUTE_SHADER
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+// tex_pressure or tex_vpf for PASS_PROJECT.
+layout(set = 3, binding = 0, rgba32f) uniform writeonly image2D i_out;
+
+void main(){
+    ivec2 id = ivec2(gl_GlobalInvocationID.xy);
+    if(any(greaterThanEqual(id, ivec2(global_data.size))))
+        return;
+
+    imageStore(i_out, id, pressure_pass(vec2(id) + 0.5));
+}
+
+#endif
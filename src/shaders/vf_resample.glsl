@@ -0,0 +1,66 @@
+#version 460
+#if VERTEX_SHADER
+
+layout(location = 0) in vec2 i_pos;
+layout(location = 1) in vec2 i_uv;
+
+layout(location = 0) out vec2 f_pos;
+layout(location = 1) out vec2 f_uv;
+
+void main(){
+    f_pos = i_pos;
+    f_uv = i_uv;
+
+    gl_Position = vec4(i_pos, 0.0, 1.0);
+}
+#endif
+#if FRAGMENT_SHADER
+// #############################################################################
+// Resamples the source texture to the size of the render target with a box filter.
+//
+// Every target texel averages the source texels it covers weighted by the covered area. The box
+// is at least one source texel wide, so along a direction that is enlarged the weights are the
+// ones of bilinear interpolation.
+// #############################################################################
+
+layout(location = 0) in vec2 f_pos;
+layout(location = 1) in vec2 f_uv;
+
+layout(location = 0) out vec4 o;
+
+layout(set = 0, binding = 0) uniform ResampleParams{
+    vec2 dst_size;
+} params;
+
+layout(set = 1, binding = 0) uniform texture2D t_src;
+layout(set = 1, binding = 1) uniform sampler s_src;
+
+void main(){
+    ivec2 src_size = textureSize(sampler2D(t_src, s_src), 0);
+    vec2 footprint = max(vec2(src_size) / params.dst_size, vec2(1.));
+
+    vec2 lo = f_uv * vec2(src_size) - 0.5 * footprint;
+    vec2 hi = lo + footprint;
+    ivec2 first = ivec2(floor(lo));
+    ivec2 last = ivec2(ceil(hi)) - 1;
+
+    vec4 sum = vec4(0.);
+    float weight = 0.;
+    for(int y = first.y; y <= last.y; y++){
+        for(int x = first.x; x <= last.x; x++){
+            vec2 cell = vec2(x, y);
+            vec2 overlap = max(min(hi, cell + 1.) - max(lo, cell), vec2(0.));
+            float w = overlap.x * overlap.y;
+
+#if PERIODIC
+            ivec2 p = (ivec2(x, y) + src_size) % src_size;
+#else
+            ivec2 p = clamp(ivec2(x, y), ivec2(0), src_size - 1);
+#endif
+            sum += w * texelFetch(sampler2D(t_src, s_src), p, 0);
+            weight += w;
+        }
+    }
+    o = sum / weight;
+}
+#endif
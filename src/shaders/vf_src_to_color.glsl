@@ -15,16 +15,48 @@ void main(){
 }
 #endif
 #if FRAGMENT_SHADER
+// #############################################################################
+// Paints the source image as deposited pigment: cyan, magenta and yellow of the source are
+// deposited as the pigments given by SourcePigments, its alpha channel is ignored.
+// #############################################################################
 
 layout(location = 0) in vec2 f_pos;
 layout(location = 1) in vec2 f_uv;
 
-layout(location = 0) out vec4 o_color;
+// one output per color layer.
+layout(location = 0) out vec4 o_color0;
+#if PIGMENT_LAYERS > 1
+layout(location = 1) out vec4 o_color1;
+#endif
+#if PIGMENT_LAYERS > 2
+layout(location = 2) out vec4 o_color2;
+#endif
+#if PIGMENT_LAYERS > 3
+#error at most 3 pigment layers are supported
+#endif
 
-layout(set = 0, binding = 0) uniform texture2D t_src;
-layout(set = 0, binding = 1) uniform sampler s_src;
+layout(set = 0, binding = 0) uniform SourcePigments{
+    vec4 cyan[PIGMENT_LAYERS];
+    vec4 magenta[PIGMENT_LAYERS];
+    vec4 yellow[PIGMENT_LAYERS];
+} src_pigments;
+
+layout(set = 1, binding = 0) uniform texture2D t_src;
+layout(set = 1, binding = 1) uniform sampler s_src;
+
+vec4 deposit(int layer, vec3 cmy){
+    return cmy.x * src_pigments.cyan[layer] + cmy.y * src_pigments.magenta[layer] + cmy.z * src_pigments.yellow[layer];
+}
 
 void main(){
-    o_color = texture(sampler2D(t_src, s_src), f_uv);
+    vec3 cmy = 1. - texture(sampler2D(t_src, s_src), f_uv).rgb;
+
+    o_color0 = deposit(0, cmy);
+#if PIGMENT_LAYERS > 1
+    o_color1 = deposit(1, cmy);
+#endif
+#if PIGMENT_LAYERS > 2
+    o_color2 = deposit(2, cmy);
+#endif
 }
 #endif
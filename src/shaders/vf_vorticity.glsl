@@ -0,0 +1,119 @@
+#version 460
+#if VERTEX_SHADER
+
+layout(location = 0) in vec2 i_pos;
+layout(location = 1) in vec2 i_uv;
+
+layout(location = 0) out vec2 f_pos;
+layout(location = 1) out vec2 f_uv;
+layout(location = 2) out vec2 r;
+
+layout(set = 0, binding = 0) uniform GlobalData{
+    vec2 size;
+    float time;
+} global_data;
+
+void main(){
+    f_pos = i_pos;
+    f_uv = i_uv;
+    r = i_uv * global_data.size;
+
+    gl_Position = vec4(i_pos, 0.0, 1.0);
+}
+
+#endif
+#if FRAGMENT_SHADER || COMPUTE_SHADER
+
+layout(set = 0, binding = 0) uniform GlobalData{
+    vec2 size;
+    float time;
+} global_data;
+
+layout(set = 0, binding = 1) uniform PaintParams{
+    vec2 vmax;
+    float dt;
+    float K;
+    float nu;
+    float kappa;
+    float evap_nu;
+    float hmin;
+    float float_nu;
+    float paper_slope;
+    float paper_settle;
+    float cap_min;
+    float cap_max;
+    float cap_absorb;
+    float cap_epsilon;
+    float cap_delta;
+    float cap_sigma;
+    float cap_evap;
+    float edge_darkening;
+    uint projection;
+    uint advection;
+    uint limiter;
+    float vorticity;
+} params;
+
+layout(set = 1, binding = 0) uniform texture2D t_tex_vpf;
+layout(set = 1, binding = 1) uniform sampler s_tex_vpf;
+
+#define tex_vpf t_tex_vpf, s_tex_vpf
+
+vec4 tex(vec2 pos, texture2D t, sampler s){
+    return textureLod(sampler2D(t, s), pos/global_data.size, 0);
+}
+
+// Curl of the velocity at pos.
+float curl(vec2 pos){
+    return (tex(pos + vec2(1., 0.), tex_vpf).y - tex(pos + vec2(-1., 0.), tex_vpf).y
+        - tex(pos + vec2(0., 1.), tex_vpf).x + tex(pos + vec2(0., -1.), tex_vpf).x) / 2.;
+}
+
+// Vorticity confinement (Fedkiw et al.) at r, the force pushes the velocity around the
+// local maxima of the curl to restore swirls lost to numerical dissipation.
+vec4 confine(vec2 r){
+    vec4 vo = tex(r, tex_vpf);
+    if(vo.w < 0.5)
+        return vo;
+
+    vec2 grad = vec2(
+        abs(curl(r + vec2(1., 0.))) - abs(curl(r + vec2(-1., 0.))),
+        abs(curl(r + vec2(0., 1.))) - abs(curl(r + vec2(0., -1.)))
+    ) / 2.;
+    vec2 n = grad / (length(grad) + 1e-5);
+
+    float w = curl(r);
+    vo.xy += params.dt * params.vorticity * vec2(n.y, -n.x) * w;
+    vo.xy = clamp(vo.xy, -params.vmax, params.vmax);
+    return vo;
+}
+
+#endif
+#if FRAGMENT_SHADER
+
+layout(location = 0) in vec2 f_pos;
+layout(location = 1) in vec2 f_uv;
+layout(location = 2) in vec2 r;
+
+layout(location = 0) out vec4 o;
+
+void main(){
+    o = confine(r);
+}
+
+#endif
+#if COMPUTE_SHADER
+
+layout(local_size_x = 8, local_size_y = 8) in;
+
+layout(set = 2, binding = 0, rgba32f) uniform writeonly image2D i_vpf;
+
+void main(){
+    ivec2 id = ivec2(gl_GlobalInvocationID.xy);
+    if(any(greaterThanEqual(id, ivec2(global_data.size))))
+        return;
+
+    imageStore(i_vpf, id, confine(vec2(id) + 0.5));
+}
+
+#endif
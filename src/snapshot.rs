@@ -0,0 +1,329 @@
+use crate::paintsim::{PaintParams, Pigment, PigmentRegistry, Solver, MAX_PIGMENTS};
+use anyhow::*;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+
+// identifies snapshot files.
+const MAGIC: &[u8; 8] = b"FLUIDSNP";
+// has to be increased whenever the layout of the file or of PaintParams changes.
+pub const VERSION: u32 = 3;
+
+///
+/// State of a PaintSim written by PaintSim::save_state and restored by PaintSim::load_state.
+///
+/// The file starts with MAGIC and VERSION followed by the fields in declaration order, numbers
+/// are little endian and every Vec is prefixed with its length in bytes. PaintParams is written
+/// as its 4 byte fields and every pigment as its name followed by its properties. The textures
+/// are stored as the tightly packed texels read back from the GPU so restoring them is exact.
+///
+#[derive(Clone, Debug)]
+pub struct Snapshot{
+    // size of the grid.
+    pub size: [u32; 2],
+    // size of the image the simulation was created from, src is resampled to the grid.
+    pub src_size: [u32; 2],
+    pub periodic: bool,
+    // steps counted by the clock of the PaintSim.
+    pub steps: u64,
+    pub solver: Solver,
+    // also holds the advection scheme and the boundary.
+    pub params: PaintParams,
+    pub pigments: PigmentRegistry,
+    pub glazes: u32,
+    // Rgba8Unorm texels of the source image.
+    pub src: Vec<u8>,
+    // height of the paper, row y corresponds to row y of the textures.
+    pub paper: Vec<f32>,
+    pub paper_periodic: bool,
+    // 1 for solid cells.
+    pub obstacles: Vec<f32>,
+    // Rgba32Float texels, all layers of the texture arrays one after another.
+    pub tex_vpf: Vec<u8>,
+    pub tex_sat: Vec<u8>,
+    pub tex_pressure: Vec<u8>,
+    pub tex_color: Vec<u8>,
+    pub tex_float: Vec<u8>,
+    pub tex_glazes: Vec<u8>,
+}
+
+impl Snapshot{
+    pub fn write_to_path(&self, path: &str) -> Result<()>{
+        let mut w = BufWriter::new(File::create(path)?);
+        w.write_all(MAGIC)?;
+        write_u32(&mut w, VERSION)?;
+
+        write_u32(&mut w, self.size[0])?;
+        write_u32(&mut w, self.size[1])?;
+        write_u32(&mut w, self.src_size[0])?;
+        write_u32(&mut w, self.src_size[1])?;
+        write_u32(&mut w, self.periodic as u32)?;
+        w.write_all(&self.steps.to_le_bytes())?;
+        let (solver, count) = match self.solver{
+            Solver::DensityInvariant => (0, 0),
+            Solver::Jacobi{iterations} => (1, iterations),
+            Solver::GaussSeidel{iterations} => (2, iterations),
+            Solver::Multigrid{cycles} => (3, cycles),
+        };
+        write_u32(&mut w, solver)?;
+        write_u32(&mut w, count)?;
+        write_words(&mut w, bytemuck::cast_slice(bytemuck::bytes_of(&self.params)))?;
+        write_u32(&mut w, self.pigments.len() as u32)?;
+        for pigment in self.pigments.iter(){
+            write_bytes(&mut w, pigment.name.as_bytes())?;
+            write_f32s(&mut w, &pigment_properties(pigment))?;
+        }
+        write_u32(&mut w, self.glazes)?;
+
+        write_bytes(&mut w, &self.src)?;
+        write_f32s(&mut w, &self.paper)?;
+        write_u32(&mut w, self.paper_periodic as u32)?;
+        write_f32s(&mut w, &self.obstacles)?;
+        for tex in [&self.tex_vpf, &self.tex_sat, &self.tex_pressure, &self.tex_color, &self.tex_float, &self.tex_glazes]{
+            write_bytes(&mut w, tex)?;
+        }
+
+        w.flush()?;
+        Ok(())
+    }
+
+    pub fn read_from_path(path: &str) -> Result<Self>{
+        let mut r = BufReader::new(File::open(path)?);
+
+        let mut magic = [0u8; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC{
+            return Err(anyhow!("{} is not a snapshot", path));
+        }
+        let version = read_u32(&mut r)?;
+        if version != VERSION{
+            return Err(anyhow!("snapshot {} has version {}, expected {}", path, version, VERSION));
+        }
+
+        let size = [read_u32(&mut r)?, read_u32(&mut r)?];
+        let src_size = [read_u32(&mut r)?, read_u32(&mut r)?];
+        if src_size[0] == 0 || src_size[1] == 0{
+            return Err(anyhow!("source image in snapshot {} has the size {:?}", path, src_size));
+        }
+        let periodic = read_u32(&mut r)? != 0;
+        let mut steps = [0u8; 8];
+        r.read_exact(&mut steps)?;
+        let steps = u64::from_le_bytes(steps);
+        let (solver, count) = (read_u32(&mut r)?, read_u32(&mut r)?);
+        let solver = match solver{
+            0 => Solver::DensityInvariant,
+            1 => Solver::Jacobi{iterations: count},
+            2 => Solver::GaussSeidel{iterations: count},
+            3 => Solver::Multigrid{cycles: count},
+            _ => return Err(anyhow!("unknown solver {} in snapshot {}", solver, path)),
+        };
+        let mut params: PaintParams = bytemuck::Zeroable::zeroed();
+        read_words(&mut r, bytemuck::cast_slice_mut(bytemuck::bytes_of_mut(&mut params)))
+            .map_err(|err| anyhow!("parameters in snapshot {} do not match PaintParams: {}", path, err))?;
+        let count = read_u32(&mut r)? as usize;
+        if count > MAX_PIGMENTS{
+            return Err(anyhow!("snapshot {} has {} pigments, at most {} are supported", path, count, MAX_PIGMENTS));
+        }
+        let mut pigments = PigmentRegistry::new();
+        for _ in 0..count{
+            let name = String::from_utf8(read_bytes(&mut r)?)?;
+            let properties = read_f32s(&mut r)?;
+            pigments.push(pigment_from_properties(&name, &properties)?)?;
+        }
+        let glazes = read_u32(&mut r)?;
+
+        Ok(Self{
+            size,
+            src_size,
+            periodic,
+            steps,
+            solver,
+            params,
+            pigments,
+            glazes,
+            src: read_bytes(&mut r)?,
+            paper: read_f32s(&mut r)?,
+            paper_periodic: read_u32(&mut r)? != 0,
+            obstacles: read_f32s(&mut r)?,
+            tex_vpf: read_bytes(&mut r)?,
+            tex_sat: read_bytes(&mut r)?,
+            tex_pressure: read_bytes(&mut r)?,
+            tex_color: read_bytes(&mut r)?,
+            tex_float: read_bytes(&mut r)?,
+            tex_glazes: read_bytes(&mut r)?,
+        })
+    }
+}
+
+fn write_u32(w: &mut impl Write, value: u32) -> Result<()>{
+    w.write_all(&value.to_le_bytes())?;
+    Ok(())
+}
+
+///
+/// Writes 4 byte values like the fields of PaintParams as little endian words.
+///
+fn write_words(w: &mut impl Write, words: &[u32]) -> Result<()>{
+    write_bytes(w, &words.iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<_>>())
+}
+
+fn write_bytes(w: &mut impl Write, bytes: &[u8]) -> Result<()>{
+    w.write_all(&(bytes.len() as u64).to_le_bytes())?;
+    w.write_all(bytes)?;
+    Ok(())
+}
+
+fn write_f32s(w: &mut impl Write, values: &[f32]) -> Result<()>{
+    write_bytes(w, &values.iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<_>>())
+}
+
+fn read_u32(r: &mut impl Read) -> Result<u32>{
+    let mut bytes = [0u8; 4];
+    r.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+///
+/// Reads a Vec prefixed with its length, the buffer only grows with the bytes actually read so a
+/// corrupted length can not allocate more than the file holds.
+///
+fn read_bytes(r: &mut impl Read) -> Result<Vec<u8>>{
+    let mut len = [0u8; 8];
+    r.read_exact(&mut len)?;
+    let len = u64::from_le_bytes(len);
+    let mut bytes = Vec::new();
+    r.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len{
+        return Err(anyhow!("expected {} bytes, the snapshot ends after {}", len, bytes.len()));
+    }
+    Ok(bytes)
+}
+
+fn read_words(r: &mut impl Read, words: &mut [u32]) -> Result<()>{
+    let bytes = read_bytes(r)?;
+    if bytes.len() != words.len() * 4{
+        return Err(anyhow!("expected {} words, found {} bytes", words.len(), bytes.len()));
+    }
+    for (word, b) in words.iter_mut().zip(bytes.chunks_exact(4)){
+        *word = u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+    }
+    Ok(())
+}
+
+fn read_f32s(r: &mut impl Read) -> Result<Vec<f32>>{
+    let bytes = read_bytes(r)?;
+    if bytes.len() % 4 != 0{
+        return Err(anyhow!("expected a multiple of 4 bytes"));
+    }
+    Ok(bytes.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect())
+}
+
+// density, staining, granulation, absorption and scattering.
+const PIGMENT_PROPERTIES: usize = 9;
+
+fn pigment_properties(pigment: &Pigment) -> [f32; PIGMENT_PROPERTIES]{
+    let [ar, ag, ab] = pigment.absorption;
+    let [sr, sg, sb] = pigment.scattering;
+    [pigment.density, pigment.staining, pigment.granulation, ar, ag, ab, sr, sg, sb]
+}
+
+fn pigment_from_properties(name: &str, properties: &[f32]) -> Result<Pigment>{
+    if properties.len() != PIGMENT_PROPERTIES{
+        return Err(anyhow!("pigment {} has {} properties, expected {}", name, properties.len(), PIGMENT_PROPERTIES));
+    }
+    let p = properties;
+    Ok(Pigment::new(name, [p[3], p[4], p[5]], [p[6], p[7], p[8]])
+        .set_density(p[0])
+        .set_staining(p[1])
+        .set_granulation(p[2]))
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn snapshot() -> Snapshot{
+        let size = [3, 2];
+        let cells = (size[0] * size[1]) as usize;
+        let texels = |layers: usize, seed: u8| (0..cells * 16 * layers).map(|i| (i as u8).wrapping_mul(seed)).collect::<Vec<_>>();
+
+        let mut pigments = PigmentRegistry::new();
+        pigments.push(Pigment::new("rose", [0.22, 1.47, 0.57], [0.05, 0.003, 0.03]).set_staining(2.)).unwrap();
+        pigments.push(Pigment::new("umber", [0.74, 1.54, 2.10], [0.09, 0.09, 0.004]).set_density(0.04)).unwrap();
+
+        Snapshot{
+            size,
+            src_size: [7, 5],
+            periodic: true,
+            steps: 1 << 40,
+            solver: Solver::Multigrid{cycles: 3},
+            params: PaintParams{
+                vorticity: 0.25,
+                boundary: 2,
+                ..Default::default()
+            },
+            pigments,
+            glazes: 1,
+            src: (0..cells * 4).map(|i| i as u8).collect(),
+            paper: (0..cells).map(|i| i as f32 * 0.1).collect(),
+            paper_periodic: true,
+            obstacles: (0..cells).map(|i| (i % 2) as f32).collect(),
+            tex_vpf: texels(1, 3),
+            tex_sat: texels(1, 5),
+            tex_pressure: texels(1, 7),
+            tex_color: texels(2, 11),
+            tex_float: texels(2, 13),
+            tex_glazes: texels(8, 17),
+        }
+    }
+
+    #[test]
+    fn written_snapshot_reads_back_equal(){
+        let path = std::env::temp_dir().join(format!("fluid01_snapshot_{}.snp", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let written = snapshot();
+        written.write_to_path(path).unwrap();
+        let read = Snapshot::read_from_path(path);
+        std::fs::remove_file(path).unwrap();
+        let read = read.unwrap();
+
+        assert_eq!(read.size, written.size);
+        assert_eq!(read.src_size, written.src_size);
+        assert_eq!(read.periodic, written.periodic);
+        assert_eq!(read.steps, written.steps);
+        assert_eq!(read.solver, written.solver);
+        assert_eq!(bytemuck::bytes_of(&read.params), bytemuck::bytes_of(&written.params));
+        assert_eq!(read.pigments.len(), written.pigments.len());
+        for (a, b) in read.pigments.iter().zip(written.pigments.iter()){
+            assert_eq!(a.name, b.name);
+            assert_eq!(pigment_properties(a), pigment_properties(b));
+        }
+        assert_eq!(read.glazes, written.glazes);
+        assert_eq!(read.src, written.src);
+        assert_eq!(read.paper, written.paper);
+        assert_eq!(read.paper_periodic, written.paper_periodic);
+        assert_eq!(read.obstacles, written.obstacles);
+        assert_eq!(read.tex_vpf, written.tex_vpf);
+        assert_eq!(read.tex_sat, written.tex_sat);
+        assert_eq!(read.tex_pressure, written.tex_pressure);
+        assert_eq!(read.tex_color, written.tex_color);
+        assert_eq!(read.tex_float, written.tex_float);
+        assert_eq!(read.tex_glazes, written.tex_glazes);
+    }
+
+    #[test]
+    fn lengths_beyond_the_end_are_errors(){
+        let mut bytes = u64::MAX.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&[1, 2, 3]);
+        assert!(read_bytes(&mut bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn params_are_little_endian(){
+        let params = PaintParams::default();
+        let mut bytes = Vec::new();
+        write_words(&mut bytes, bytemuck::cast_slice(bytemuck::bytes_of(&params))).unwrap();
+        // the dt after the length and vmax.
+        assert_eq!(bytes[8 + 8..8 + 12], params.dt.to_le_bytes());
+    }
+}
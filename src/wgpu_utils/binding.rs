@@ -1,9 +1,6 @@
 #[allow(unused)]
 use anyhow::*;
-use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
-use std::sync::Arc;
-use std::any::{Any, TypeId};
 
 pub trait CreateBindGroupLayout{
     fn create_bind_group_layout(device: &wgpu::Device, label: Option<&str>) -> BindGroupLayoutWithDesc;
@@ -14,18 +11,18 @@ pub trait CreateBindGroup: CreateBindGroupLayout{
 }
 
 pub trait GetBindGroupLayout{
-    fn get_bind_group_layout<'l>(&'l self) -> &'l BindGroupLayoutWithDesc;
+    fn get_bind_group_layout(&self) -> &BindGroupLayoutWithDesc;
 }
 
 pub trait GetBindGroup{
-    fn get_bind_group<'l>(&'l self) -> &'l wgpu::BindGroup;
+    fn get_bind_group(&self) -> &wgpu::BindGroup;
 }
 
 
 
 
 pub trait ToBindGroupLayouts{
-    fn bind_group_layouts<'l>(&'l self) -> Vec<&'l wgpu::BindGroupLayout>;
+    fn bind_group_layouts(&self) -> Vec<&wgpu::BindGroupLayout>;
 }
 
 
@@ -40,6 +37,12 @@ pub struct BindGroupLayoutBuilder{
     entries: Vec<wgpu::BindGroupLayoutEntry>,
 }
 
+impl Default for BindGroupLayoutBuilder{
+    fn default() -> Self{
+        Self::new()
+    }
+}
+
 impl BindGroupLayoutBuilder{
     pub fn new() -> Self{
         Self{
@@ -159,7 +162,7 @@ impl<'l> BindGroupBuilder<'l>{
     }
 
     #[inline]
-    pub fn sampler(mut self, sampler: &'l wgpu::Sampler) -> Self{
+    pub fn sampler(self, sampler: &'l wgpu::Sampler) -> Self{
         self.resource(wgpu::BindingResource::Sampler(sampler))
     }
 
@@ -169,7 +172,7 @@ impl<'l> BindGroupBuilder<'l>{
     }
 
     #[inline]
-    pub fn texture(mut self, texture_view: &'l wgpu::TextureView) -> Self{
+    pub fn texture(self, texture_view: &'l wgpu::TextureView) -> Self{
         self.resource(wgpu::BindingResource::TextureView(texture_view))
     }
 
@@ -195,12 +198,6 @@ pub trait BindGroupContent{
     fn push_resources_to<'bgb>(&'bgb self, bind_group_builder: &mut BindGroupBuilder<'bgb>);
 }
 
-// TODO: Macro for Structs that can be bind groups.
-macro_rules! bind_group_content{
-    ($struct_name:ident, $($field_name:ident),+) =>{
-    }
-}
-
 macro_rules! bind_group_content_for_tuple{
     ($($name:ident)+) => {
         #[allow(non_snake_case)]
@@ -294,20 +291,20 @@ impl<C: BindGroupContent> DerefMut for BindGroup<C>{
 }
 
 impl<C: BindGroupContent> GetBindGroupLayout for BindGroup<C>{
-    fn get_bind_group_layout<'l>(&'l self) -> &'l BindGroupLayoutWithDesc {
+    fn get_bind_group_layout(&self) -> &BindGroupLayoutWithDesc {
         &self.bind_group_layout
     }
 }
 
 impl<C: BindGroupContent> GetBindGroup for BindGroup<C>{
-    fn get_bind_group<'l>(&'l self) -> &'l wgpu::BindGroup {
+    fn get_bind_group(&self) -> &wgpu::BindGroup {
         &self.bind_group
     }
 }
 
 
 
-mod glsl{
+pub mod glsl{
     pub fn buffer(read_only: bool) -> wgpu::BindingType {
         wgpu::BindingType::Buffer {
             ty: wgpu::BufferBindingType::Storage { read_only },
@@ -325,7 +322,12 @@ mod glsl{
     }
 
     pub fn sampler(filtering: bool) -> wgpu::BindingType {
-        wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering)
+        if filtering{
+            wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering)
+        }
+        else{
+            wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering)
+        }
     }
 
     #[allow(non_snake_case)]
@@ -456,4 +458,12 @@ pub mod wgsl{
             multisampled: false,
         }
     }
+
+    pub fn texture_2d_array() -> wgpu::BindingType{
+        wgpu::BindingType::Texture{
+            sample_type: wgpu::TextureSampleType::Float{ filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2Array,
+            multisampled: false,
+        }
+    }
 }
@@ -1,7 +1,5 @@
-use anyhow::*;
 use wgpu::util::DeviceExt;
-use std::{marker::PhantomData, ops::{Deref, DerefMut, RangeBounds}, borrow::{Borrow, BorrowMut}, future, cell::Cell};
-use binding::CreateBindGroupLayout;
+use std::{marker::PhantomData, ops::{Deref, DerefMut, RangeBounds}};
 use std::mem::ManuallyDrop;
 use std::ops::Bound;
 
@@ -17,7 +15,7 @@ pub struct BufferSlice<'bs, C: bytemuck::Pod>{
     len: wgpu::BufferAddress,
 }
 
-/// ```rust
+/// ```ignore
 /// let array = [0, 1, 2, 3, 4];
 /// let mapped_buffer = MappedBuffer::new_storage(device, None, array);
 ///
@@ -60,7 +58,7 @@ impl<'bs, C: bytemuck::Pod> BufferSlice<'bs, C>{
     ///
     /// Map the slice and block this thread untill maping is complete.
     ///
-    /// ```rust
+    /// ```ignore
     /// println!("{}", slice.map_blocking(device)[0]);
     /// ```
     ///
@@ -103,7 +101,7 @@ impl<'bs, C: bytemuck::Pod> BufferSlice<'bs, C>{
     ///
     /// Map the slice mutably and block this thread untill maping is complete.
     ///
-    /// ```rust
+    /// ```ignore
     /// slice.map_blocking_mut(device)[0] = 1;
     /// ```
     ///
@@ -198,25 +196,26 @@ impl<C: bytemuck::Pod> Buffer<C>{
         self.len
     }
 
-    pub fn slice<S: RangeBounds<wgpu::BufferAddress>>(&self, bounds: S) -> BufferSlice<C>{
+    pub fn is_empty(&self) -> bool{
+        self.len == 0
+    }
+
+    pub fn slice<S: RangeBounds<wgpu::BufferAddress>>(&self, bounds: S) -> BufferSlice<'_, C>{
         let start_bound = bounds.start_bound();
         let end_bound = bounds.end_bound();
 
         let start_bound = match start_bound{
             Bound::Unbounded => 0 as wgpu::BufferAddress,
-            Bound::Included(offset) => {offset + 0},
+            Bound::Included(offset) => {*offset},
             Bound::Excluded(offset) => {offset + 1},
         };
 
         let end_bound = match end_bound{
             Bound::Unbounded => {(self.len() -1) as wgpu::BufferAddress},
-            Bound::Included(offset) => {offset - 0},
+            Bound::Included(offset) => {*offset},
             Bound::Excluded(offset) => {offset - 1},
         };
 
-        let start_bound = start_bound;
-        let end_bound = end_bound;
-
         let size = end_bound - start_bound;
 
         BufferSlice{
@@ -13,12 +13,62 @@ use winit::{
 
 pub trait State{
     fn new(fstate: &mut AppState) -> Self;
-    fn render(&mut self, app: &mut AppState, control_flow: &mut ControlFlow) -> Result<(), wgpu::SurfaceError>{Ok(())}
-    fn pre_render(&mut self, app: &mut AppState, control_flow: &mut ControlFlow) -> Result<(), wgpu::SurfaceError>{Ok(())}
-    fn input(&mut self, event: &WindowEvent) -> bool{false}
-    fn cursor_moved(&mut self, fstate: &mut AppState, device_id: &winit::event::DeviceId, position: &winit::dpi::PhysicalPosition<f64>){}
-    fn device_event(&mut self, fstate: &mut AppState, device_id: &winit::event::DeviceId, device_event: &DeviceEvent){}
-    fn resize(&mut self, fstate: &mut AppState, new_size: winit::dpi::PhysicalSize<u32>){}
+    fn render(&mut self, _app: &mut AppState, _control_flow: &mut ControlFlow) -> Result<(), wgpu::SurfaceError>{Ok(())}
+    fn pre_render(&mut self, _app: &mut AppState, _control_flow: &mut ControlFlow) -> Result<(), wgpu::SurfaceError>{Ok(())}
+    fn input(&mut self, _event: &WindowEvent) -> bool{false}
+    fn cursor_moved(&mut self, _fstate: &mut AppState, _device_id: &winit::event::DeviceId, _position: &winit::dpi::PhysicalPosition<f64>){}
+    fn mouse_input(&mut self, _fstate: &mut AppState, _device_id: &winit::event::DeviceId, _state: &ElementState, _button: &MouseButton){}
+    fn device_event(&mut self, _fstate: &mut AppState, _device_id: &winit::event::DeviceId, _device_event: &DeviceEvent){}
+    fn resize(&mut self, _fstate: &mut AppState, _new_size: winit::dpi::PhysicalSize<u32>){}
+}
+
+///
+/// Request a device with the features used by the simulation.
+///
+pub async fn request_device(adapter: &wgpu::Adapter) -> anyhow::Result<(wgpu::Device, wgpu::Queue)>{
+    let (device, queue) = adapter.request_device(
+        &wgpu::DeviceDescriptor{
+            features: wgpu::Features::empty()
+                .union(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+                .union(wgpu::Features::VERTEX_WRITABLE_STORAGE)
+                .union(wgpu::Features::MAPPABLE_PRIMARY_BUFFERS),
+            limits: wgpu::Limits::default(),
+            label: None,
+        },
+        None,
+    ).await.map_err(|err| anyhow::anyhow!("requesting a device from {} failed: {}", adapter.get_info().name, err))?;
+    Ok((device, queue))
+}
+
+///
+/// Device and queue without a window or surface, used for offline rendering.
+///
+pub struct HeadlessState{
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+}
+
+impl HeadlessState{
+    ///
+    /// Fails if there is no suitable adapter or it does not provide a device with the features
+    /// of request_device.
+    ///
+    pub async fn new(force_fallback_adapter: bool) -> anyhow::Result<Self>{
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let adapter = instance.request_adapter(
+            &wgpu::RequestAdapterOptions{
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: None,
+                force_fallback_adapter,
+            },
+        ).await.ok_or(anyhow::anyhow!("no suitable adapter found"))?;
+        let (device, queue) = request_device(&adapter).await?;
+
+        Ok(Self{
+            device,
+            queue,
+        })
+    }
 }
 
 pub struct AppState{
@@ -44,17 +94,7 @@ impl AppState{
                 force_fallback_adapter: false,
             },
         ).await.unwrap();
-        let (device, queue) = adapter.request_device(
-            &wgpu::DeviceDescriptor{
-                features: wgpu::Features::empty()
-                    .union(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
-                    .union(wgpu::Features::VERTEX_WRITABLE_STORAGE)
-                    .union(wgpu::Features::MAPPABLE_PRIMARY_BUFFERS),
-                limits: wgpu::Limits::default(),
-                label: None,
-            },
-            None,
-        ).await.unwrap();
+        let (device, queue) = request_device(&adapter).await.unwrap();
         let config = wgpu::SurfaceConfiguration{
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
             format: surface.get_preferred_format(&adapter).unwrap(),
@@ -119,7 +159,7 @@ impl<S: 'static +  State> Framework<S>{
                 Event::WindowEvent{
                     ref event,
                     window_id,
-                } if window_id == self.app.window.id() => if !self.state.input(event){
+                } if window_id == self.app.window.id() && !self.state.input(event) => {
                     match event{
                         WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
                         WindowEvent::Resized(physical_size) => {
@@ -134,6 +174,9 @@ impl<S: 'static +  State> Framework<S>{
                         WindowEvent::CursorMoved{device_id, position, ..} => {
                             self.state.cursor_moved(&mut self.app, device_id, position);
                         }
+                        WindowEvent::MouseInput{device_id, state, button, ..} => {
+                            self.state.mouse_input(&mut self.app, device_id, state, button);
+                        }
                         _ => {},
                     }
                 },
@@ -1,11 +1,9 @@
-use super::binding;
 use super::pipeline;
 use super::vert::*;
 use super::buffer::*;
 use super::uniform::UniformBindGroup;
 use super::binding::GetBindGroup;
 use bytemuck;
-use cgmath::*;
 #[allow(unused)]
 use wgpu::util::DeviceExt;
 use anyhow::*;
@@ -112,7 +110,7 @@ impl<V: Vert> Model<V>{
 
 impl<V: Vert> Drawable for Model<V>{
     fn draw<'rp>(&'rp self, render_pass: &'_ mut pipeline::RenderPassPipeline<'rp, '_>) {
-        render_pass.set_bind_group(0, &self.uniform_buffer.get_bind_group(), &[]);
+        render_pass.set_bind_group(0, self.uniform_buffer.get_bind_group(), &[]);
 
         self.mesh.draw(render_pass);
     }
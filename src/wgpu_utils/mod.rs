@@ -2,8 +2,12 @@ pub mod binding;
 pub mod buffer;
 pub mod framework;
 pub mod mesh;
+pub mod multigrid;
+pub mod pingpong;
 pub mod pipeline;
+pub mod reduction;
 pub mod render_target;
+pub mod resample;
 pub mod texture;
 pub mod uniform;
 pub mod vert;
@@ -0,0 +1,269 @@
+use super::binding::{BindGroup, GetBindGroup, GetBindGroupLayout, CreateBindGroupLayout};
+use super::mesh::{Mesh, Drawable};
+use super::pingpong::PingPong;
+use super::pipeline::{self, shader_with_shaderc_defines, VertexStateBuilder, FragmentStateBuilder, PipelineLayoutBuilder, RenderPipelineBuilder, RenderPassBuilder};
+use super::render_target::ColorAttachment;
+use super::texture::Texture;
+use super::uniform::Uniform;
+use super::vert::Vert2;
+use anyhow::*;
+
+///
+/// Size and equation of one level of the multigrid as it is laid out in the uniform buffer.
+///
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MultigridLevel{
+    pub size: [f32; 2],
+    pub alpha: f32,
+    // scaled by the squared grid spacing of the level.
+    pub beta: f32,
+}
+
+///
+/// Geometric multigrid solver for the screened Poisson equation
+///
+/// alpha * x - beta * lapl(x) = b
+///
+/// on a Rgba32Float texture holding the solution in x and the right hand side b in y with a zero
+/// gradient across the border, or wrapping around it if the address mode is Repeat. A pressure
+/// solve lapl(p) = div uses alpha = 0 and beta = -1, an implicit diffusion step uses alpha = 1
+/// and beta = dt * nu.
+///
+/// Every V-cycle smooths with red black Gauss-Seidel iterations, restricts the residual to the
+/// next coarser level, solves for the error there and adds it back to the finer level down to
+/// the coarsest level of the pyramid.
+///
+pub struct Multigrid{
+    mesh: Mesh<Vert2>,
+    // uniforms of every level starting with the finest one.
+    levels: Vec<BindGroup<Uniform<MultigridLevel>>>,
+    // textures of the coarser levels, the finest level is the texture passed to solve.
+    textures: Vec<PingPong<Texture>>,
+
+    pipeline_smooth: pipeline::RenderPipeline,
+    pipeline_restrict: pipeline::RenderPipeline,
+    pipeline_prolongate: pipeline::RenderPipeline,
+
+    // Gauss-Seidel iterations before and after the coarse grid correction and on the coarsest
+    // level.
+    pre_smooth: u32,
+    post_smooth: u32,
+    coarse_smooth: u32,
+}
+
+impl Multigrid{
+    // Gauss-Seidel iterations before and after the coarse grid correction and on the coarsest
+    // level used by new().
+    pub const DEFAULT_SMOOTHING: (u32, u32, u32) = (2, 2, 20);
+
+    ///
+    /// Sizes of the levels starting with size, halving them while both sides stay at least
+    /// min_size.
+    ///
+    pub fn level_sizes(size: [u32; 2], min_size: u32) -> Vec<[u32; 2]>{
+        let mut sizes = vec![size];
+        let mut size = size;
+        while size[0] / 2 >= min_size.max(1) && size[1] / 2 >= min_size.max(1){
+            size = [size[0].div_ceil(2), size[1].div_ceil(2)];
+            sizes.push(size);
+        }
+        sizes
+    }
+
+    ///
+    /// The address mode has to match the one of the textures passed to solve, the coarser levels
+    /// are only periodic if their sizes stay even.
+    ///
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, size: [u32; 2], min_size: u32, alpha: f32, beta: f32, address_mode: wgpu::AddressMode) -> Result<Self>{
+        let mesh = Mesh::new(device, &Vert2::QUAD_VERTS, &Vert2::QUAD_IDXS)?;
+
+        let sizes = Self::level_sizes(size, min_size);
+
+        let levels = sizes.iter().enumerate().map(|(i, size)|{
+            BindGroup::new(Uniform::new(device, Self::level(*size, i, alpha, beta)), device)
+        }).collect::<Vec<_>>();
+
+        let mut textures = Vec::new();
+        for size in sizes.iter().skip(1){
+            textures.push(PingPong::new(
+                Texture::new_black_with_address_mode(*size, device, queue, Some("MultigridLevel"), wgpu::TextureFormat::Rgba32Float, address_mode)?,
+                Texture::new_black_with_address_mode(*size, device, queue, Some("MultigridLevel"), wgpu::TextureFormat::Rgba32Float, address_mode)?,
+                device
+            ));
+        }
+
+        let tex_layout = BindGroup::<Texture>::create_bind_group_layout(device, None);
+        let pipeline_layout = PipelineLayoutBuilder::new()
+            .push(levels[0].get_bind_group_layout())
+            .push(&tex_layout)
+            .push(&tex_layout)
+            .create(device, None);
+
+        let mut pipelines = Vec::new();
+        for pass in ["PASS_SMOOTH", "PASS_RESTRICT", "PASS_PROLONGATE"]{
+            let defines = [(pass, "1"), ("PERIODIC", if address_mode == wgpu::AddressMode::Repeat {"1"} else {"0"})];
+            let vert_shader = shader_with_shaderc_defines(device, include_str!("../shaders/vf_multigrid.glsl"), shaderc::ShaderKind::Vertex, "main", &defines, None)?;
+            let frag_shader = shader_with_shaderc_defines(device, include_str!("../shaders/vf_multigrid.glsl"), shaderc::ShaderKind::Fragment, "main", &defines, None)?;
+
+            let vert_state = VertexStateBuilder::new(&vert_shader)
+                .push_vert_layout(mesh.vert_buffer_layout())
+                .build();
+
+            let frag_state = FragmentStateBuilder::new(&frag_shader)
+                .push_target_replace(wgpu::TextureFormat::Rgba32Float)
+                .build();
+
+            pipelines.push(RenderPipelineBuilder::new(vert_state, frag_state)
+                .set_layout(&pipeline_layout)
+                .build(device));
+        }
+        let pipeline_prolongate = pipelines.pop().unwrap();
+        let pipeline_restrict = pipelines.pop().unwrap();
+        let pipeline_smooth = pipelines.pop().unwrap();
+
+        Ok(Self{
+            mesh,
+            levels,
+            textures,
+            pipeline_smooth,
+            pipeline_restrict,
+            pipeline_prolongate,
+            pre_smooth: Self::DEFAULT_SMOOTHING.0,
+            post_smooth: Self::DEFAULT_SMOOTHING.1,
+            coarse_smooth: Self::DEFAULT_SMOOTHING.2,
+        })
+    }
+
+    pub fn set_smoothing(mut self, pre_smooth: u32, post_smooth: u32, coarse_smooth: u32) -> Self{
+        self.pre_smooth = pre_smooth;
+        self.post_smooth = post_smooth;
+        self.coarse_smooth = coarse_smooth;
+        self
+    }
+
+    pub fn smoothing(&self) -> (u32, u32, u32){
+        (self.pre_smooth, self.post_smooth, self.coarse_smooth)
+    }
+
+    ///
+    /// Number of levels including the finest one.
+    ///
+    pub fn levels(&self) -> usize{
+        self.levels.len()
+    }
+
+    ///
+    /// Change the coefficients of the equation.
+    ///
+    pub fn set_equation(&mut self, queue: &mut wgpu::Queue, alpha: f32, beta: f32){
+        for (i, level) in self.levels.iter_mut().enumerate(){
+            let size = level.get_content().size;
+            *level.borrow_ref(queue) = Self::level([size[0] as u32, size[1] as u32], i, alpha, beta);
+        }
+    }
+
+    ///
+    /// Runs cycles V-cycles on tex which has to be of the size the Multigrid was created with.
+    ///
+    /// The current content of tex.x is used as initial guess.
+    ///
+    pub fn solve(&mut self, encoder: &mut wgpu::CommandEncoder, tex: &mut PingPong<Texture>, cycles: u32){
+        debug_assert_eq!([tex.size[0] as f32, tex.size[1] as f32], self.levels[0].get_content().size);
+
+        for _ in 0..cycles{
+            self.v_cycle(encoder, tex);
+        }
+    }
+
+    fn v_cycle(&mut self, encoder: &mut wgpu::CommandEncoder, tex: &mut PingPong<Texture>){
+        let coarsest = self.levels.len() - 1;
+
+        for level in 0..coarsest{
+            self.smooth(encoder, tex, level, self.pre_smooth);
+            // restrict the residual to the next coarser level.
+            {
+                let fine = self.texture(tex, level);
+                let coarse = self.texture(tex, level + 1);
+                self.pass(encoder, &self.pipeline_restrict, level + 1, &coarse.write().view, fine, fine);
+            }
+            self.swap(tex, level + 1);
+        }
+
+        self.smooth(encoder, tex, coarsest, self.coarse_smooth);
+
+        for level in (0..coarsest).rev(){
+            // add the error solved for on the coarser level.
+            {
+                let fine = self.texture(tex, level);
+                let coarse = self.texture(tex, level + 1);
+                self.pass(encoder, &self.pipeline_prolongate, level, &fine.write().view, fine, coarse);
+            }
+            self.swap(tex, level);
+            self.smooth(encoder, tex, level, self.post_smooth);
+        }
+    }
+
+    fn smooth(&mut self, encoder: &mut wgpu::CommandEncoder, tex: &mut PingPong<Texture>, level: usize, iterations: u32){
+        for _ in 0..iterations{
+            {
+                let src = self.texture(tex, level);
+                self.pass(encoder, &self.pipeline_smooth, level, &src.write().view, src, src);
+            }
+            self.swap(tex, level);
+        }
+    }
+
+    fn pass(&self, encoder: &mut wgpu::CommandEncoder, pipeline: &pipeline::RenderPipeline, level: usize, target: &wgpu::TextureView, src: &BindGroup<Texture>, coarse: &BindGroup<Texture>){
+        let mut render_pass = RenderPassBuilder::new()
+            .push_color_attachment(target.color_attachment_clear())
+            .begin(encoder, Some("MultigridPass"));
+
+        let mut render_pass_pipeline = render_pass.set_pipeline(pipeline);
+
+        render_pass_pipeline.set_bind_group(0, self.levels[level].get_bind_group(), &[]);
+        render_pass_pipeline.set_bind_group(1, src.get_bind_group(), &[]);
+        render_pass_pipeline.set_bind_group(2, coarse.get_bind_group(), &[]);
+
+        self.mesh.draw(&mut render_pass_pipeline);
+    }
+
+    fn texture<'a>(&'a self, tex: &'a PingPong<Texture>, level: usize) -> &'a PingPong<Texture>{
+        if level == 0 {tex} else {&self.textures[level - 1]}
+    }
+
+    fn swap(&mut self, tex: &mut PingPong<Texture>, level: usize){
+        if level == 0 {tex.swap()} else {self.textures[level - 1].swap()}
+    }
+
+    fn level(size: [u32; 2], level: usize, alpha: f32, beta: f32) -> MultigridLevel{
+        MultigridLevel{
+            size: [size[0] as f32, size[1] as f32],
+            alpha,
+            // the grid spacing doubles with every level.
+            beta: beta / 4f32.powi(level as i32),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn level_sizes_halve_even_sizes(){
+        assert_eq!(Multigrid::level_sizes([64, 32], 8), vec![[64, 32], [32, 16], [16, 8]]);
+    }
+
+    #[test]
+    fn level_sizes_round_odd_sizes_up(){
+        assert_eq!(Multigrid::level_sizes([45, 23], 4), vec![[45, 23], [23, 12], [12, 6]]);
+    }
+
+    #[test]
+    fn level_sizes_stop_at_the_min_size(){
+        assert_eq!(Multigrid::level_sizes([16, 16], 16), vec![[16, 16]]);
+        assert_eq!(Multigrid::level_sizes([1, 1], 0), vec![[1, 1]]);
+        assert_eq!(Multigrid::level_sizes([4, 2], 0), vec![[4, 2], [2, 1]]);
+    }
+}
@@ -0,0 +1,75 @@
+use super::binding::{BindGroup, BindGroupContent};
+use std::ops::{Deref, DerefMut};
+
+///
+/// Two bind groups of the same content of which one is read and the other one written by a pass.
+///
+/// Instead of copying the written content back after every pass the roles are exchanged with
+/// swap() which only flips an index. The bind groups of both sides are built once in new().
+///
+/// Derefs to the read side which holds the current state.
+///
+pub struct PingPong<C: BindGroupContent>{
+    bind_groups: [BindGroup<C>; 2],
+    read: usize,
+}
+
+impl<C: BindGroupContent> PingPong<C>{
+    pub fn new(read: C, write: C, device: &wgpu::Device) -> Self{
+        Self{
+            bind_groups: [BindGroup::new(read, device), BindGroup::new(write, device)],
+            read: 0,
+        }
+    }
+
+    ///
+    /// Exchange the read and write side.
+    ///
+    #[inline]
+    pub fn swap(&mut self){
+        self.read = 1 - self.read;
+    }
+
+    #[inline]
+    pub fn read(&self) -> &BindGroup<C>{
+        &self.bind_groups[self.read]
+    }
+
+    #[inline]
+    pub fn write(&self) -> &BindGroup<C>{
+        &self.bind_groups[1 - self.read]
+    }
+
+    #[inline]
+    pub fn read_index(&self) -> usize{
+        self.read
+    }
+
+    #[inline]
+    pub fn write_index(&self) -> usize{
+        1 - self.read
+    }
+
+    ///
+    /// One of the two sides independent of its role, used to build bind groups referencing
+    /// both sides upfront.
+    ///
+    #[inline]
+    pub fn get(&self, index: usize) -> &BindGroup<C>{
+        &self.bind_groups[index]
+    }
+}
+
+impl<C: BindGroupContent> Deref for PingPong<C>{
+    type Target = BindGroup<C>;
+
+    fn deref(&self) -> &Self::Target{
+        &self.bind_groups[self.read]
+    }
+}
+
+impl<C: BindGroupContent> DerefMut for PingPong<C>{
+    fn deref_mut(&mut self) -> &mut Self::Target{
+        &mut self.bind_groups[self.read]
+    }
+}
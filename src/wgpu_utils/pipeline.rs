@@ -1,12 +1,8 @@
-use std::collections::HashMap;
-use std::ops::Deref;
 use std::path::Path;
-use std::path::PathBuf;
-use std::{fs, ops};
+use std::fs;
 use std::fs::File;
 use std::io::Read;
 use std::str;
-use std::sync::Arc;
 use super::binding;
 use std::borrow::Cow;
 use anyhow::*;
@@ -14,8 +10,7 @@ use core::ops::Range;
 use core::num::NonZeroU32;
 use naga;
 
-const DEFAULT_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Bgra8Unorm;
-const DEFAULT_ENTRY_POINT: &'static str = "main";
+const DEFAULT_ENTRY_POINT: &str = "main";
 
 pub trait RenderData{
     fn pipeline_layout() -> PipelineLayout;
@@ -97,7 +92,6 @@ pub struct VertexStateBuilder<'vsb>{
     entry_point: &'vsb str,
     //module: &'vsb wgpu::ShaderModule,
     vertex_shader: &'vsb wgpu::ShaderModule,
-    index: usize,
 }
 
 impl<'vsb> VertexStateBuilder<'vsb>{
@@ -105,7 +99,6 @@ impl<'vsb> VertexStateBuilder<'vsb>{
         Self{
             vertex_buffer_layouts: Vec::new(),
             entry_point: DEFAULT_ENTRY_POINT,
-            index: 0,
             vertex_shader,
         }
     }
@@ -141,7 +134,12 @@ pub struct PipelineLayout{
 pub struct PipelineLayoutBuilder<'l>{
     bind_group_layouts: Vec<&'l binding::BindGroupLayoutWithDesc>,
     push_constant_ranges: Vec<wgpu::PushConstantRange>,
-    index: usize,
+}
+
+impl<'l> Default for PipelineLayoutBuilder<'l>{
+    fn default() -> Self{
+        Self::new()
+    }
 }
 
 impl<'l> PipelineLayoutBuilder<'l>{
@@ -149,7 +147,6 @@ impl<'l> PipelineLayoutBuilder<'l>{
         Self{
             bind_group_layouts: Vec::new(),
             push_constant_ranges: Vec::new(),
-            index: 0,
         }
     }
 
@@ -291,7 +288,7 @@ impl<'cpb> ComputePipelineBuilder<'cpb>{
 /// name.
 ///
 /// Not quite sure about lifetime inheritance.
-
+///
 pub struct RenderPass<'rp>{
     pub render_pass: wgpu::RenderPass<'rp>,
 }
@@ -318,6 +315,12 @@ pub struct RenderPassBuilder<'rp>{
     color_attachments: Vec<wgpu::RenderPassColorAttachment<'rp>>,
 }
 
+impl<'rp> Default for RenderPassBuilder<'rp>{
+    fn default() -> Self{
+        Self::new()
+    }
+}
+
 impl<'rp> RenderPassBuilder<'rp>{
     pub fn new() -> Self{
         Self{
@@ -346,7 +349,7 @@ pub fn shader_load(device: &wgpu::Device, path: &str, stage: naga::ShaderStage,
     let mut f = File::open(path)?;
     let metadata = fs::metadata(path)?;
     let mut buffer = vec![0; metadata.len() as usize];
-    f.read(&mut buffer)?;
+    f.read_exact(&mut buffer)?;
     let src = str::from_utf8(&buffer)?;
 
 
@@ -370,6 +373,14 @@ pub fn shader_load(device: &wgpu::Device, path: &str, stage: naga::ShaderStage,
 }
 
 pub fn shader_with_shaderc(device: &wgpu::Device, src: &str, kind: shaderc::ShaderKind, entry_point: &str, label: Option<&str>) -> Result<wgpu::ShaderModule>{
+    shader_with_shaderc_defines(device, src, kind, entry_point, &[], label)
+}
+
+///
+/// Like shader_with_shaderc with additional macro definitions to select between variants of a
+/// shader.
+///
+pub fn shader_with_shaderc_defines(device: &wgpu::Device, src: &str, kind: shaderc::ShaderKind, entry_point: &str, defines: &[(&str, &str)], label: Option<&str>) -> Result<wgpu::ShaderModule>{
 
     let mut compiler = shaderc::Compiler::new().ok_or(anyhow!("error creating compiler"))?;
     let mut options = shaderc::CompileOptions::new().ok_or(anyhow!("error creating shaderc options"))?;
@@ -382,6 +393,9 @@ pub fn shader_with_shaderc(device: &wgpu::Device, src: &str, kind: shaderc::Shad
     options.add_macro_definition("VERTEX_SHADER", Some(if kind == shaderc::ShaderKind::Vertex {"1"} else {"0"}));
     options.add_macro_definition("FRAGMENT_SHADER", Some(if kind == shaderc::ShaderKind::Fragment {"1"} else {"0"}));
     options.add_macro_definition("COMPUTE_SHADER", Some(if kind == shaderc::ShaderKind::Compute {"1"} else {"0"}));
+    for (name, value) in defines{
+        options.add_macro_definition(name, Some(value));
+    }
 
     /*
        options.set_include_callback(|name, include_type, source_file, _depth|{
@@ -0,0 +1,171 @@
+use super::binding::{BindGroupLayoutBuilder, BindGroupLayoutWithDesc, BindGroupBuilder, CreateBindGroupLayout, GetBindGroup, BindGroup, wgsl};
+use super::buffer::Buffer;
+use super::pipeline::{self, shader_with_shaderc_defines, PipelineLayoutBuilder, ComputePipeline};
+use super::texture::{Texture, TextureArray};
+use anyhow::*;
+
+// side of the tiles reduced by one workgroup of the first pass.
+const TILE_SIZE: u32 = 16;
+
+///
+/// Sum, minimum and maximum of a layer as it is laid out in the storage buffers.
+///
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct Partial{
+    sum: [f32; 4],
+    min: [f32; 4],
+    max: [f32; 4],
+}
+
+///
+/// Sum, minimum, maximum and mean of every channel of a texture or of a layer of a texture
+/// array.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ChannelStats{
+    pub sum: [f32; 4],
+    pub min: [f32; 4],
+    pub max: [f32; 4],
+    pub mean: [f32; 4],
+}
+
+///
+/// What is reduced for every texel.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReduceInput{
+    // the channels of the texel.
+    Texels,
+    // a velocity, height and wet area mask texel such as the one of PaintSim::tex_vpf: the speed
+    // in x, y and z unchanged and 1 for a wet cell (w >= 0.5) or 0 in w.
+    Vpf,
+}
+
+///
+/// Parallel reduction of the channels of a Rgba32Float texture or texture array of a fixed size
+/// with compute shaders.
+///
+/// The first pass reduces every 16x16 tile of every layer to a partial result in shared memory,
+/// the second pass reduces the partial results of every layer with one workgroup per layer. The
+/// results are copied into a staging buffer read by read_blocking.
+///
+pub struct Reduction{
+    size: [u32; 2],
+    layers: u32,
+    // workgroups of the first pass in x and y.
+    groups: [u32; 2],
+
+    buffers: wgpu::BindGroup,
+    results: Buffer<Partial>,
+    staging: Buffer<Partial>,
+
+    pipeline_texels: ComputePipeline,
+    pipeline_partials: ComputePipeline,
+}
+
+impl Reduction{
+    pub fn new_texture(device: &wgpu::Device, size: [u32; 2], input: ReduceInput) -> Result<Self>{
+        let layout = BindGroup::<Texture>::create_bind_group_layout(device, None);
+        Self::new(device, size, 1, &layout, false, input)
+    }
+
+    pub fn new_texture_array(device: &wgpu::Device, size: [u32; 2], layers: u32, input: ReduceInput) -> Result<Self>{
+        let layout = BindGroup::<TextureArray>::create_bind_group_layout(device, None);
+        Self::new(device, size, layers, &layout, true, input)
+    }
+
+    fn new(device: &wgpu::Device, size: [u32; 2], layers: u32, tex_layout: &BindGroupLayoutWithDesc, array: bool, input: ReduceInput) -> Result<Self>{
+        let groups = [size[0].div_ceil(TILE_SIZE), size[1].div_ceil(TILE_SIZE)];
+
+        let partials = Buffer::<Partial>::new_empty(device, wgpu::BufferUsages::STORAGE, Some("ReductionPartials"), (groups[0] * groups[1] * layers) as usize);
+        let results = Buffer::<Partial>::new_empty(device, wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC, Some("ReductionResults"), layers as usize);
+        let staging = Buffer::<Partial>::new_empty(device, wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST, Some("ReductionStaging"), layers as usize);
+
+        let buffers_layout = BindGroupLayoutBuilder::new()
+            .push_entry_compute(wgsl::buffer(false))
+            .push_entry_compute(wgsl::buffer(false))
+            .create(device, None);
+        let buffers = BindGroupBuilder::new(&buffers_layout)
+            .resource(partials.as_entire_binding())
+            .resource(results.as_entire_binding())
+            .create(device, Some("ReductionBuffers"));
+
+        let texture_array = ("TEXTURE_ARRAY", if array {"1"} else {"0"});
+        let vpf = ("VPF", if input == ReduceInput::Vpf {"1"} else {"0"});
+
+        let comp_shader = shader_with_shaderc_defines(device, include_str!("../shaders/comp_reduce.glsl"), shaderc::ShaderKind::Compute, "main", &[("PASS_TEXELS", "1"), texture_array, vpf], None)?;
+        let pipeline_layout = PipelineLayoutBuilder::new()
+            .push(tex_layout)
+            .push(&buffers_layout)
+            .create(device, None);
+        let pipeline_texels = pipeline::ComputePipelineBuilder::new(&comp_shader)
+            .set_layout(&pipeline_layout)
+            .build(device);
+
+        let comp_shader = shader_with_shaderc_defines(device, include_str!("../shaders/comp_reduce.glsl"), shaderc::ShaderKind::Compute, "main", &[("PASS_PARTIALS", "1")], None)?;
+        let pipeline_layout = PipelineLayoutBuilder::new()
+            .push(&buffers_layout)
+            .create(device, None);
+        let pipeline_partials = pipeline::ComputePipelineBuilder::new(&comp_shader)
+            .set_layout(&pipeline_layout)
+            .build(device);
+
+        Ok(Self{
+            size,
+            layers,
+            groups,
+            buffers,
+            results,
+            staging,
+            pipeline_texels,
+            pipeline_partials,
+        })
+    }
+
+    ///
+    /// Records the reduction of a texture or texture array of the size and kind given to the
+    /// constructor.
+    ///
+    pub fn reduce(&self, encoder: &mut wgpu::CommandEncoder, texture: &impl GetBindGroup){
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor{
+                label: Some("ReduceTexels"),
+            });
+            cpass.set_pipeline(&self.pipeline_texels.pipeline);
+            cpass.set_bind_group(0, texture.get_bind_group(), &[]);
+            cpass.set_bind_group(1, &self.buffers, &[]);
+            cpass.dispatch(self.groups[0], self.groups[1], self.layers);
+        }
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor{
+                label: Some("ReducePartials"),
+            });
+            cpass.set_pipeline(&self.pipeline_partials.pipeline);
+            cpass.set_bind_group(0, &self.buffers, &[]);
+            cpass.dispatch(self.layers, 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(
+            &self.results.buffer,
+            0,
+            &self.staging.buffer,
+            0,
+            (self.layers as usize * std::mem::size_of::<Partial>()) as u64
+        );
+    }
+
+    ///
+    /// Reads back the result of the last reduction submitted, one entry per layer.
+    ///
+    pub fn read_blocking(&self, device: &wgpu::Device) -> Vec<ChannelStats>{
+        let cells = (self.size[0] * self.size[1]) as f32;
+        self.staging.slice(..).map_blocking(device).iter().map(|p|{
+            ChannelStats{
+                sum: p.sum,
+                min: p.min,
+                max: p.max,
+                mean: [p.sum[0] / cells, p.sum[1] / cells, p.sum[2] / cells, p.sum[3] / cells],
+            }
+        }).collect()
+    }
+}
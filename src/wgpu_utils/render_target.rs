@@ -1,25 +1,21 @@
 use anyhow::*;
-use super::texture;
 
 ///
 /// RenderTarget should be implemented for anything that can be rendered to.
 ///
-///
-///
-
 pub trait RenderTarget{
     fn render_pass_clear<'a>(&'a self, encoder: &'a mut wgpu::CommandEncoder, label: Option<&'a str>) -> Result<wgpu::RenderPass<'a>>;
     fn render_pass_load<'a>(&'a self, encoder: &'a mut wgpu::CommandEncoder, label: Option<&'a str>) -> Result<wgpu::RenderPass<'a>>;
 }
 
 pub trait ColorAttachment{
-    fn color_attachment_clear(&self) -> wgpu::RenderPassColorAttachment;
-    fn color_attachment_clear_with(&self, color: wgpu::Color) -> wgpu::RenderPassColorAttachment;
-    fn color_attachment_load(&self) -> wgpu::RenderPassColorAttachment;
+    fn color_attachment_clear(&self) -> wgpu::RenderPassColorAttachment<'_>;
+    fn color_attachment_clear_with(&self, color: wgpu::Color) -> wgpu::RenderPassColorAttachment<'_>;
+    fn color_attachment_load(&self) -> wgpu::RenderPassColorAttachment<'_>;
 }
 
 impl ColorAttachment for wgpu::TextureView{
-    fn color_attachment_clear(&self) -> wgpu::RenderPassColorAttachment {
+    fn color_attachment_clear(&self) -> wgpu::RenderPassColorAttachment<'_> {
         wgpu::RenderPassColorAttachment{
             view: self,
             resolve_target: None,
@@ -35,7 +31,7 @@ impl ColorAttachment for wgpu::TextureView{
         }
     }
 
-    fn color_attachment_clear_with(&self, color: wgpu::Color) -> wgpu::RenderPassColorAttachment{
+    fn color_attachment_clear_with(&self, color: wgpu::Color) -> wgpu::RenderPassColorAttachment<'_>{
         wgpu::RenderPassColorAttachment{
             view: self,
             resolve_target: None,
@@ -46,7 +42,7 @@ impl ColorAttachment for wgpu::TextureView{
         }
     }
 
-    fn color_attachment_load(&self) -> wgpu::RenderPassColorAttachment{
+    fn color_attachment_load(&self) -> wgpu::RenderPassColorAttachment<'_>{
         wgpu::RenderPassColorAttachment{
             view: self,
             resolve_target: None,
@@ -105,7 +101,7 @@ impl RenderTarget for [&wgpu::TextureView]{
         for view in self{
             color_attachments.push(
                 wgpu::RenderPassColorAttachment{
-                    view: *view,
+                    view,
                     resolve_target: None,
                     ops: wgpu::Operations{
                         load: wgpu::LoadOp::Clear( wgpu::Color{
@@ -123,7 +119,7 @@ impl RenderTarget for [&wgpu::TextureView]{
 
         Ok(encoder.begin_render_pass(&wgpu::RenderPassDescriptor{
             label,
-            color_attachments: &&color_attachments,
+            color_attachments: &color_attachments,
             depth_stencil_attachment: None,
         }))
     }
@@ -133,7 +129,7 @@ impl RenderTarget for [&wgpu::TextureView]{
         for view in self{
             color_attachments.push(
                 wgpu::RenderPassColorAttachment{
-                    view: *view,
+                    view,
                     resolve_target: None,
                     ops: wgpu::Operations{
                         load: wgpu::LoadOp::Load,
@@ -145,7 +141,7 @@ impl RenderTarget for [&wgpu::TextureView]{
 
         Ok(encoder.begin_render_pass(&wgpu::RenderPassDescriptor{
             label,
-            color_attachments: &&color_attachments,
+            color_attachments: &color_attachments,
             depth_stencil_attachment: None,
         }))
     }
@@ -0,0 +1,88 @@
+use super::binding::{BindGroup, GetBindGroup, GetBindGroupLayout, CreateBindGroupLayout};
+use super::mesh::{Mesh, Drawable};
+use super::pipeline::{self, shader_with_shaderc_defines, VertexStateBuilder, FragmentStateBuilder, PipelineLayoutBuilder, RenderPipelineBuilder, RenderPassBuilder};
+use super::render_target::ColorAttachment;
+use super::texture::Texture;
+use super::uniform::Uniform;
+use super::vert::Vert2;
+use anyhow::*;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ResampleParams{
+    dst_size: [f32; 2],
+    _pad0: [f32; 2],
+}
+
+///
+/// Render pass resampling a texture of any size to a render target of a fixed size and format
+/// with a box filter (vf_resample.glsl).
+///
+/// Shrinking averages the covered texels instead of picking one, enlarging interpolates
+/// bilinearly. With the address mode Repeat the filter wraps around the edges of the source.
+///
+pub struct Resample{
+    mesh: Mesh<Vert2>,
+    params: BindGroup<Uniform<ResampleParams>>,
+    pipeline: pipeline::RenderPipeline,
+    dst_size: [u32; 2],
+}
+
+impl Resample{
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, dst_size: [u32; 2], address_mode: wgpu::AddressMode) -> Result<Self>{
+        let mesh = Mesh::new(device, &Vert2::QUAD_VERTS, &Vert2::QUAD_IDXS)?;
+
+        let params = BindGroup::new(Uniform::new(device, ResampleParams{
+            dst_size: [dst_size[0] as f32, dst_size[1] as f32],
+            _pad0: [0.; 2],
+        }), device);
+
+        let defines = [("PERIODIC", if address_mode == wgpu::AddressMode::Repeat {"1"} else {"0"})];
+        let vert_shader = shader_with_shaderc_defines(device, include_str!("../shaders/vf_resample.glsl"), shaderc::ShaderKind::Vertex, "main", &defines, None)?;
+        let frag_shader = shader_with_shaderc_defines(device, include_str!("../shaders/vf_resample.glsl"), shaderc::ShaderKind::Fragment, "main", &defines, None)?;
+
+        let vert_state = VertexStateBuilder::new(&vert_shader)
+            .push_vert_layout(mesh.vert_buffer_layout())
+            .build();
+
+        let frag_state = FragmentStateBuilder::new(&frag_shader)
+            .push_target_replace(format)
+            .build();
+
+        let pipeline_layout = PipelineLayoutBuilder::new()
+            .push(params.get_bind_group_layout())
+            .push(&BindGroup::<Texture>::create_bind_group_layout(device, None))
+            .create(device, None);
+
+        let pipeline = RenderPipelineBuilder::new(vert_state, frag_state)
+            .set_layout(&pipeline_layout)
+            .build(device);
+
+        Ok(Self{
+            mesh,
+            params,
+            pipeline,
+            dst_size,
+        })
+    }
+
+    pub fn dst_size(&self) -> [u32; 2]{
+        self.dst_size
+    }
+
+    ///
+    /// Renders src into dst which has to have the size and format the Resample was created with.
+    ///
+    pub fn resample(&self, encoder: &mut wgpu::CommandEncoder, src: &BindGroup<Texture>, dst: &wgpu::TextureView){
+        let mut render_pass = RenderPassBuilder::new()
+            .push_color_attachment(dst.color_attachment_clear())
+            .begin(encoder, Some("ResamplePass"));
+
+        let mut render_pass_pipeline = render_pass.set_pipeline(&self.pipeline);
+
+        render_pass_pipeline.set_bind_group(0, self.params.get_bind_group(), &[]);
+        render_pass_pipeline.set_bind_group(1, src.get_bind_group(), &[]);
+
+        self.mesh.draw(&mut render_pass_pipeline);
+    }
+}
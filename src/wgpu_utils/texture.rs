@@ -3,14 +3,12 @@ use anyhow::*;
 use super::render_target::*;
 use super::binding;
 use super::binding::*;
+use super::buffer::Buffer;
 use std::fs;
 use std::fs::File;
 use std::io::Read;
 
 
-///
-/// 
-///
 pub struct Texture{
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
@@ -31,7 +29,7 @@ impl Texture{
         let mut f = File::open(path)?;
         let metadata = fs::metadata(path)?;
         let mut buffer = vec![0; metadata.len() as usize];
-        f.read(&mut buffer)?;
+        f.read_exact(&mut buffer)?;
         Self::from_bytes(
             device,
             queue,
@@ -47,8 +45,21 @@ impl Texture{
         label: Option<&str>,
         format: wgpu::TextureFormat
     ) -> Result<Self>{
-        let data: Vec<u8> = vec![0; (size[0] * size[1] * 4) as usize];
+        Self::new_black_with_address_mode(size, device, queue, label, format, wgpu::AddressMode::ClampToEdge)
+    }
 
+    ///
+    /// Like new_black with a sampler using address_mode in both directions, Repeat makes
+    /// samples outside of the texture wrap around.
+    ///
+    pub fn new_black_with_address_mode(
+        size: [u32; 2],
+        device: &wgpu::Device,
+        _queue: &wgpu::Queue,
+        label: Option<&str>,
+        format: wgpu::TextureFormat,
+        address_mode: wgpu::AddressMode,
+    ) -> Result<Self>{
         let extent = wgpu::Extent3d{
             width: size[0],
             height: size[1],
@@ -66,6 +77,8 @@ impl Texture{
                     | wgpu::TextureUsages::COPY_DST
                     | wgpu::TextureUsages::COPY_SRC
                     | wgpu::TextureUsages::RENDER_ATTACHMENT
+                    // storage textures are written by the compute passes if the format allows it.
+                    | (format.describe().guaranteed_format_features.allowed_usages & wgpu::TextureUsages::STORAGE_BINDING)
             }
         );
         let texture_view_desc = wgpu::TextureViewDescriptor{
@@ -75,8 +88,8 @@ impl Texture{
         let view = texture.create_view(&texture_view_desc);
         let sampler = device.create_sampler(
             &wgpu::SamplerDescriptor{
-                address_mode_u: wgpu::AddressMode::ClampToEdge,
-                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_u: address_mode,
+                address_mode_v: address_mode,
                 address_mode_w: wgpu::AddressMode::ClampToEdge,
                 mag_filter: wgpu::FilterMode::Linear,
                 min_filter: wgpu::FilterMode::Nearest,
@@ -208,6 +221,130 @@ impl Texture{
             }
         );
     }
+
+    ///
+    /// Number of bytes in one row of the texture without padding.
+    ///
+    pub fn bytes_per_row(&self) -> u32{
+        self.format.describe().block_size as u32 * self.size[0]
+    }
+
+    ///
+    /// Number of bytes in one row of the texture padded to wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as
+    /// required when copying to a buffer.
+    ///
+    pub fn padded_bytes_per_row(&self) -> u32{
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        self.bytes_per_row().div_ceil(align) * align
+    }
+
+    pub fn copy_all_to_buffer(&self, dst: &Buffer<u8>, encoder: &mut wgpu::CommandEncoder){
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture{
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer{
+                buffer: &dst.buffer,
+                layout: wgpu::ImageDataLayout{
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(self.padded_bytes_per_row()),
+                    rows_per_image: std::num::NonZeroU32::new(self.size[1]),
+                },
+            },
+            wgpu::Extent3d{
+                width: self.size[0],
+                height: self.size[1],
+                depth_or_array_layers: 1,
+            }
+        );
+    }
+
+    ///
+    /// Overwrite the whole texture with bytes in the layout returned by read_blocking.
+    ///
+    pub fn write_bytes(&self, queue: &wgpu::Queue, bytes: &[u8]){
+        queue.write_texture(
+            wgpu::ImageCopyTexture{
+                aspect: wgpu::TextureAspect::All,
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            bytes,
+            wgpu::ImageDataLayout{
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(self.bytes_per_row()),
+                rows_per_image: std::num::NonZeroU32::new(self.size[1]),
+            },
+            wgpu::Extent3d{
+                width: self.size[0],
+                height: self.size[1],
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    ///
+    /// Overwrite the rectangle at origin of the given size with tightly packed rows of bytes.
+    ///
+    pub fn write_region(&self, queue: &wgpu::Queue, origin: [u32; 2], size: [u32; 2], bytes: &[u8]){
+        queue.write_texture(
+            wgpu::ImageCopyTexture{
+                aspect: wgpu::TextureAspect::All,
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d{
+                    x: origin[0],
+                    y: origin[1],
+                    z: 0,
+                },
+            },
+            bytes,
+            wgpu::ImageDataLayout{
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(self.format.describe().block_size as u32 * size[0]),
+                rows_per_image: std::num::NonZeroU32::new(size[1]),
+            },
+            wgpu::Extent3d{
+                width: size[0],
+                height: size[1],
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    ///
+    /// Read the content of the texture back to the cpu.
+    /// Blocks untill the copy is complete and returns the rows without padding.
+    ///
+    pub fn read_blocking(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<u8>{
+        let padded_bytes_per_row = self.padded_bytes_per_row() as usize;
+        let bytes_per_row = self.bytes_per_row() as usize;
+
+        let staging = Buffer::<u8>::new_empty(
+            device,
+            wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            Some("TextureReadback"),
+            padded_bytes_per_row * self.size[1] as usize
+        );
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor{
+            label: Some("TextureReadbackEncoder"),
+        });
+        self.copy_all_to_buffer(&staging, &mut encoder);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let mapped = staging.slice(..).map_blocking(device);
+
+        let mut data = Vec::with_capacity(bytes_per_row * self.size[1] as usize);
+        for row in mapped.chunks(padded_bytes_per_row){
+            data.extend_from_slice(&row[..bytes_per_row]);
+        }
+        data
+    }
 }
 
 impl RenderTarget for Texture{
@@ -231,6 +368,351 @@ impl BindGroupContent for Texture{
     }
 }
 
+///
+/// A 2D texture with multiple array layers.
+///
+/// The whole array is bound as a texture2DArray, the views of the single layers can be used as
+/// render targets.
+///
+pub struct TextureArray{
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub layer_views: Vec<wgpu::TextureView>,
+    pub sampler: wgpu::Sampler,
+    pub format: wgpu::TextureFormat,
+    pub size: [u32; 2],
+    pub layers: u32,
+    pub address_mode: wgpu::AddressMode,
+}
+
+impl TextureArray{
+    pub fn new_black(
+        size: [u32; 2],
+        layers: u32,
+        device: &wgpu::Device,
+        label: Option<&str>,
+        format: wgpu::TextureFormat
+    ) -> Result<Self>{
+        Self::new_black_with_address_mode(size, layers, device, label, format, wgpu::AddressMode::ClampToEdge)
+    }
+
+    ///
+    /// Like new_black with a sampler using address_mode within the layers.
+    ///
+    pub fn new_black_with_address_mode(
+        size: [u32; 2],
+        layers: u32,
+        device: &wgpu::Device,
+        label: Option<&str>,
+        format: wgpu::TextureFormat,
+        address_mode: wgpu::AddressMode,
+    ) -> Result<Self>{
+        let extent = wgpu::Extent3d{
+            width: size[0],
+            height: size[1],
+            depth_or_array_layers: layers,
+        };
+        let texture = device.create_texture(
+            &wgpu::TextureDescriptor{
+                label,
+                size: extent,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_DST
+                    | wgpu::TextureUsages::COPY_SRC
+                    | wgpu::TextureUsages::RENDER_ATTACHMENT
+                    // storage textures are written by the compute passes if the format allows it.
+                    | (format.describe().guaranteed_format_features.allowed_usages & wgpu::TextureUsages::STORAGE_BINDING)
+            }
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor{
+            format: Some(format),
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let layer_views = (0..layers).map(|layer|{
+            texture.create_view(&wgpu::TextureViewDescriptor{
+                format: Some(format),
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_array_layer: layer,
+                array_layer_count: std::num::NonZeroU32::new(1),
+                ..Default::default()
+            })
+        }).collect();
+        let sampler = Self::create_sampler(device, address_mode);
+
+        Ok(Self{
+            texture,
+            view,
+            layer_views,
+            sampler,
+            format,
+            size,
+            layers,
+            address_mode,
+        })
+    }
+
+    fn create_sampler(device: &wgpu::Device, address_mode: wgpu::AddressMode) -> wgpu::Sampler{
+        device.create_sampler(
+            &wgpu::SamplerDescriptor{
+                address_mode_u: address_mode,
+                address_mode_v: address_mode,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Nearest,
+                mipmap_filter: wgpu::FilterMode::Nearest,
+                ..Default::default()
+            }
+        )
+    }
+
+    pub fn copy_all_to(&self, dst: &mut TextureArray, encoder: &mut wgpu::CommandEncoder){
+        encoder.copy_texture_to_texture(
+            wgpu::ImageCopyTexture{
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All
+            },
+            wgpu::ImageCopyTexture{
+                texture: &dst.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::Extent3d{
+                width: self.size[0],
+                height: self.size[1],
+                depth_or_array_layers: self.layers,
+            }
+        );
+    }
+
+    ///
+    /// Copy layers src_layer..src_layer+layers to the layers starting at dst_layer of dst.
+    ///
+    pub fn copy_layers_to(&self, src_layer: u32, dst: &mut TextureArray, dst_layer: u32, layers: u32, encoder: &mut wgpu::CommandEncoder){
+        encoder.copy_texture_to_texture(
+            wgpu::ImageCopyTexture{
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d{x: 0, y: 0, z: src_layer},
+                aspect: wgpu::TextureAspect::All
+            },
+            wgpu::ImageCopyTexture{
+                texture: &dst.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d{x: 0, y: 0, z: dst_layer},
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::Extent3d{
+                width: self.size[0],
+                height: self.size[1],
+                depth_or_array_layers: layers,
+            }
+        );
+    }
+
+    pub fn bytes_per_row(&self) -> u32{
+        self.format.describe().block_size as u32 * self.size[0]
+    }
+
+    pub fn padded_bytes_per_row(&self) -> u32{
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        self.bytes_per_row().div_ceil(align) * align
+    }
+
+    ///
+    /// Read all layers back to the cpu.
+    /// Blocks untill the copy is complete and returns the layers one after another without
+    /// padding.
+    ///
+    pub fn read_blocking(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<u8>{
+        let padded_bytes_per_row = self.padded_bytes_per_row() as usize;
+        let bytes_per_row = self.bytes_per_row() as usize;
+
+        let staging = Buffer::<u8>::new_empty(
+            device,
+            wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            Some("TextureArrayReadback"),
+            padded_bytes_per_row * (self.size[1] * self.layers) as usize
+        );
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor{
+            label: Some("TextureArrayReadbackEncoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture{
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer{
+                buffer: &staging.buffer,
+                layout: wgpu::ImageDataLayout{
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_bytes_per_row as u32),
+                    rows_per_image: std::num::NonZeroU32::new(self.size[1]),
+                },
+            },
+            wgpu::Extent3d{
+                width: self.size[0],
+                height: self.size[1],
+                depth_or_array_layers: self.layers,
+            }
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let mapped = staging.slice(..).map_blocking(device);
+
+        let mut data = Vec::with_capacity(bytes_per_row * (self.size[1] * self.layers) as usize);
+        for row in mapped.chunks(padded_bytes_per_row){
+            data.extend_from_slice(&row[..bytes_per_row]);
+        }
+        data
+    }
+
+    ///
+    /// Overwrite all layers with bytes in the layout returned by read_blocking.
+    ///
+    pub fn write_bytes(&self, queue: &wgpu::Queue, bytes: &[u8]){
+        queue.write_texture(
+            wgpu::ImageCopyTexture{
+                aspect: wgpu::TextureAspect::All,
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            bytes,
+            wgpu::ImageDataLayout{
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(self.bytes_per_row()),
+                rows_per_image: std::num::NonZeroU32::new(self.size[1]),
+            },
+            wgpu::Extent3d{
+                width: self.size[0],
+                height: self.size[1],
+                depth_or_array_layers: self.layers,
+            },
+        );
+    }
+}
+
+impl BindGroupContent for TextureArray{
+    fn push_entries_to(bind_group_layout_builder: &mut BindGroupLayoutBuilder) {
+        bind_group_layout_builder.push_entry_all_ref(binding::wgsl::texture_2d_array());
+        bind_group_layout_builder.push_entry_all_ref(binding::wgsl::sampler());
+    }
+
+    fn push_resources_to<'bgb>(&'bgb self, bind_group_builder: &mut BindGroupBuilder<'bgb>) {
+        bind_group_builder.texture_ref(&self.view);
+        bind_group_builder.sampler_ref(&self.sampler);
+    }
+}
+
+///
+/// A view of a Rgba32Float Texture bound as a write only image2D in compute shaders.
+///
+pub struct StorageTexture{
+    pub view: wgpu::TextureView,
+}
+
+impl Texture{
+    pub fn storage(&self) -> Result<StorageTexture>{
+        if self.format != wgpu::TextureFormat::Rgba32Float{
+            return Err(anyhow!("storage textures have to be Rgba32Float"));
+        }
+        Ok(StorageTexture{
+            view: self.texture.create_view(&wgpu::TextureViewDescriptor{
+                format: Some(self.format),
+                ..Default::default()
+            }),
+        })
+    }
+}
+
+impl BindGroupContent for StorageTexture{
+    fn push_entries_to(bind_group_layout_builder: &mut BindGroupLayoutBuilder) {
+        bind_group_layout_builder.push_entry_compute_ref(binding::glsl::image2D(wgpu::TextureFormat::Rgba32Float, wgpu::StorageTextureAccess::WriteOnly));
+    }
+
+    fn push_resources_to<'bgb>(&'bgb self, bind_group_builder: &mut BindGroupBuilder<'bgb>) {
+        bind_group_builder.texture_ref(&self.view);
+    }
+}
+
+///
+/// A view of a Rgba32Float TextureArray bound as a write only image2DArray in compute shaders.
+///
+pub struct StorageTextureArray{
+    pub view: wgpu::TextureView,
+}
+
+impl TextureArray{
+    pub fn storage(&self) -> Result<StorageTextureArray>{
+        if self.format != wgpu::TextureFormat::Rgba32Float{
+            return Err(anyhow!("storage textures have to be Rgba32Float"));
+        }
+        Ok(StorageTextureArray{
+            view: self.texture.create_view(&wgpu::TextureViewDescriptor{
+                format: Some(self.format),
+                dimension: Some(wgpu::TextureViewDimension::D2Array),
+                ..Default::default()
+            }),
+        })
+    }
+}
+
+impl BindGroupContent for StorageTextureArray{
+    fn push_entries_to(bind_group_layout_builder: &mut BindGroupLayoutBuilder) {
+        bind_group_layout_builder.push_entry_compute_ref(binding::glsl::image2DArray(wgpu::TextureFormat::Rgba32Float, wgpu::StorageTextureAccess::WriteOnly));
+    }
+
+    fn push_resources_to<'bgb>(&'bgb self, bind_group_builder: &mut BindGroupBuilder<'bgb>) {
+        bind_group_builder.texture_ref(&self.view);
+    }
+}
+
+///
+/// Another view and sampler of a TextureArray, sampled like the TextureArray itself.
+///
+/// Lets a bind group combine several texture arrays which are also bound on their own.
+///
+pub struct SampledTextureArray{
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+}
+
+impl TextureArray{
+    pub fn sampled(&self, device: &wgpu::Device) -> SampledTextureArray{
+        SampledTextureArray{
+            view: self.texture.create_view(&wgpu::TextureViewDescriptor{
+                format: Some(self.format),
+                dimension: Some(wgpu::TextureViewDimension::D2Array),
+                ..Default::default()
+            }),
+            sampler: Self::create_sampler(device, self.address_mode),
+        }
+    }
+}
+
+impl BindGroupContent for SampledTextureArray{
+    fn push_entries_to(bind_group_layout_builder: &mut BindGroupLayoutBuilder) {
+        bind_group_layout_builder.push_entry_all_ref(binding::wgsl::texture_2d_array());
+        bind_group_layout_builder.push_entry_all_ref(binding::wgsl::sampler());
+    }
+
+    fn push_resources_to<'bgb>(&'bgb self, bind_group_builder: &mut BindGroupBuilder<'bgb>) {
+        bind_group_builder.texture_ref(&self.view);
+        bind_group_builder.sampler_ref(&self.sampler);
+    }
+}
+
 pub type BindGroupTexture = BindGroup<Texture>;
 
 impl BindGroupTexture{
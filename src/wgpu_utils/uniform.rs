@@ -2,7 +2,6 @@ use super::binding::BindGroup;
 use super::binding::CreateBindGroupLayout;
 use super::buffer::*;
 use super::binding;
-use wgpu::util::DeviceExt;
 use std::ops::{Deref, DerefMut};
 
 ///
@@ -125,6 +124,10 @@ impl<C: bytemuck::Pod> Uniform<C>{
             uniform: self,
         }
     }
+
+    pub fn get_content(&self) -> &C{
+        &self.uniform_vec.content[0]
+    }
 }
 
 impl<C: bytemuck::Pod> binding::BindGroupContent for Uniform<C>{
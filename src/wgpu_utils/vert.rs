@@ -1,6 +1,3 @@
-use bytemuck::*;
-use super::buffer::*;
-use wgpu::util::DeviceExt;
 
 pub trait Vert: bytemuck::Pod 
 + bytemuck::Zeroable 
@@ -0,0 +1,89 @@
+use fluid01::headless::{self, HeadlessArgs};
+
+// The GPU tests need an adapter and are ignored by default, run them with
+// `cargo test -- --ignored`. Arguments in FLUID01_TEST_ARGS are added to every check, for
+// example `--fallback` to use the fallback adapter.
+
+// checks run by the GPU tests, with the arguments they add.
+const CHECKS: &[&[&str]] = &[
+    &["--check-reference"],
+    &["--check-reference", "--compute"],
+    &["--check-reference", "--periodic"],
+    &["--check-tiling"],
+    &["--check-tiling", "--solver", "multigrid"],
+];
+
+///
+/// The checks take the input but no output.
+///
+fn args(extra: &[&str]) -> HeadlessArgs{
+    let mut args = vec!["assets/test03.jpg".to_string(), "--grid".to_string(), "48x32".to_string(), "--steps".to_string(), "20".to_string()];
+    args.extend(extra.iter().map(|arg| arg.to_string()));
+    if let Ok(test_args) = std::env::var("FLUID01_TEST_ARGS"){
+        args.extend(test_args.split_whitespace().map(|arg| arg.to_string()));
+    }
+    HeadlessArgs::parse(&args).unwrap()
+}
+
+fn check(extra: &[&str]){
+    headless::run(&args(extra)).unwrap();
+}
+
+///
+/// Runs without an adapter so arguments the checks reject fail even where the GPU tests are
+/// ignored.
+///
+#[test]
+fn check_arguments_parse(){
+    for extra in CHECKS{
+        args(extra);
+    }
+}
+
+///
+/// The edge checks have to take the image as input and compare periodic simulations, otherwise
+/// they pass without looking at the edges.
+///
+#[test]
+fn periodic_checks_run_a_periodic_simulation(){
+    for extra in &CHECKS[2..]{
+        let args = args(extra);
+        assert_eq!(args.input, "assets/test03.jpg");
+        assert!(args.output.is_empty());
+        assert!(args.periodic, "{:?} does not run a periodic simulation", extra);
+    }
+}
+
+#[test]
+#[ignore = "needs a GPU adapter"]
+fn render_step_matches_reference(){
+    check(CHECKS[0]);
+}
+
+#[test]
+#[ignore = "needs a GPU adapter"]
+fn compute_step_matches_reference(){
+    check(CHECKS[1]);
+}
+
+#[test]
+#[ignore = "needs a GPU adapter"]
+fn periodic_step_matches_reference(){
+    check(CHECKS[2]);
+}
+
+///
+/// A dab on the corner of a periodic canvas has to evolve like the same dab moved into the
+/// middle, see headless::check_tiling.
+///
+#[test]
+#[ignore = "needs a GPU adapter"]
+fn periodic_edges_match_the_inside(){
+    check(CHECKS[3]);
+}
+
+#[test]
+#[ignore = "needs a GPU adapter"]
+fn periodic_edges_match_the_inside_with_multigrid(){
+    check(CHECKS[4]);
+}